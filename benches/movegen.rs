@@ -0,0 +1,46 @@
+//! How long finding every placement of a piece takes, on an empty well and
+//! on a ragged stack where many positions are reached by tucks and spins
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use tetris_ggez::bot::ready;
+use tetris_ggez::diagram;
+use tetris_ggez::game_state::{GameMode, GameState, Setup};
+use tetris_ggez::movegen::placements;
+use tetris_ggez::settings::Settings;
+use tetris_ggez::simulation::TICK;
+use tetris_ggez::tetromino::Shape;
+
+const RAGGED: &str = "
+    ....G.....
+    GG..GG...G
+    G...G..GGG
+    GGG.GG.G.G
+    G.GGGG.GGG
+    GGGG.GGGG.";
+
+fn game_on(board: &str, shape: Shape) -> GameState {
+    let setup = Setup {
+        board: diagram::parse(board).unwrap().board,
+        hold: None,
+        queue: vec![shape],
+    };
+    let mut game = GameState::with_setup(GameMode::Sandbox, &Settings::default(), 1, &setup);
+    while !ready(&game) {
+        game.update(TICK);
+    }
+    game
+}
+
+fn bench_placements(c: &mut Criterion) {
+    for &(name, board) in [("empty", ".........."), ("ragged", RAGGED)].iter() {
+        for &shape in [Shape::I, Shape::T, Shape::S].iter() {
+            let game = game_on(board, shape);
+            c.bench_function(&format!("placements {:?} {}", shape, name), |b| {
+                b.iter(|| placements(&game))
+            });
+        }
+    }
+}
+
+criterion_group!(benches, bench_placements);
+criterion_main!(benches);
@@ -0,0 +1,75 @@
+use serde_derive::{Deserialize, Serialize};
+
+/// Everything a player can ask the game to do, independent of the device
+/// the request came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    SoftDrop,
+    HardDrop,
+    RotateCw,
+    RotateCcw,
+    #[serde(rename = "rotate_180")]
+    Rotate180,
+    Hold,
+    Pause,
+    Restart,
+    /// Let the computer take over the game, or give it back
+    Autoplay,
+}
+
+impl Action {
+    pub const ALL: [Action; 11] = [
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::SoftDrop,
+        Action::HardDrop,
+        Action::RotateCw,
+        Action::RotateCcw,
+        Action::Rotate180,
+        Action::Hold,
+        Action::Pause,
+        Action::Restart,
+        Action::Autoplay,
+    ];
+
+    /// Name used for the action in configuration files
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::MoveLeft => "move_left",
+            Action::MoveRight => "move_right",
+            Action::SoftDrop => "soft_drop",
+            Action::HardDrop => "hard_drop",
+            Action::RotateCw => "rotate_cw",
+            Action::RotateCcw => "rotate_ccw",
+            Action::Rotate180 => "rotate_180",
+            Action::Hold => "hold",
+            Action::Pause => "pause",
+            Action::Restart => "restart",
+            Action::Autoplay => "autoplay",
+        }
+    }
+
+    /// Whether the action moves or turns the falling piece without placing
+    /// it, the presses finesse counts
+    pub fn moves_piece(self) -> bool {
+        matches!(
+            self,
+            Action::MoveLeft
+                | Action::MoveRight
+                | Action::SoftDrop
+                | Action::RotateCw
+                | Action::RotateCcw
+                | Action::Rotate180
+        )
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL
+            .iter()
+            .cloned()
+            .find(|action| action.name() == name)
+    }
+}
@@ -0,0 +1,170 @@
+//! A small bot speaking the Tetris Bot Protocol, to test the game's side of
+//! the protocol. It follows the game from the messages it gets and suggests
+//! dropping the first piece of the queue where it ends up lowest, trying
+//! every orientation and column. It never holds and never spins.
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Write};
+use std::process;
+
+use tetris_ggez::tbp::{
+    Board, BotMessage, FrontendMessage, Location, Move, Orientation, Spin, BOARD_HEIGHT,
+    BOARD_WIDTH,
+};
+use tetris_ggez::tetromino::Shape;
+
+/// Row pieces are dropped from, above anything the game's well can hold
+const DROP_FROM: i32 = 22;
+
+struct Game {
+    board: Board,
+    hold: Option<Shape>,
+    queue: VecDeque<Shape>,
+}
+
+impl Game {
+    fn fits(&self, location: &Location) -> bool {
+        location.blocks().iter().all(|&(x, y)| {
+            x >= 0
+                && (x as usize) < BOARD_WIDTH
+                && y >= 0
+                && (y as usize) < BOARD_HEIGHT
+                && self.board[y as usize][x as usize].is_none()
+        })
+    }
+
+    /// Where a piece lands when dropped straight down
+    fn drop(&self, mut location: Location) -> Option<Location> {
+        if !self.fits(&location) {
+            return None;
+        }
+        loop {
+            let lower = Location {
+                y: location.y - 1,
+                ..location
+            };
+            if !self.fits(&lower) {
+                return Some(location);
+            }
+            location = lower;
+        }
+    }
+
+    /// Every place the first piece of the queue can be dropped to, the
+    /// lowest first
+    fn moves(&self) -> Vec<Move> {
+        let piece = match self.queue.front() {
+            Some(&piece) => piece,
+            None => return Vec::new(),
+        };
+        let mut moves = Vec::new();
+        for &orientation in Orientation::ALL.iter() {
+            for x in 0..BOARD_WIDTH as i32 {
+                let start = Location {
+                    piece,
+                    orientation,
+                    x,
+                    y: DROP_FROM,
+                };
+                if let Some(location) = self.drop(start) {
+                    let top = location.blocks().iter().map(|&(_, y)| y).max();
+                    let spin = Spin::None;
+                    moves.push((top, Move { location, spin }));
+                }
+            }
+        }
+        moves.sort_by_key(|&(top, _)| top);
+        moves.into_iter().map(|(_, mv)| mv).collect()
+    }
+
+    /// Place a piece, holding first if it is not the first in the queue
+    fn play(&mut self, mv: &Move) {
+        let piece = mv.location.piece;
+        if let Some(first) = self.queue.pop_front() {
+            if first != piece && self.hold.replace(first).is_none() {
+                self.queue.pop_front();
+            }
+        }
+        for &(x, y) in mv.location.blocks().iter() {
+            if let Some(cell) = self
+                .board
+                .get_mut(y as usize)
+                .and_then(|row| row.get_mut(x as usize))
+            {
+                *cell = Some(piece.letter());
+            }
+        }
+        self.board.retain(|row| row.contains(&None));
+        self.board.resize(BOARD_HEIGHT, vec![None; BOARD_WIDTH]);
+    }
+}
+
+fn send(out: &mut impl Write, message: &BotMessage) {
+    let sent = serde_json::to_string(message)
+        .map_err(|err| err.to_string())
+        .and_then(|line| {
+            writeln!(out, "{}", line)
+                .and_then(|()| out.flush())
+                .map_err(|err| err.to_string())
+        });
+    if let Err(err) = sent {
+        eprintln!("tbp-stub: {}", err);
+        process::exit(1);
+    }
+}
+
+fn main() {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    send(
+        &mut out,
+        &BotMessage::Info {
+            name: "tbp-stub".to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            author: env!("CARGO_PKG_AUTHORS").to_string(),
+            features: Vec::new(),
+        },
+    );
+    let mut game: Option<Game> = None;
+    for line in stdin.lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        let message = match serde_json::from_str(&line) {
+            Ok(message) => message,
+            Err(err) => {
+                eprintln!("tbp-stub: {}", err);
+                continue;
+            }
+        };
+        match message {
+            FrontendMessage::Rules { .. } => send(&mut out, &BotMessage::Ready),
+            FrontendMessage::Start {
+                hold, queue, board, ..
+            } => {
+                game = Some(Game {
+                    board,
+                    hold,
+                    queue: queue.into_iter().collect(),
+                })
+            }
+            FrontendMessage::Stop => game = None,
+            FrontendMessage::Suggest => {
+                let moves = game.as_ref().map(Game::moves).unwrap_or_default();
+                send(&mut out, &BotMessage::Suggestion { moves });
+            }
+            FrontendMessage::Play { mv } => {
+                if let Some(ref mut game) = game {
+                    game.play(&mv);
+                }
+            }
+            FrontendMessage::NewPiece { piece } => {
+                if let Some(ref mut game) = game {
+                    game.queue.push_back(piece);
+                }
+            }
+            FrontendMessage::Quit => break,
+        }
+    }
+}
@@ -0,0 +1,323 @@
+//! Runs games without a window, to check rule changes and measure bots
+//! where no display is available. A game is played by the built-in bot, by
+//! a recorded replay or by a script of moves, and the result is printed as
+//! text or JSON.
+//!
+//! Scripts are action names separated by whitespace, e.g. `rotate_cw
+//! move_left hard_drop`. Every action is tapped in a frame of its own and
+//! `wait <frames>` lets frames pass without input. `#` starts a comment.
+//! A new piece can only move sideways once it is inside the well, so a
+//! script waits a few frames after each drop.
+use std::path::{Path, PathBuf};
+use std::{env, fs, process};
+
+use serde_derive::Serialize;
+
+use tetris_ggez::action::Action;
+use tetris_ggez::bot::{Autoplay, Bot, HeuristicBot, Weights};
+use tetris_ggez::diagram;
+use tetris_ggez::game_state::{GameMode, Ruleset};
+use tetris_ggez::replay::Replay;
+use tetris_ggez::settings::Settings;
+use tetris_ggez::simulation::{Input, Simulation};
+use tetris_ggez::tbp::TbpBot;
+
+const USAGE: &str =
+    "usage: tetris-sim (--bot | --tbp <command> | --replay <file> | --script <file>)
+    [--mode marathon|sprint|ultra] [--ruleset classic|guideline] [--seed <n>]
+    [--pieces <n>] [--frames <n>] [--weights <file>] [--lookahead]
+    [--save-replay <file>] [--json]";
+
+const MODES: [GameMode; 3] = [GameMode::Marathon, GameMode::Sprint, GameMode::Ultra];
+
+/// Pieces a bot game is stopped after unless told otherwise, bots may
+/// never top out in Marathon
+const DEFAULT_BOT_PIECES: u32 = 1000;
+
+fn exit_with(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(2)
+}
+
+/// Who plays the game
+enum Player {
+    Bot,
+    /// A bot speaking the Tetris Bot Protocol, started with this command
+    Tbp(String),
+    Replay(PathBuf),
+    Script(PathBuf),
+}
+
+struct Options {
+    player: Player,
+    mode: GameMode,
+    ruleset: Option<Ruleset>,
+    seed: Option<u64>,
+    max_pieces: Option<u32>,
+    max_frames: Option<u32>,
+    /// JSON file with the bot's weights, missing ones keep their default
+    weights: Option<PathBuf>,
+    lookahead: bool,
+    save_replay: Option<PathBuf>,
+    json: bool,
+}
+
+fn parse_args() -> Options {
+    let mut args = env::args().skip(1);
+    let mut player = None;
+    let mut options = Options {
+        player: Player::Bot,
+        mode: GameMode::Marathon,
+        ruleset: None,
+        seed: None,
+        max_pieces: None,
+        max_frames: None,
+        weights: None,
+        lookahead: false,
+        save_replay: None,
+        json: false,
+    };
+    let number = |value: Option<String>| -> u64 {
+        value
+            .and_then(|value| value.parse().ok())
+            .unwrap_or_else(|| exit_with(USAGE))
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--bot" => player = Some(Player::Bot),
+            "--tbp" => player = args.next().map(Player::Tbp),
+            "--replay" => player = args.next().map(|path| Player::Replay(path.into())),
+            "--script" => player = args.next().map(|path| Player::Script(path.into())),
+            "--mode" => {
+                let name = args.next().unwrap_or_default();
+                options.mode = *MODES
+                    .iter()
+                    .find(|mode| mode.name().eq_ignore_ascii_case(&name))
+                    .unwrap_or_else(|| exit_with(USAGE));
+            }
+            "--ruleset" => {
+                let name = args.next().unwrap_or_default();
+                options.ruleset = Ruleset::ALL
+                    .iter()
+                    .cloned()
+                    .find(|ruleset| ruleset.name().eq_ignore_ascii_case(&name));
+                if options.ruleset.is_none() {
+                    exit_with(USAGE);
+                }
+            }
+            "--seed" => options.seed = Some(number(args.next())),
+            "--pieces" => options.max_pieces = Some(number(args.next()) as u32),
+            "--frames" => options.max_frames = Some(number(args.next()) as u32),
+            "--weights" => options.weights = args.next().map(PathBuf::from),
+            "--lookahead" => options.lookahead = true,
+            "--save-replay" => options.save_replay = args.next().map(PathBuf::from),
+            "--json" => options.json = true,
+            _ => exit_with(USAGE),
+        }
+    }
+    options.player = player.unwrap_or_else(|| exit_with(USAGE));
+    options
+}
+
+/// A step of a script
+enum Step {
+    Tap(Action),
+    Wait(u32),
+}
+
+fn parse_script(text: &str) -> Result<Vec<Step>, String> {
+    let mut steps = Vec::new();
+    for line in text.lines() {
+        let line = line.split('#').next().unwrap_or("");
+        let mut words = line.split_whitespace();
+        while let Some(word) = words.next() {
+            let step = if word == "wait" {
+                let frames = words
+                    .next()
+                    .and_then(|frames| frames.parse().ok())
+                    .ok_or_else(|| "wait needs a number of frames".to_string())?;
+                Step::Wait(frames)
+            } else {
+                Step::Tap(
+                    Action::from_name(word).ok_or_else(|| format!("unknown action {}", word))?,
+                )
+            };
+            steps.push(step);
+        }
+    }
+    Ok(steps)
+}
+
+/// A game being played and recorded, until it ends or hits a limit
+struct Run {
+    sim: Simulation,
+    replay: Replay,
+    max_pieces: Option<u32>,
+    max_frames: Option<u32>,
+}
+
+impl Run {
+    fn done(&self) -> bool {
+        let game = self.sim.game();
+        game.is_over()
+            || self.max_pieces.map(|max| game.stats().pieces >= max) == Some(true)
+            || self.max_frames.map(|max| self.sim.frame() >= max) == Some(true)
+    }
+
+    fn step(&mut self, inputs: &[Input]) {
+        for &input in inputs {
+            self.replay.record(&mut self.sim, input);
+        }
+        self.sim.step();
+    }
+}
+
+/// What is printed at the end
+#[derive(Serialize)]
+struct Summary {
+    mode: GameMode,
+    ruleset: Ruleset,
+    seed: u64,
+    frames: u32,
+    score: u64,
+    lines: u32,
+    pieces: u32,
+    /// Key presses more than needed to place the pieces
+    finesse_faults: u32,
+    game_over: bool,
+    goal_reached: bool,
+    /// The well as a diagram, one row per line
+    board: Vec<String>,
+}
+
+impl Summary {
+    fn new(sim: &Simulation) -> Self {
+        let game = sim.game();
+        let board = diagram::print(game.base(), Some(game.current()));
+        Self {
+            mode: game.mode(),
+            ruleset: game.ruleset(),
+            seed: game.seed(),
+            frames: sim.frame(),
+            score: game.points(),
+            lines: game.lines(),
+            pieces: game.stats().pieces,
+            finesse_faults: game.stats().finesse_faults,
+            game_over: game.is_over() && !game.goal_reached(),
+            goal_reached: game.goal_reached(),
+            board: board.lines().map(String::from).collect(),
+        }
+    }
+
+    fn print(&self, json: bool) {
+        if json {
+            match serde_json::to_string(self) {
+                Ok(text) => println!("{}", text),
+                Err(err) => exit_with(&format!("json: {}", err)),
+            }
+            return;
+        }
+        println!("mode         {}", self.mode.name());
+        println!("ruleset      {}", self.ruleset.name());
+        println!("seed         {}", self.seed);
+        println!("frames       {}", self.frames);
+        println!("score        {}", self.score);
+        println!("lines        {}", self.lines);
+        println!("pieces       {}", self.pieces);
+        println!("faults       {}", self.finesse_faults);
+        println!("game over    {}", self.game_over);
+        println!("goal reached {}", self.goal_reached);
+        println!();
+        for row in self.board.iter() {
+            println!("{}", row);
+        }
+    }
+}
+
+/// The bot asked for on the command line
+fn bot(options: &Options, settings: &Settings) -> Box<dyn Bot> {
+    if let Player::Tbp(ref command) = options.player {
+        return match TbpBot::launch(command, settings.ruleset) {
+            Ok(bot) => Box::new(bot),
+            Err(err) => exit_with(&format!("bot `{}`: {}", command, err)),
+        };
+    }
+    let weights = match options.weights {
+        Some(ref path) => fs::read_to_string(path)
+            .map_err(|err| err.to_string())
+            .and_then(|text| serde_json::from_str::<Weights>(&text).map_err(|err| err.to_string()))
+            .unwrap_or_else(|err| exit_with(&format!("weights {}: {}", path.display(), err))),
+        None => Weights::default(),
+    };
+    let mut bot = HeuristicBot::new(weights);
+    bot.lookahead = options.lookahead;
+    Box::new(bot)
+}
+
+fn main() {
+    let options = parse_args();
+    let mut settings = Settings::default();
+    if let Some(ruleset) = options.ruleset {
+        settings.ruleset = ruleset;
+    }
+    let seed = options.seed.unwrap_or_else(rand::random);
+    let new_run = |max_pieces| {
+        let replay = Replay::new(options.mode, seed, &settings);
+        Run {
+            sim: replay.start(),
+            replay,
+            max_pieces,
+            max_frames: options.max_frames,
+        }
+    };
+
+    let (sim, replay) = match options.player {
+        Player::Bot | Player::Tbp(_) => {
+            let mut run = new_run(options.max_pieces.or(Some(DEFAULT_BOT_PIECES)));
+            let mut autoplay = Autoplay::new(bot(&options, &settings));
+            while !run.done() {
+                let inputs = autoplay.inputs(run.sim.game());
+                run.step(&inputs);
+            }
+            (run.sim, run.replay)
+        }
+        Player::Script(ref path) => {
+            let steps = fs::read_to_string(path)
+                .map_err(|err| err.to_string())
+                .and_then(|text| parse_script(&text))
+                .unwrap_or_else(|err| exit_with(&format!("script {}: {}", path.display(), err)));
+            let mut run = new_run(options.max_pieces);
+            for step in steps {
+                match step {
+                    Step::Tap(action) => {
+                        if !run.done() {
+                            run.step(&[Input::Press(action), Input::Release(action)]);
+                        }
+                    }
+                    Step::Wait(frames) => {
+                        for _ in 0..frames {
+                            if !run.done() {
+                                run.step(&[]);
+                            }
+                        }
+                    }
+                }
+            }
+            (run.sim, run.replay)
+        }
+        Player::Replay(ref path) => {
+            let replay = Replay::load(path)
+                .unwrap_or_else(|err| exit_with(&format!("replay {}: {}", path.display(), err)));
+            (replay.play(|_| ()), replay)
+        }
+    };
+
+    if let Some(ref path) = options.save_replay {
+        let mut replay = replay;
+        replay.finish(&sim);
+        if let Err(err) = replay.save_to(Path::new(path)) {
+            exit_with(&format!("replay {}: {}", path.display(), err));
+        }
+    }
+    Summary::new(&sim).print(options.json);
+}
@@ -0,0 +1,112 @@
+//! Trains the weights of the built-in bot on the fast engine, printing the
+//! best lines and score of every generation. The population is saved to a
+//! checkpoint after each generation and picked up from there when the
+//! training is started again, and the best weights are written as a file
+//! `tetris-sim --weights` reads.
+use std::path::PathBuf;
+use std::{env, fs, process, thread};
+
+use tetris_ggez::game_state::Ruleset;
+use tetris_ggez::tuner::{Population, Training};
+
+const USAGE: &str = "usage: tetris-train [--generations <n>] [--population <n>] [--elite <n>]
+    [--games <n>] [--pieces <n>] [--ruleset classic|guideline] [--seed <n>]
+    [--threads <n>] [--checkpoint <file>] [--output <file>]";
+
+fn exit_with(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(2)
+}
+
+struct Options {
+    training: Training,
+    generations: u32,
+    seed: u64,
+    checkpoint: Option<PathBuf>,
+    /// Where the best weights so far are written after every generation
+    output: Option<PathBuf>,
+}
+
+fn parse_args() -> Options {
+    let mut args = env::args().skip(1);
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let mut options = Options {
+        training: Training {
+            threads,
+            ..Training::default()
+        },
+        generations: 20,
+        seed: 1,
+        checkpoint: None,
+        output: None,
+    };
+    let number = |value: Option<String>| -> u64 {
+        value
+            .and_then(|value| value.parse().ok())
+            .unwrap_or_else(|| exit_with(USAGE))
+    };
+    while let Some(arg) = args.next() {
+        let training = &mut options.training;
+        match arg.as_str() {
+            "--generations" => options.generations = number(args.next()) as u32,
+            "--population" => training.population = number(args.next()) as usize,
+            "--elite" => training.elite = number(args.next()) as usize,
+            "--games" => training.games = number(args.next()) as u32,
+            "--pieces" => training.pieces = number(args.next()) as u32,
+            "--ruleset" => {
+                let name = args.next().unwrap_or_default();
+                training.ruleset = Ruleset::ALL
+                    .iter()
+                    .cloned()
+                    .find(|ruleset| ruleset.name().eq_ignore_ascii_case(&name))
+                    .unwrap_or_else(|| exit_with(USAGE));
+            }
+            "--seed" => options.seed = number(args.next()),
+            "--threads" => training.threads = number(args.next()) as usize,
+            "--checkpoint" => options.checkpoint = args.next().map(PathBuf::from),
+            "--output" => options.output = args.next().map(PathBuf::from),
+            _ => exit_with(USAGE),
+        }
+    }
+    options
+}
+
+fn main() {
+    let options = parse_args();
+    let mut population = match options.checkpoint {
+        Some(ref path) if path.exists() => Population::load(path)
+            .unwrap_or_else(|err| exit_with(&format!("{}: {}", path.display(), err))),
+        _ => Population::new(options.seed),
+    };
+    if population.generation > 0 {
+        println!("resuming after generation {}", population.generation);
+    }
+    while population.generation < options.generations {
+        let report = population.evolve(&options.training);
+        println!(
+            "generation {:3}  best {:8.1} lines {:9.1} points  mean {:8.1} lines",
+            report.generation, report.best.lines, report.best.score, report.mean_lines
+        );
+        if let Some(ref path) = options.checkpoint {
+            if let Err(err) = population.save(path) {
+                eprintln!("{}: {}", path.display(), err);
+            }
+        }
+        if let (Some(path), Some(best)) = (&options.output, &population.best) {
+            let written = serde_json::to_string_pretty(&best.weights)
+                .map_err(|err| err.to_string())
+                .and_then(|text| fs::write(path, text).map_err(|err| err.to_string()));
+            if let Err(err) = written {
+                eprintln!("{}: {}", path.display(), err);
+            }
+        }
+    }
+    if let Some(best) = population.best {
+        println!(
+            "best {:.1} lines {:.1} points\n{}",
+            best.lines,
+            best.score,
+            serde_json::to_string_pretty(&best.weights).unwrap_or_default()
+        );
+    }
+}
@@ -0,0 +1,426 @@
+//! Terminal frontend, for playing where no window can be opened such as
+//! over SSH. It runs the same simulation as the window and records replays
+//! the same way, only drawing and input are its own.
+//!
+//! Terminals report keys being pressed but not released, so every key is a
+//! tap and held keys repeat at the terminal's own rate instead of DAS and
+//! ARR.
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+use std::{env, process, thread};
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
+use crossterm::style::{Color, Print, SetBackgroundColor, SetForegroundColor};
+use crossterm::{cursor, execute, queue, terminal};
+
+use tetris_ggez::action::Action;
+use tetris_ggez::broadcast::Broadcaster;
+use tetris_ggez::game_state::{GameMode, GameState};
+use tetris_ggez::replay::Replay;
+use tetris_ggez::settings::Settings;
+use tetris_ggez::simulation::{Input, Simulation, TICK};
+use tetris_ggez::tetromino::{Tetromino, GRID_SIZE};
+
+const USAGE: &str = "usage: tetris-tui [marathon|sprint|ultra]";
+const MODES: [GameMode; 3] = [GameMode::Marathon, GameMode::Sprint, GameMode::Ultra];
+
+/// Size of the drawn screen in terminal columns and rows
+const SCREEN: (u16, u16) = (50, 24);
+/// Columns the side panel and the next queue start at
+const PANEL_X: u16 = 24;
+const NEXT_X: u16 = 42;
+/// Frames between redraws, the screen is also only written when it changed
+const DRAW_INTERVAL: u32 = 2;
+/// Background of the well while it flashes for a finesse fault
+const FLASH: Color = Color::Rgb {
+    r: 96,
+    g: 24,
+    b: 24,
+};
+/// Frames simulated at most to catch up after a stall
+const MAX_STEPS: u32 = 10;
+
+const HELP: &str = "arrows move  z/x rotate  a 180  space drop  c hold  p pause  r restart  q quit";
+
+/// What a key asks for
+enum Command {
+    Action(Action),
+    Pause,
+    Restart,
+    Quit,
+}
+
+fn command(key: KeyEvent) -> Option<Command> {
+    if key.modifiers.contains(KeyModifiers::CONTROL) {
+        return match key.code {
+            KeyCode::Char('c') => Some(Command::Quit),
+            _ => None,
+        };
+    }
+    let action = match key.code {
+        KeyCode::Left => Action::MoveLeft,
+        KeyCode::Right => Action::MoveRight,
+        KeyCode::Down => Action::SoftDrop,
+        KeyCode::Up => Action::RotateCw,
+        KeyCode::Esc => return Some(Command::Quit),
+        KeyCode::Char(c) => match c.to_ascii_lowercase() {
+            'x' => Action::RotateCw,
+            'z' => Action::RotateCcw,
+            'a' => Action::Rotate180,
+            ' ' => Action::HardDrop,
+            'c' => Action::Hold,
+            'p' => return Some(Command::Pause),
+            'r' => return Some(Command::Restart),
+            'q' => return Some(Command::Quit),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(Command::Action(action))
+}
+
+fn color((r, g, b, _): (u8, u8, u8, u8)) -> Color {
+    Color::Rgb { r, g, b }
+}
+
+/// One character on the screen
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Glyph {
+    ch: char,
+    fg: Color,
+    bg: Color,
+}
+
+const BLANK: Glyph = Glyph {
+    ch: ' ',
+    fg: Color::Reset,
+    bg: Color::Reset,
+};
+
+/// The screen is drawn here first and only written to the terminal if it
+/// differs from the last one
+#[derive(Clone, PartialEq)]
+struct Canvas {
+    glyphs: Vec<Glyph>,
+}
+
+impl Canvas {
+    fn new() -> Self {
+        Self {
+            glyphs: vec![BLANK; SCREEN.0 as usize * SCREEN.1 as usize],
+        }
+    }
+
+    fn put(&mut self, x: u16, y: u16, glyph: Glyph) {
+        if x < SCREEN.0 && y < SCREEN.1 {
+            self.glyphs[y as usize * SCREEN.0 as usize + x as usize] = glyph;
+        }
+    }
+
+    fn text(&mut self, x: u16, y: u16, text: &str) {
+        for (i, ch) in text.chars().enumerate() {
+            let glyph = Glyph { ch, ..BLANK };
+            self.put(x + i as u16, y, glyph);
+        }
+    }
+
+    /// A cell of the well, two columns wide so cells come out square-ish
+    fn cell(&mut self, x: u16, y: u16, text: [char; 2], fg: Color, bg: Color) {
+        for (i, &ch) in text.iter().enumerate() {
+            self.put(x + i as u16, y, Glyph { ch, fg, bg });
+        }
+    }
+
+    /// A piece outside of the well with its top left corner at `(x, y)`.
+    /// Returns the number of rows it took.
+    fn piece(&mut self, x: u16, y: u16, piece: &Tetromino) -> u16 {
+        let min_x = piece.body.iter().map(|seg| seg.x).min().unwrap_or(0);
+        let min_y = piece.body.iter().map(|seg| seg.y).min().unwrap_or(0);
+        let mut height = 0;
+        for seg in piece.body.iter() {
+            let (dx, dy) = ((seg.x - min_x) as u16, (seg.y - min_y) as u16);
+            self.cell(
+                x + 2 * dx,
+                y + dy,
+                [' ', ' '],
+                Color::Reset,
+                color(seg.color),
+            );
+            height = height.max(dy + 1);
+        }
+        height
+    }
+
+    fn write(&self, out: &mut impl Write) -> crossterm::Result<()> {
+        let (mut fg, mut bg) = (Color::Reset, Color::Reset);
+        queue!(out, SetForegroundColor(fg), SetBackgroundColor(bg))?;
+        for (y, row) in self.glyphs.chunks(SCREEN.0 as usize).enumerate() {
+            queue!(out, cursor::MoveTo(0, y as u16))?;
+            for glyph in row {
+                if glyph.fg != fg {
+                    fg = glyph.fg;
+                    queue!(out, SetForegroundColor(fg))?;
+                }
+                if glyph.bg != bg {
+                    bg = glyph.bg;
+                    queue!(out, SetBackgroundColor(bg))?;
+                }
+                queue!(out, Print(glyph.ch))?;
+            }
+        }
+        out.flush()?;
+        Ok(())
+    }
+}
+
+/// Draw the well and the side panel, with a status line below. A paused
+/// game shows an empty well and no hold or next pieces, so pausing cannot
+/// be used to plan ahead.
+fn draw(game: &GameState, paused: bool, status: &str) -> Canvas {
+    let mut canvas = Canvas::new();
+    let (width, height) = (GRID_SIZE.0 as u16, GRID_SIZE.1 as u16);
+
+    // the well with its walls, rows above it are not shown
+    canvas.text(0, 0, &format!("┌{}┐", "─".repeat(2 * width as usize)));
+    for y in 0..height {
+        canvas.text(0, y + 1, "│");
+        canvas.text(2 * width + 1, y + 1, "│");
+    }
+    canvas.text(
+        0,
+        height + 1,
+        &format!("└{}┘", "─".repeat(2 * width as usize)),
+    );
+    let mut well_cell = |x: i16, y: i16, text, fg, bg| {
+        if x >= 0 && y >= 0 && x < GRID_SIZE.0 && y < GRID_SIZE.1 {
+            canvas.cell(1 + 2 * x as u16, 1 + y as u16, text, fg, bg);
+        }
+    };
+    // a finesse fault with the trainer on flashes the well
+    if game.flashing() && !paused {
+        for y in 0..GRID_SIZE.1 {
+            for x in 0..GRID_SIZE.0 {
+                well_cell(x, y, [' ', ' '], Color::Reset, FLASH);
+            }
+        }
+    }
+    if let (Some(ghost), false) = (game.ghost(), paused) {
+        for seg in ghost.body.iter() {
+            well_cell(seg.x, seg.y, ['[', ']'], color(seg.color), Color::Reset);
+        }
+    }
+    if !paused {
+        for seg in game.base().iter().chain(game.current().body.iter()) {
+            well_cell(seg.x, seg.y, [' ', ' '], Color::Reset, color(seg.color));
+        }
+    }
+
+    // score, hold and statistics
+    canvas.text(PANEL_X, 1, &format!("Score {}", game.points()));
+    canvas.text(PANEL_X, 3, "Hold");
+    if let (Some(shape), false) = (game.hold(), paused) {
+        canvas.piece(PANEL_X + 1, 4, &Tetromino::from(shape));
+    }
+    for (i, (label, value)) in game.stat_rows().iter().enumerate() {
+        let y = 9 + i as u16;
+        canvas.text(PANEL_X, y, label);
+        canvas.text(PANEL_X + 8, y, value);
+    }
+
+    // next queue, as many pieces as fit
+    canvas.text(NEXT_X, 1, "Next");
+    let mut y = 2;
+    for shape in game.next().filter(|_| !paused) {
+        let piece = Tetromino::from(shape);
+        let rows = piece.body.iter().map(|seg| seg.y).max().unwrap_or(0)
+            - piece.body.iter().map(|seg| seg.y).min().unwrap_or(0)
+            + 1;
+        if y + rows as u16 > height + 1 {
+            break;
+        }
+        y += canvas.piece(NEXT_X + 1, y, &piece) + 1;
+    }
+
+    canvas.text(0, height + 2, status);
+    canvas
+}
+
+/// A game being played and recorded
+struct Session {
+    sim: Simulation,
+    replay: Replay,
+    paused: bool,
+    saved: bool,
+    /// Why the replay could not be saved, shown in the status line since
+    /// the terminal is not printed to while playing
+    save_error: Option<String>,
+}
+
+impl Session {
+    fn new(mode: GameMode, settings: &Settings) -> Self {
+        let seed = rand::random();
+        let replay = Replay::new(mode, seed, settings);
+        Self {
+            sim: replay.start(),
+            replay,
+            paused: false,
+            saved: false,
+            save_error: None,
+        }
+    }
+
+    /// A key is a press immediately followed by a release
+    fn tap(&mut self, action: Action) {
+        if self.paused || self.sim.game().is_over() {
+            return;
+        }
+        self.replay.record(&mut self.sim, Input::Press(action));
+        self.replay.record(&mut self.sim, Input::Release(action));
+    }
+
+    fn step(&mut self) {
+        if !self.paused && !self.sim.game().is_over() {
+            self.sim.step();
+        }
+        if self.sim.game().is_over() && !self.saved {
+            self.saved = true;
+            self.replay.finish(&self.sim);
+            if let Err(err) = self.replay.save() {
+                self.save_error = Some(format!("replay: {}", err));
+            }
+        }
+    }
+
+    fn status(&self) -> String {
+        let game = self.sim.game();
+        if game.is_over() {
+            let result = if game.goal_reached() {
+                "Finished!"
+            } else {
+                "Game over"
+            };
+            match self.save_error {
+                Some(ref err) => format!("{}  {}", result, err),
+                None => format!("{}  r restarts, q quits", result),
+            }
+        } else if self.paused {
+            "Paused, p resumes".to_string()
+        } else {
+            HELP.to_string()
+        }
+    }
+}
+
+/// Puts the terminal back the way it was when dropped, also on errors
+struct RawTerminal;
+
+impl RawTerminal {
+    fn enter(out: &mut impl Write) -> crossterm::Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+        Ok(RawTerminal)
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn run(mode: GameMode) -> crossterm::Result<()> {
+    let settings = Settings::load_or_default();
+    let address = settings.spectator_address.trim();
+    let mut broadcaster = if address.is_empty() {
+        None
+    } else {
+        Broadcaster::bind(address)
+            .map_err(|err| eprintln!("spectators at {}: {}", address, err))
+            .ok()
+    };
+    let mut out = io::stdout();
+    let _terminal = RawTerminal::enter(&mut out)?;
+    execute!(out, terminal::Clear(terminal::ClearType::All))?;
+
+    let mut session = Session::new(mode, &settings);
+    let mut shown: Option<Canvas> = None;
+    let mut since_draw = DRAW_INTERVAL;
+    let mut next_frame = Instant::now();
+    loop {
+        while event::poll(Duration::from_secs(0))? {
+            let key = match event::read()? {
+                Event::Key(key) => key,
+                // a resized terminal may have been cleared
+                Event::Resize(..) => {
+                    shown = None;
+                    continue;
+                }
+                _ => continue,
+            };
+            match command(key) {
+                Some(Command::Action(action)) => session.tap(action),
+                Some(Command::Pause) => session.paused = !session.paused,
+                Some(Command::Restart) => session = Session::new(mode, &settings),
+                Some(Command::Quit) => return Ok(()),
+                None => continue,
+            }
+            // show the effect of a key right away
+            since_draw = DRAW_INTERVAL;
+        }
+
+        let mut steps = 0;
+        while next_frame <= Instant::now() && steps < MAX_STEPS {
+            session.step();
+            next_frame += TICK;
+            steps += 1;
+        }
+        if steps == MAX_STEPS {
+            next_frame = Instant::now() + TICK;
+        }
+        if let Some(ref mut broadcaster) = broadcaster {
+            if steps > 0 {
+                broadcaster.publish(session.sim.game());
+            }
+        }
+
+        since_draw += steps;
+        if since_draw >= DRAW_INTERVAL {
+            since_draw = 0;
+            let canvas = draw(session.sim.game(), session.paused, &session.status());
+            if shown.as_ref() != Some(&canvas) {
+                if shown.is_none() {
+                    execute!(out, terminal::Clear(terminal::ClearType::All))?;
+                }
+                canvas.write(&mut out)?;
+                shown = Some(canvas);
+            }
+        }
+
+        let now = Instant::now();
+        if next_frame > now {
+            thread::sleep(next_frame - now);
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mode = match args.first() {
+        None => GameMode::Marathon,
+        Some(name) => match MODES
+            .iter()
+            .find(|mode| mode.name().eq_ignore_ascii_case(name))
+        {
+            Some(&mode) if args.len() == 1 => mode,
+            _ => {
+                eprintln!("{}", USAGE);
+                process::exit(2);
+            }
+        },
+    };
+    if let Err(err) = run(mode) {
+        eprintln!("terminal: {}", err);
+        process::exit(1);
+    }
+}
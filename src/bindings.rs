@@ -0,0 +1,580 @@
+//! Keyboard and gamepad bindings, loaded from `bindings.toml` in the config
+//! directory.
+//!
+//! The file maps action names to one or more key names, with gamepad
+//! buttons and the stick deadzone in their own table, e.g.
+//!
+//! ```toml
+//! move_left = ["Left", "J"]
+//! hard_drop = ["Space"]
+//!
+//! [gamepad]
+//! deadzone = 0.4
+//! hold = ["LeftTrigger", "RightTrigger"]
+//! ```
+//!
+//! Actions missing from the file keep their default keys and buttons.
+//!
+//! The two players of a versus game have their own keys in the `player1`
+//! and `player2` tables, and gamepads are handed out to them in the order
+//! they are first used. Keys not in those tables, such as the pause key,
+//! work as in a single player game.
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::path::PathBuf;
+use std::{fmt, fs, io};
+
+use ggez::event::{Button, KeyCode};
+
+use crate::action::Action;
+use crate::config::config_dir;
+
+const FILE_NAME: &str = "bindings.toml";
+const GAMEPAD_TABLE: &str = "gamepad";
+const DEADZONE: &str = "deadzone";
+const PLAYER_TABLES: [&str; 2] = ["player1", "player2"];
+
+/// Stick deflection below which the stick counts as centered
+pub(crate) const DEFAULT_DEADZONE: f32 = 0.3;
+
+/// Maps keys and gamepad buttons to the actions they trigger
+#[derive(Debug, Clone)]
+pub(crate) struct Bindings {
+    keys: HashMap<KeyCode, Action>,
+    /// Keys of each player in a versus game
+    players: [HashMap<KeyCode, Action>; 2],
+    buttons: HashMap<Button, Action>,
+    deadzone: f32,
+}
+
+/// Why a bindings file could not be used
+#[derive(Debug)]
+pub(crate) enum BindingsError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    UnknownAction(String),
+    NotAKeyList(String),
+    UnknownKey {
+        action: String,
+        key: String,
+    },
+    DuplicateKey {
+        key: String,
+        first: Action,
+        second: Action,
+    },
+    NotATable(String),
+    InvalidDeadzone,
+}
+
+impl fmt::Display for BindingsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BindingsError::Io(err) => write!(f, "cannot read file: {}", err),
+            BindingsError::Parse(err) => write!(f, "invalid TOML: {}", err),
+            BindingsError::UnknownAction(name) => write!(
+                f,
+                "unknown action `{}`, expected one of: {}",
+                name,
+                Action::ALL
+                    .iter()
+                    .map(|action| action.name())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            BindingsError::NotAKeyList(action) => write!(
+                f,
+                "`{}` must be a list of key names, e.g. {} = [\"Left\"]",
+                action, action
+            ),
+            BindingsError::UnknownKey { action, key } => {
+                write!(f, "unknown key `{}` bound to `{}`", key, action)
+            }
+            BindingsError::DuplicateKey { key, first, second } => write!(
+                f,
+                "key `{}` is bound to both `{}` and `{}`",
+                key,
+                first.name(),
+                second.name()
+            ),
+            BindingsError::NotATable(name) => write!(f, "`{}` must be a table", name),
+            BindingsError::InvalidDeadzone => write!(
+                f,
+                "`{}.{}` must be a number between 0.0 and 1.0",
+                GAMEPAD_TABLE, DEADZONE
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BindingsError {}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Self {
+            keys: bind(&HashMap::new(), default_keys, key_name).expect("default keys are unique"),
+            players: bind_players(&[HashMap::new(), HashMap::new()])
+                .expect("default player keys are unique"),
+            buttons: bind(&HashMap::new(), default_buttons, button_name)
+                .expect("default buttons are unique"),
+            deadzone: DEFAULT_DEADZONE,
+        }
+    }
+}
+
+fn default_keys(action: Action) -> &'static [KeyCode] {
+    match action {
+        Action::MoveLeft => &[KeyCode::Left],
+        Action::MoveRight => &[KeyCode::Right],
+        Action::SoftDrop => &[KeyCode::Down],
+        Action::HardDrop => &[KeyCode::Space],
+        Action::RotateCw => &[KeyCode::Up, KeyCode::X],
+        Action::RotateCcw => &[KeyCode::Z, KeyCode::LControl],
+        Action::Rotate180 => &[KeyCode::A],
+        Action::Hold => &[KeyCode::C, KeyCode::LShift],
+        Action::Pause => &[KeyCode::Escape, KeyCode::P],
+        Action::Restart => &[KeyCode::R],
+        Action::Autoplay => &[KeyCode::F2],
+    }
+}
+
+/// The left hand side of the keyboard for the first player of a versus
+/// game
+fn default_player1_keys(action: Action) -> &'static [KeyCode] {
+    match action {
+        Action::MoveLeft => &[KeyCode::A],
+        Action::MoveRight => &[KeyCode::D],
+        Action::SoftDrop => &[KeyCode::S],
+        Action::HardDrop => &[KeyCode::W],
+        Action::RotateCw => &[KeyCode::E],
+        Action::RotateCcw => &[KeyCode::Q],
+        Action::Rotate180 => &[KeyCode::R],
+        Action::Hold => &[KeyCode::LShift],
+        Action::Pause | Action::Restart | Action::Autoplay => &[],
+    }
+}
+
+/// The arrows and the keys next to them for the second player
+fn default_player2_keys(action: Action) -> &'static [KeyCode] {
+    match action {
+        Action::MoveLeft => &[KeyCode::Left],
+        Action::MoveRight => &[KeyCode::Right],
+        Action::SoftDrop => &[KeyCode::Down],
+        Action::HardDrop => &[KeyCode::Up],
+        Action::RotateCw => &[KeyCode::Period],
+        Action::RotateCcw => &[KeyCode::Comma],
+        Action::Rotate180 => &[KeyCode::Slash],
+        Action::Hold => &[KeyCode::RShift],
+        Action::Pause | Action::Restart | Action::Autoplay => &[],
+    }
+}
+
+fn default_buttons(action: Action) -> &'static [Button] {
+    match action {
+        Action::MoveLeft => &[Button::DPadLeft],
+        Action::MoveRight => &[Button::DPadRight],
+        Action::SoftDrop => &[Button::DPadDown],
+        Action::HardDrop => &[Button::DPadUp],
+        Action::RotateCw => &[Button::South],
+        Action::RotateCcw => &[Button::East],
+        Action::Rotate180 => &[Button::North],
+        Action::Hold => &[Button::West, Button::LeftTrigger, Button::RightTrigger],
+        Action::Pause => &[Button::Start],
+        Action::Restart => &[Button::Select],
+        Action::Autoplay => &[Button::RightThumb],
+    }
+}
+
+impl Bindings {
+    /// Load the bindings file from the config directory. A missing file
+    /// gives the default bindings, an invalid one is reported and ignored.
+    pub(crate) fn load_or_default() -> Self {
+        let path = match Self::path() {
+            Some(path) => path,
+            None => return Self::default(),
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => Self::from_toml(&text).unwrap_or_else(|err| {
+                eprintln!("{}: {}; using default key bindings", path.display(), err);
+                Self::default()
+            }),
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Self::default(),
+            Err(err) => {
+                eprintln!(
+                    "{}: {}; using default key bindings",
+                    path.display(),
+                    BindingsError::Io(err)
+                );
+                Self::default()
+            }
+        }
+    }
+
+    pub(crate) fn path() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join(FILE_NAME))
+    }
+
+    /// Parse and validate the contents of a bindings file
+    pub(crate) fn from_toml(text: &str) -> Result<Self, BindingsError> {
+        let table: toml::value::Table = toml::from_str(text).map_err(BindingsError::Parse)?;
+
+        let keys = parse_lists(
+            &table,
+            &[GAMEPAD_TABLE, PLAYER_TABLES[0], PLAYER_TABLES[1]],
+            key_from_name,
+        )?;
+        let mut players = [HashMap::new(), HashMap::new()];
+        for (keys, name) in players.iter_mut().zip(PLAYER_TABLES.iter()) {
+            if let Some(player) = table.get(*name) {
+                let player = player
+                    .as_table()
+                    .ok_or_else(|| BindingsError::NotATable(name.to_string()))?;
+                *keys = parse_lists(player, &[], key_from_name)?;
+            }
+        }
+        let mut buttons = HashMap::new();
+        let mut deadzone = DEFAULT_DEADZONE;
+        if let Some(gamepad) = table.get(GAMEPAD_TABLE) {
+            let gamepad = gamepad
+                .as_table()
+                .ok_or_else(|| BindingsError::NotATable(GAMEPAD_TABLE.to_string()))?;
+            buttons = parse_lists(gamepad, &[DEADZONE], button_from_name)?;
+            if let Some(value) = gamepad.get(DEADZONE) {
+                deadzone = value
+                    .as_float()
+                    .filter(|value| *value >= 0.0 && *value < 1.0)
+                    .ok_or(BindingsError::InvalidDeadzone)? as f32;
+            }
+        }
+
+        Ok(Self {
+            keys: bind(&keys, default_keys, key_name)?,
+            players: bind_players(&players)?,
+            buttons: bind(&buttons, default_buttons, button_name)?,
+            deadzone,
+        })
+    }
+
+    /// The action bound to a key, if any
+    pub(crate) fn action(&self, key: KeyCode) -> Option<Action> {
+        self.keys.get(&key).cloned()
+    }
+
+    /// The player of a versus game a key belongs to and the action it is
+    /// bound to, if any
+    pub(crate) fn player_action(&self, key: KeyCode) -> Option<(usize, Action)> {
+        self.players
+            .iter()
+            .enumerate()
+            .find_map(|(player, keys)| keys.get(&key).map(|&action| (player, action)))
+    }
+
+    /// The action bound to a gamepad button, if any
+    pub(crate) fn button_action(&self, button: Button) -> Option<Action> {
+        self.buttons.get(&button).cloned()
+    }
+
+    pub(crate) fn deadzone(&self) -> f32 {
+        self.deadzone
+    }
+}
+
+/// Read `action = ["Name", ...]` entries of a table, skipping the entries
+/// listed in `skip`
+fn parse_lists<T>(
+    table: &toml::value::Table,
+    skip: &[&str],
+    lookup: fn(&str) -> Option<T>,
+) -> Result<HashMap<Action, Vec<T>>, BindingsError> {
+    let mut configured = HashMap::new();
+    for (name, value) in table.iter() {
+        if skip.contains(&name.as_str()) {
+            continue;
+        }
+        let action =
+            Action::from_name(name).ok_or_else(|| BindingsError::UnknownAction(name.clone()))?;
+        let list = value
+            .as_array()
+            .ok_or_else(|| BindingsError::NotAKeyList(name.clone()))?;
+        let mut inputs = Vec::new();
+        for input in list {
+            let input_name = input
+                .as_str()
+                .ok_or_else(|| BindingsError::NotAKeyList(name.clone()))?;
+            inputs.push(lookup(input_name).ok_or_else(|| BindingsError::UnknownKey {
+                action: name.clone(),
+                key: input_name.to_string(),
+            })?);
+        }
+        configured.insert(action, inputs);
+    }
+    Ok(configured)
+}
+
+/// Build the input to action map, falling back to the defaults for actions
+/// that were not configured. Every input may trigger a single action only.
+fn bind<T: Copy + Eq + Hash>(
+    configured: &HashMap<Action, Vec<T>>,
+    defaults: fn(Action) -> &'static [T],
+    name: fn(T) -> String,
+) -> Result<HashMap<T, Action>, BindingsError> {
+    let mut bound = HashMap::new();
+    for &action in Action::ALL.iter() {
+        let inputs = match configured.get(&action) {
+            Some(inputs) => inputs.as_slice(),
+            None => defaults(action),
+        };
+        for &input in inputs {
+            if let Some(&first) = bound.get(&input) {
+                return Err(BindingsError::DuplicateKey {
+                    key: name(input),
+                    first,
+                    second: action,
+                });
+            }
+            bound.insert(input, action);
+        }
+    }
+    Ok(bound)
+}
+
+/// Bind the keys of both players. A key may only belong to one of them.
+fn bind_players(
+    configured: &[HashMap<Action, Vec<KeyCode>>; 2],
+) -> Result<[HashMap<KeyCode, Action>; 2], BindingsError> {
+    let first = bind(&configured[0], default_player1_keys, key_name)?;
+    let second = bind(&configured[1], default_player2_keys, key_name)?;
+    for (&key, &action) in second.iter() {
+        if let Some(&taken) = first.get(&key) {
+            return Err(BindingsError::DuplicateKey {
+                key: key_name(key),
+                first: taken,
+                second: action,
+            });
+        }
+    }
+    Ok([first, second])
+}
+
+/// Gamepad buttons that can be used in the bindings file
+const BUTTON_NAMES: &[(&str, Button)] = &[
+    ("South", Button::South),
+    ("East", Button::East),
+    ("North", Button::North),
+    ("West", Button::West),
+    ("LeftTrigger", Button::LeftTrigger),
+    ("LeftTrigger2", Button::LeftTrigger2),
+    ("RightTrigger", Button::RightTrigger),
+    ("RightTrigger2", Button::RightTrigger2),
+    ("Select", Button::Select),
+    ("Start", Button::Start),
+    ("Mode", Button::Mode),
+    ("LeftThumb", Button::LeftThumb),
+    ("RightThumb", Button::RightThumb),
+    ("DPadUp", Button::DPadUp),
+    ("DPadDown", Button::DPadDown),
+    ("DPadLeft", Button::DPadLeft),
+    ("DPadRight", Button::DPadRight),
+];
+
+pub(crate) fn button_from_name(name: &str) -> Option<Button> {
+    BUTTON_NAMES
+        .iter()
+        .find(|(button_name, _)| button_name.eq_ignore_ascii_case(name))
+        .map(|&(_, button)| button)
+}
+
+pub(crate) fn button_name(button: Button) -> String {
+    BUTTON_NAMES
+        .iter()
+        .find(|&&(_, b)| b == button)
+        .map(|(name, _)| name.to_string())
+        .unwrap_or_else(|| format!("{:?}", button))
+}
+
+/// Keys that can be used in the bindings file, named after their `KeyCode`
+const KEY_NAMES: &[(&str, KeyCode)] = &[
+    ("A", KeyCode::A),
+    ("B", KeyCode::B),
+    ("C", KeyCode::C),
+    ("D", KeyCode::D),
+    ("E", KeyCode::E),
+    ("F", KeyCode::F),
+    ("G", KeyCode::G),
+    ("H", KeyCode::H),
+    ("I", KeyCode::I),
+    ("J", KeyCode::J),
+    ("K", KeyCode::K),
+    ("L", KeyCode::L),
+    ("M", KeyCode::M),
+    ("N", KeyCode::N),
+    ("O", KeyCode::O),
+    ("P", KeyCode::P),
+    ("Q", KeyCode::Q),
+    ("R", KeyCode::R),
+    ("S", KeyCode::S),
+    ("T", KeyCode::T),
+    ("U", KeyCode::U),
+    ("V", KeyCode::V),
+    ("W", KeyCode::W),
+    ("X", KeyCode::X),
+    ("Y", KeyCode::Y),
+    ("Z", KeyCode::Z),
+    ("Key0", KeyCode::Key0),
+    ("Key1", KeyCode::Key1),
+    ("Key2", KeyCode::Key2),
+    ("Key3", KeyCode::Key3),
+    ("Key4", KeyCode::Key4),
+    ("Key5", KeyCode::Key5),
+    ("Key6", KeyCode::Key6),
+    ("Key7", KeyCode::Key7),
+    ("Key8", KeyCode::Key8),
+    ("Key9", KeyCode::Key9),
+    ("F1", KeyCode::F1),
+    ("F2", KeyCode::F2),
+    ("F3", KeyCode::F3),
+    ("F4", KeyCode::F4),
+    ("F5", KeyCode::F5),
+    ("F6", KeyCode::F6),
+    ("F7", KeyCode::F7),
+    ("F8", KeyCode::F8),
+    ("F9", KeyCode::F9),
+    ("F10", KeyCode::F10),
+    ("F11", KeyCode::F11),
+    ("F12", KeyCode::F12),
+    ("Left", KeyCode::Left),
+    ("Right", KeyCode::Right),
+    ("Up", KeyCode::Up),
+    ("Down", KeyCode::Down),
+    ("Space", KeyCode::Space),
+    ("Return", KeyCode::Return),
+    ("Escape", KeyCode::Escape),
+    ("Tab", KeyCode::Tab),
+    ("Back", KeyCode::Back),
+    ("Insert", KeyCode::Insert),
+    ("Delete", KeyCode::Delete),
+    ("Home", KeyCode::Home),
+    ("End", KeyCode::End),
+    ("PageUp", KeyCode::PageUp),
+    ("PageDown", KeyCode::PageDown),
+    ("LShift", KeyCode::LShift),
+    ("RShift", KeyCode::RShift),
+    ("LControl", KeyCode::LControl),
+    ("RControl", KeyCode::RControl),
+    ("LAlt", KeyCode::LAlt),
+    ("RAlt", KeyCode::RAlt),
+    ("Numpad0", KeyCode::Numpad0),
+    ("Numpad1", KeyCode::Numpad1),
+    ("Numpad2", KeyCode::Numpad2),
+    ("Numpad3", KeyCode::Numpad3),
+    ("Numpad4", KeyCode::Numpad4),
+    ("Numpad5", KeyCode::Numpad5),
+    ("Numpad6", KeyCode::Numpad6),
+    ("Numpad7", KeyCode::Numpad7),
+    ("Numpad8", KeyCode::Numpad8),
+    ("Numpad9", KeyCode::Numpad9),
+    ("Comma", KeyCode::Comma),
+    ("Period", KeyCode::Period),
+    ("Slash", KeyCode::Slash),
+    ("Semicolon", KeyCode::Semicolon),
+    ("Apostrophe", KeyCode::Apostrophe),
+    ("LBracket", KeyCode::LBracket),
+    ("RBracket", KeyCode::RBracket),
+    ("Minus", KeyCode::Minus),
+    ("Equals", KeyCode::Equals),
+    ("Backslash", KeyCode::Backslash),
+    ("Grave", KeyCode::Grave),
+];
+
+/// Look a key up by name, ignoring case
+pub(crate) fn key_from_name(name: &str) -> Option<KeyCode> {
+    KEY_NAMES
+        .iter()
+        .find(|(key_name, _)| key_name.eq_ignore_ascii_case(name))
+        .map(|&(_, key)| key)
+}
+
+pub(crate) fn key_name(key: KeyCode) -> String {
+    KEY_NAMES
+        .iter()
+        .find(|&&(_, k)| k == key)
+        .map(|(name, _)| name.to_string())
+        .unwrap_or_else(|| format!("{:?}", key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_actions_keep_defaults() {
+        let bindings = Bindings::from_toml("hold = [\"H\"]").unwrap();
+        assert_eq!(bindings.action(KeyCode::H), Some(Action::Hold));
+        assert_eq!(bindings.action(KeyCode::C), None);
+        assert_eq!(bindings.action(KeyCode::Left), Some(Action::MoveLeft));
+    }
+
+    #[test]
+    fn test_invalid_files_are_rejected() {
+        match Bindings::from_toml("jump = [\"Space\"]") {
+            Err(BindingsError::UnknownAction(name)) => assert_eq!(name, "jump"),
+            other => panic!("unexpected result {:?}", other),
+        }
+        match Bindings::from_toml("hold = [\"Lft\"]") {
+            Err(BindingsError::UnknownKey { key, .. }) => assert_eq!(key, "Lft"),
+            other => panic!("unexpected result {:?}", other),
+        }
+        match Bindings::from_toml("hold = [\"Space\"]") {
+            Err(BindingsError::DuplicateKey { first, second, .. }) => {
+                assert_eq!((first, second), (Action::HardDrop, Action::Hold))
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(Bindings::from_toml("hold = \"H\"").is_err());
+    }
+
+    #[test]
+    fn test_gamepad_table() {
+        let bindings = Bindings::from_toml("[gamepad]\ndeadzone = 0.5\nhold = [\"Mode\"]").unwrap();
+        assert_eq!(bindings.button_action(Button::Mode), Some(Action::Hold));
+        assert_eq!(bindings.button_action(Button::West), None);
+        assert_eq!(bindings.deadzone(), 0.5);
+        assert!(Bindings::from_toml("[gamepad]\ndeadzone = 1.5").is_err());
+        assert!(Bindings::from_toml("[gamepad]\nhold = [\"Trigger\"]").is_err());
+    }
+
+    #[test]
+    fn test_player_tables() {
+        let bindings = Bindings::default();
+        assert_eq!(
+            bindings.player_action(KeyCode::A),
+            Some((0, Action::MoveLeft))
+        );
+        assert_eq!(
+            bindings.player_action(KeyCode::Up),
+            Some((1, Action::HardDrop))
+        );
+        assert_eq!(bindings.player_action(KeyCode::Escape), None);
+
+        let bindings = Bindings::from_toml("[player2]\nhold = [\"Key0\"]").unwrap();
+        assert_eq!(
+            bindings.player_action(KeyCode::Key0),
+            Some((1, Action::Hold))
+        );
+        assert_eq!(bindings.player_action(KeyCode::RShift), None);
+        assert_eq!(
+            bindings.player_action(KeyCode::LShift),
+            Some((0, Action::Hold))
+        );
+        assert_eq!(bindings.action(KeyCode::Key0), None);
+
+        // the same key for both players
+        match Bindings::from_toml("[player2]\nhold = [\"W\"]") {
+            Err(BindingsError::DuplicateKey { first, second, .. }) => {
+                assert_eq!((first, second), (Action::HardDrop, Action::Hold))
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(Bindings::from_toml("player1 = 3").is_err());
+    }
+}
@@ -0,0 +1,279 @@
+//! The stack as a bit mask per row, and the rules pieces move by on it:
+//! sliding, rotating and being pushed back in from the walls, falling and
+//! landing, T-spin corners, clearing rows and rising garbage. `GameState`
+//! keeps its stack here, and the move generator and the bots search and
+//! rate placements on copies of it, so they all play by the same rules.
+//! Nothing is allocated.
+use serde_derive::{Deserialize, Serialize};
+
+use crate::tetromino::{Motion, Segment, Shape, GRID_SIZE};
+
+const WIDTH: i16 = GRID_SIZE.0;
+const HEIGHT: usize = GRID_SIZE.1 as usize;
+const FULL_ROW: u16 = (1 << WIDTH) - 1;
+
+/// The cells of a piece in the coordinates of the well, in the order of
+/// its body, so the second one is the center it rotates around
+pub type Cells = [(i16, i16); 4];
+
+/// The stack, a bit mask per row from the top down with bit `x` set for a
+/// filled cell in column `x`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Board {
+    pub rows: [u16; HEIGHT],
+}
+
+impl Board {
+    /// The board of a stack, cells outside of the well are left out
+    pub fn new(stack: &[Segment]) -> Self {
+        let mut board = Self::default();
+        for seg in stack {
+            if Self::inside(seg.x, seg.y) {
+                board.rows[seg.y as usize] |= 1 << seg.x;
+            }
+        }
+        board
+    }
+
+    fn inside(x: i16, y: i16) -> bool {
+        (0..WIDTH).contains(&x) && (0..HEIGHT as i16).contains(&y)
+    }
+
+    /// Whether the cell is in the well and taken by the stack
+    pub fn filled(&self, x: i16, y: i16) -> bool {
+        Self::inside(x, y) && self.rows[y as usize] & 1 << x != 0
+    }
+
+    /// Outside of the walls or the floor, or taken by the stack. The well
+    /// is open at the top.
+    pub fn blocked(&self, x: i16, y: i16) -> bool {
+        !(0..WIDTH).contains(&x) || y >= HEIGHT as i16 || self.filled(x, y)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.iter().all(|&row| row == 0)
+    }
+
+    /// Whether the piece rests on the stack or the floor
+    pub fn landed(&self, cells: &Cells) -> bool {
+        cells
+            .iter()
+            .any(|&(x, y)| y == HEIGHT as i16 - 1 || self.filled(x, y + 1))
+    }
+
+    /// A landed piece touching the top row cannot be dropped, the game is
+    /// over
+    pub fn hit_ceiling(&self, cells: &Cells) -> bool {
+        cells.iter().any(|&(_, y)| y == 0) && self.landed(cells)
+    }
+
+    /// Where the piece lands falling straight down
+    pub fn fall(&self, cells: &Cells) -> Cells {
+        let mut cells = *cells;
+        while !self.landed(&cells) {
+            for cell in cells.iter_mut() {
+                cell.1 += 1;
+            }
+        }
+        cells
+    }
+
+    /// Move or rotate the piece, or leave it where it is if it cannot
+    pub fn moved(&self, shape: Shape, cells: &Cells, motion: Motion) -> Cells {
+        match motion {
+            Motion::Left => self.shift(cells, -1),
+            Motion::Right => self.shift(cells, 1),
+            Motion::RotateCw | Motion::RotateCcw | Motion::Rotate180 => {
+                self.rotate(shape, cells, motion)
+            }
+        }
+    }
+
+    /// Move the piece sideways by `dx` columns. Pieces only move once all
+    /// of them is inside the well.
+    pub fn shift(&self, cells: &Cells, dx: i16) -> Cells {
+        let free = cells
+            .iter()
+            .all(|&(x, y)| x + dx >= 0 && x + dx < WIDTH && y > -1 && !self.filled(x + dx, y));
+        let mut moved = *cells;
+        if free {
+            for cell in moved.iter_mut() {
+                cell.0 += dx;
+            }
+        }
+        moved
+    }
+
+    /// Rotate around the second cell, then push the piece back in if it
+    /// sticks out of a wall. The y axis points down, so `(dx, dy) ->
+    /// (-dy, dx)` turns the piece clockwise on screen. The O piece does not
+    /// turn, and no piece turns into the stack or through the floor.
+    pub fn rotate(&self, shape: Shape, cells: &Cells, motion: Motion) -> Cells {
+        if shape == Shape::O {
+            return *cells;
+        }
+        let (cx, cy) = cells[1];
+        let mut rotated = *cells;
+        for cell in rotated.iter_mut() {
+            let (dx, dy) = (cell.0 - cx, cell.1 - cy);
+            let (dx, dy) = match motion {
+                Motion::RotateCw => (-dy, dx),
+                Motion::RotateCcw => (dy, -dx),
+                _ => (-dx, -dy),
+            };
+            *cell = (cx + dx, cy + dy);
+        }
+        if rotated
+            .iter()
+            .any(|&(x, y)| y >= HEIGHT as i16 || self.filled(x, y))
+        {
+            return *cells;
+        }
+        let min_x = rotated.iter().map(|&(x, _)| x).min().unwrap_or(0);
+        let max_x = rotated.iter().map(|&(x, _)| x).max().unwrap_or(0);
+        let kick = if min_x < 0 {
+            min_x
+        } else if max_x >= WIDTH {
+            max_x - WIDTH + 1
+        } else {
+            0
+        };
+        for cell in rotated.iter_mut() {
+            cell.0 -= kick;
+        }
+        rotated
+    }
+
+    /// Three of the four cells diagonal to the center of the piece blocked,
+    /// a T rotated into such a place is a T-spin
+    pub fn tspin_corners(&self, cells: &Cells) -> bool {
+        let (cx, cy) = cells[1];
+        [(-1, -1), (1, -1), (-1, 1), (1, 1)]
+            .iter()
+            .filter(|&&(dx, dy)| self.blocked(cx + dx, cy + dy))
+            .count()
+            >= 3
+    }
+
+    /// Add the cells of a piece to the stack, the ones above the well are
+    /// left out
+    pub fn lock(&mut self, cells: &Cells) {
+        for &(x, y) in cells.iter() {
+            if Self::inside(x, y) {
+                self.rows[y as usize] |= 1 << x;
+            }
+        }
+    }
+
+    /// Remove the full rows and move the rows above them down. Returns a
+    /// mask with bit `y` set for every row `y` that was full.
+    pub fn clear_full_rows(&mut self) -> u32 {
+        let mut cleared = 0;
+        let mut to = HEIGHT;
+        for from in (0..HEIGHT).rev() {
+            if self.rows[from] == FULL_ROW {
+                cleared |= 1 << from;
+            } else {
+                to -= 1;
+                self.rows[to] = self.rows[from];
+            }
+        }
+        for row in self.rows[..to].iter_mut() {
+            *row = 0;
+        }
+        cleared
+    }
+
+    /// Push the stack up by a row of garbage with a hole in the column.
+    /// Returns false if that pushed any of the stack out of the well.
+    pub fn push_garbage(&mut self, hole: i16) -> bool {
+        let spilled = self.rows[0] != 0;
+        self.rows.copy_within(1.., 0);
+        self.rows[HEIGHT - 1] = FULL_ROW & !(1 << hole);
+        !spilled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagram;
+
+    fn board(text: &str) -> Board {
+        Board::new(&diagram::parse(text).unwrap().board)
+    }
+
+    #[test]
+    fn test_moves() {
+        let board = board("GGG....GGG\n..........\n..........");
+        let bottom = HEIGHT as i16 - 1;
+        // an O under the overhang slides further under it
+        let o = [(3, bottom - 1), (4, bottom - 1), (3, bottom), (4, bottom)];
+        assert!(board.landed(&o));
+        assert_eq!(board.shift(&o, -1)[0], (2, bottom - 1));
+        assert_eq!(board.fall(&board.shift(&o, -1)), board.shift(&o, -1));
+
+        // a vertical I against the left wall turns and is pushed back in
+        let i = [(0, 5), (0, 6), (0, 7), (0, 8)];
+        let turned = board.rotate(Shape::I, &i, Motion::RotateCw);
+        assert_eq!(turned, [(3, 6), (2, 6), (1, 6), (0, 6)]);
+        assert!(!board.landed(&turned));
+        assert_eq!(board.fall(&turned)[0], (3, bottom - 3));
+        // not into the stack
+        let low = [
+            (4, bottom - 3),
+            (4, bottom - 2),
+            (4, bottom - 1),
+            (4, bottom),
+        ];
+        assert_eq!(board.rotate(Shape::I, &low, Motion::RotateCw), low);
+        // nor sideways while sticking out of the top
+        let high = [(5, -1), (5, 0), (5, 1), (5, 2)];
+        assert_eq!(board.shift(&high, 1), high);
+    }
+
+    #[test]
+    fn test_clears_and_garbage() {
+        let mut board = board("GGGGGG....\nGGGGGG....");
+        let bottom = HEIGHT as i16 - 1;
+        board.lock(&[
+            (6, bottom - 1),
+            (7, bottom - 1),
+            (8, bottom - 1),
+            (9, bottom - 1),
+        ]);
+        assert_eq!(board.clear_full_rows(), 1 << (HEIGHT - 2));
+        assert_eq!(board.rows[HEIGHT - 1], 0b11_1111);
+        assert_eq!(board.rows[HEIGHT - 2], 0);
+        assert_eq!(board.clear_full_rows(), 0);
+
+        assert!(board.push_garbage(9));
+        assert_eq!(board.rows[HEIGHT - 1], FULL_ROW & !(1 << 9));
+        assert_eq!(board.rows[HEIGHT - 2], 0b11_1111);
+        for _ in 2..HEIGHT {
+            assert!(board.push_garbage(0));
+        }
+        assert!(!board.push_garbage(0));
+        assert!(!board.is_empty());
+    }
+
+    #[test]
+    fn test_tspin_corners() {
+        let board = board("...G......\nGGG...GGGG\nGGGG.GGGGG");
+        let bottom = HEIGHT as i16 - 1;
+        let t = [
+            (3, bottom - 1),
+            (4, bottom - 1),
+            (4, bottom),
+            (5, bottom - 1),
+        ];
+        assert!(board.tspin_corners(&t));
+        let t = [
+            (4, bottom - 3),
+            (4, bottom - 2),
+            (3, bottom - 2),
+            (5, bottom - 2),
+        ];
+        assert!(!board.tspin_corners(&t));
+    }
+}
@@ -0,0 +1,760 @@
+//! Computer players. A bot looks at the game and plans the moves that place
+//! the current piece, and `Autoplay` hands them to the simulation as the
+//! inputs a player's keys make. Placements come from `movegen`, so bots
+//! tuck and spin as well. `Opponent` does the same for the computer player
+//! of versus games, held back to a difficulty.
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::action::Action;
+use crate::game_state::GameState;
+use crate::movegen::{placements, placements_of, Placement, ROTATION_MARGIN};
+use crate::randomizer::Rng;
+use crate::simulation::{Input, TICK};
+use crate::tetromino::{Segment, GRID_SIZE};
+
+/// Something that can play the game
+pub trait Bot {
+    /// Actions that place the current piece, ending with a hard drop. A
+    /// soft drop is held until the piece lands, the other actions are
+    /// tapped. A plan of just a hold swaps the piece, and the bot is asked
+    /// again once the new piece is `ready`.
+    fn plan(&mut self, game: &GameState) -> Vec<Action>;
+}
+
+/// Whether the current piece is far enough into the well to be placed
+/// anywhere, pieces can only be moved sideways while all of it is inside
+pub fn ready(game: &GameState) -> bool {
+    game.current()
+        .body
+        .iter()
+        .all(|seg| seg.y >= ROTATION_MARGIN)
+}
+
+/// The game after the current piece is locked where a placement puts it
+pub(crate) fn after(game: &GameState, placement: &Placement) -> GameState {
+    let mut result = game.clone();
+    result.place(placement);
+    result
+}
+
+/// The places of the current piece. A piece that has only just spawned
+/// is looked at from where it can be moved anywhere.
+fn reachable(game: &GameState) -> Vec<Placement> {
+    if ready(game) {
+        placements(game)
+    } else {
+        placements_of(game, game.current().shape())
+    }
+}
+
+/// Height of the stack in rows
+pub fn stack_height(game: &GameState) -> i16 {
+    game.base()
+        .iter()
+        .map(|seg| GRID_SIZE.1 - seg.y)
+        .max()
+        .unwrap_or(0)
+}
+
+/// Puts every piece where the stack stays lowest. It is not good at the
+/// game, but it is simple and never thinks for long.
+pub struct LowestBot;
+
+impl Bot for LowestBot {
+    fn plan(&mut self, game: &GameState) -> Vec<Action> {
+        placements(game)
+            .into_iter()
+            .min_by_key(|placement| {
+                let result = after(game, placement);
+                (
+                    result.is_over() && !result.goal_reached(),
+                    stack_height(&result),
+                )
+            })
+            .map(|placement| placement.actions())
+            .unwrap_or_else(|| vec![Action::HardDrop])
+    }
+}
+
+/// Properties of a board that tell how well it can be played on
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Features {
+    /// Sum of the heights of all columns
+    pub aggregate_height: i32,
+    /// Empty cells with a filled cell somewhere above them
+    pub holes: i32,
+    /// Sum of the height differences of neighbouring columns
+    pub bumpiness: i32,
+    /// Columns lower than both neighbours, the walls count as high. A well
+    /// of depth `d` counts `1 + 2 + ... + d`, deep wells are much harder to
+    /// fill than shallow ones.
+    pub wells: i32,
+    /// Changes between filled and empty cells along the rows that have any
+    /// filled cell, the walls count as filled
+    pub row_transitions: i32,
+    /// Changes between filled and empty cells down the columns, the floor
+    /// counts as filled
+    pub column_transitions: i32,
+    /// Lines cleared to get to the board
+    pub lines_cleared: i32,
+}
+
+impl Features {
+    pub fn new(board: &[Segment], lines_cleared: u32) -> Self {
+        let mut rows = [0; GRID_SIZE.1 as usize];
+        for seg in board {
+            if seg.x >= 0 && seg.x < GRID_SIZE.0 && seg.y >= 0 && seg.y < GRID_SIZE.1 {
+                rows[seg.y as usize] |= 1 << seg.x;
+            }
+        }
+        Self::from_rows(&rows, lines_cleared)
+    }
+
+    /// Features of a board given as one bit mask per row from the top down,
+    /// bit `x` set for a filled cell in column `x`
+    pub fn from_rows(rows: &[u16], lines_cleared: u32) -> Self {
+        const WIDTH: usize = GRID_SIZE.0 as usize;
+        let height = rows.len();
+        let filled = |x: usize, y: usize| rows[y] & 1 << x != 0;
+        let mut heights = [0; WIDTH];
+        for (x, column) in heights.iter_mut().enumerate() {
+            *column = (0..height)
+                .find(|&y| filled(x, y))
+                .map(|top| (height - top) as i32)
+                .unwrap_or(0);
+        }
+
+        let mut features = Features {
+            aggregate_height: heights.iter().sum(),
+            lines_cleared: lines_cleared as i32,
+            ..Features::default()
+        };
+        for x in 0..WIDTH {
+            let top = height - heights[x] as usize;
+            features.holes += (top..height).filter(|&y| !filled(x, y)).count() as i32;
+            if x + 1 < WIDTH {
+                features.bumpiness += (heights[x] - heights[x + 1]).abs();
+            }
+            let left = if x > 0 { heights[x - 1] } else { i32::MAX };
+            let right = heights.get(x + 1).cloned().unwrap_or(i32::MAX);
+            let depth = left.min(right) - heights[x];
+            if depth > 0 {
+                features.wells += depth * (depth + 1) / 2;
+            }
+            let mut above = false;
+            for y in 0..height {
+                features.column_transitions += (filled(x, y) != above) as i32;
+                above = filled(x, y);
+            }
+            features.column_transitions += !above as i32;
+        }
+        // walls on both sides count as filled
+        let walled = |row: u16| u32::from(row) << 1 | 1 | 1 << (WIDTH + 1);
+        for &row in rows.iter().filter(|&&row| row != 0) {
+            let row = walled(row);
+            let changes = (row ^ row >> 1) & ((1 << (WIDTH + 1)) - 1);
+            features.row_transitions += changes.count_ones() as i32;
+        }
+        features
+    }
+}
+
+/// How much each feature counts when boards are compared, boards with a
+/// higher score are better. Most weights are negative.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Weights {
+    pub aggregate_height: f32,
+    pub holes: f32,
+    pub bumpiness: f32,
+    pub wells: f32,
+    pub row_transitions: f32,
+    pub column_transitions: f32,
+    pub lines_cleared: f32,
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Self {
+            aggregate_height: -0.5,
+            holes: -7.9,
+            bumpiness: -0.2,
+            wells: -3.4,
+            row_transitions: -3.2,
+            column_transitions: -9.3,
+            lines_cleared: 3.4,
+        }
+    }
+}
+
+impl Weights {
+    /// The weights in the order of the fields, to treat them as a vector
+    pub fn to_array(&self) -> [f32; 7] {
+        [
+            self.aggregate_height,
+            self.holes,
+            self.bumpiness,
+            self.wells,
+            self.row_transitions,
+            self.column_transitions,
+            self.lines_cleared,
+        ]
+    }
+
+    pub fn from_array(values: [f32; 7]) -> Self {
+        Self {
+            aggregate_height: values[0],
+            holes: values[1],
+            bumpiness: values[2],
+            wells: values[3],
+            row_transitions: values[4],
+            column_transitions: values[5],
+            lines_cleared: values[6],
+        }
+    }
+
+    pub fn score(&self, features: &Features) -> f32 {
+        self.aggregate_height * features.aggregate_height as f32
+            + self.holes * features.holes as f32
+            + self.bumpiness * features.bumpiness as f32
+            + self.wells * features.wells as f32
+            + self.row_transitions * features.row_transitions as f32
+            + self.column_transitions * features.column_transitions as f32
+            + self.lines_cleared * features.lines_cleared as f32
+    }
+}
+
+/// What the heuristic bot does with the current piece
+#[derive(Debug, Clone)]
+pub enum Choice {
+    Place(Placement),
+    /// Swap the piece with the held one, or the next one if none is held
+    Hold,
+}
+
+/// Rates every placement by the features of the board it leaves and picks
+/// the best one. It can also consider the held piece, and look one piece
+/// further ahead by scoring a placement by the best placement of the next
+/// piece after it.
+pub struct HeuristicBot {
+    pub weights: Weights,
+    /// Hold the current piece when the other piece fits better
+    pub hold: bool,
+    /// Also place the next piece before rating a placement, which is much
+    /// slower
+    pub lookahead: bool,
+}
+
+impl HeuristicBot {
+    pub fn new(weights: Weights) -> Self {
+        Self {
+            weights,
+            hold: true,
+            lookahead: false,
+        }
+    }
+
+    /// Score of a game after placing pieces in `start`, lost games are worst
+    fn rate(&self, start: &GameState, result: &GameState) -> f32 {
+        if result.is_over() && !result.goal_reached() {
+            return f32::NEG_INFINITY;
+        }
+        let features = Features::from_rows(&result.board().rows, result.lines() - start.lines());
+        self.weights.score(&features)
+    }
+
+    /// The best placement of the current piece with its score
+    fn best(&self, game: &GameState) -> Option<(f32, Placement)> {
+        reachable(game)
+            .into_iter()
+            .map(|placement| {
+                let result = after(game, &placement);
+                let mut score = self.rate(game, &result);
+                if self.lookahead && score > f32::NEG_INFINITY {
+                    score = placements_of(&result, result.current().shape())
+                        .iter()
+                        .map(|next| self.rate(game, &after(&result, next)))
+                        .fold(f32::NEG_INFINITY, f32::max);
+                }
+                (score, placement)
+            })
+            .max_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal))
+    }
+
+    /// Where to put the current piece, or whether to hold it first. `None`
+    /// once there is no place left for it.
+    pub fn choose(&self, game: &GameState) -> Option<Choice> {
+        let best = self.best(game);
+        if self.hold && game.can_hold() {
+            let mut held = game.clone();
+            held.perform(Action::Hold);
+            let held = self.best(&held).map(|(score, _)| score);
+            match (&best, held) {
+                (Some((score, _)), Some(held)) if held <= *score => (),
+                (_, Some(_)) => return Some(Choice::Hold),
+                _ => (),
+            }
+        }
+        best.map(|(_, placement)| Choice::Place(placement))
+    }
+}
+
+impl Default for HeuristicBot {
+    fn default() -> Self {
+        Self::new(Weights::default())
+    }
+}
+
+impl Bot for HeuristicBot {
+    fn plan(&mut self, game: &GameState) -> Vec<Action> {
+        match self.choose(game) {
+            Some(Choice::Place(placement)) => placement.actions(),
+            Some(Choice::Hold) => vec![Action::Hold],
+            None => vec![Action::HardDrop],
+        }
+    }
+}
+
+/// Tell the game that the computer plays it from now on, unless it knows.
+/// Its pieces are not judged for finesse then.
+fn take_over(game: &GameState) -> Vec<Input> {
+    if game.computer_playing() {
+        Vec::new()
+    } else {
+        vec![Input::Press(Action::Autoplay)]
+    }
+}
+
+/// Whether the current piece rests on the stack or the floor
+fn landed(game: &GameState) -> bool {
+    game.drop_position().body == game.current().body
+}
+
+/// Hands the actions of a plan to the game as the piece can take them.
+/// Actions are tapped, a soft drop is held until the piece lands and the
+/// actions after it are tapped then.
+#[derive(Default)]
+struct Plan {
+    actions: VecDeque<Action>,
+    /// Soft drop is held
+    dropping: bool,
+    /// Pieces placed when the plan was made, the rest of it is dropped if
+    /// the piece locks before it is done
+    pieces: u32,
+}
+
+impl Plan {
+    fn new(game: &GameState, actions: Vec<Action>) -> Self {
+        Self {
+            actions: actions.into_iter().collect(),
+            dropping: false,
+            pieces: game.stats().pieces,
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.actions.is_empty() && !self.dropping
+    }
+
+    /// Inputs to apply before the next frame to carry on with the plan
+    fn inputs(&mut self, game: &GameState) -> Vec<Input> {
+        let mut inputs = Vec::new();
+        let locked = game.stats().pieces != self.pieces;
+        if locked {
+            self.actions.clear();
+        }
+        if self.dropping {
+            if !locked && !landed(game) {
+                return inputs;
+            }
+            self.dropping = false;
+            inputs.push(Input::Release(Action::SoftDrop));
+        }
+        while let Some(action) = self.actions.pop_front() {
+            if action == Action::SoftDrop {
+                self.dropping = true;
+                inputs.push(Input::Press(action));
+                break;
+            }
+            inputs.push(Input::Press(action));
+            inputs.push(Input::Release(action));
+        }
+        inputs
+    }
+}
+
+/// Lets a bot play a simulated game
+pub struct Autoplay {
+    bot: Box<dyn Bot>,
+    plan: Plan,
+}
+
+impl Autoplay {
+    pub fn new(bot: Box<dyn Bot>) -> Self {
+        Self {
+            bot,
+            plan: Plan::default(),
+        }
+    }
+
+    /// Inputs to apply before the next frame. Once the current piece is
+    /// `ready` the bot plans where it goes, and the plan is carried out
+    /// from that frame on, so the piece ends up exactly where the bot
+    /// expected.
+    pub fn inputs(&mut self, game: &GameState) -> Vec<Input> {
+        if game.is_over() {
+            return Vec::new();
+        }
+        let mut inputs = take_over(game);
+        if self.plan.is_done() && ready(game) {
+            self.plan = Plan::new(game, self.bot.plan(game));
+        }
+        inputs.extend(self.plan.inputs(game));
+        inputs
+    }
+}
+
+/// How well the computer opponent of versus games plays
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+    Expert,
+}
+
+/// What holds a computer opponent back
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Strength {
+    /// Pieces placed per second at most. A piece cannot be placed before
+    /// it has entered the well, so slow gravity holds the opponent back as
+    /// well.
+    pub pps: f32,
+    /// Also place the next piece before a place is rated, as the heuristic
+    /// bot's `lookahead`
+    pub lookahead: bool,
+    /// Chance in percent that a piece goes to a random place instead of
+    /// the best one
+    pub mistakes: u32,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 4] = [
+        Difficulty::Easy,
+        Difficulty::Medium,
+        Difficulty::Hard,
+        Difficulty::Expert,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Medium => "Medium",
+            Difficulty::Hard => "Hard",
+            Difficulty::Expert => "Expert",
+        }
+    }
+
+    pub fn strength(self) -> Strength {
+        let (pps, lookahead, mistakes) = match self {
+            Difficulty::Easy => (0.4, false, 15),
+            Difficulty::Medium => (0.6, false, 5),
+            Difficulty::Hard => (1.0, true, 2),
+            // about as fast as pieces enter the well at top speed
+            Difficulty::Expert => (5.0, true, 0),
+        };
+        Strength {
+            pps,
+            lookahead,
+            mistakes,
+        }
+    }
+}
+
+/// The computer player of versus games. It plays like the heuristic bot,
+/// but waits for its time between pieces and now and then makes a
+/// mistake. New pieces are soft dropped until they can be placed.
+pub struct Opponent {
+    bot: HeuristicBot,
+    strength: Strength,
+    rng: Rng,
+    /// Time since the last piece was dropped
+    waited: Duration,
+    /// Soft drop is held to bring a new piece into the well
+    dropping: bool,
+    plan: Plan,
+}
+
+impl Opponent {
+    pub fn new(strength: Strength, seed: u64) -> Self {
+        let mut opponent = Self {
+            bot: HeuristicBot::default(),
+            strength,
+            rng: Rng::new(seed),
+            waited: Duration::from_secs(0),
+            dropping: false,
+            plan: Plan::default(),
+        };
+        opponent.set_strength(strength);
+        opponent
+    }
+
+    pub fn set_strength(&mut self, strength: Strength) {
+        self.strength = strength;
+        self.bot.lookahead = strength.lookahead;
+    }
+
+    /// A random place for the current piece that does not lose the game
+    fn mistake(&mut self, game: &GameState) -> Option<Vec<Action>> {
+        let mut options: Vec<_> = placements(game)
+            .into_iter()
+            .filter(|placement| !after(game, placement).is_over())
+            .collect();
+        if options.is_empty() {
+            return None;
+        }
+        let index = self.rng.below(options.len() as u32) as usize;
+        Some(options.swap_remove(index).actions())
+    }
+
+    /// Inputs to apply before the next frame, asked for once every frame
+    pub fn inputs(&mut self, game: &GameState) -> Vec<Input> {
+        self.waited += TICK;
+        if game.is_over() {
+            return Vec::new();
+        }
+        let mut inputs = take_over(game);
+        inputs.extend(self.play(game));
+        inputs
+    }
+
+    /// The inputs for the current piece, paced by the strength
+    fn play(&mut self, game: &GameState) -> Vec<Input> {
+        if !self.plan.is_done() {
+            return self.plan.inputs(game);
+        }
+        if !ready(game) {
+            if self.dropping {
+                return Vec::new();
+            }
+            self.dropping = true;
+            return vec![Input::Press(Action::SoftDrop)];
+        }
+        let mut inputs = Vec::new();
+        if self.dropping {
+            self.dropping = false;
+            inputs.push(Input::Release(Action::SoftDrop));
+        }
+        let interval = Duration::from_secs_f32(1.0 / self.strength.pps.max(0.1));
+        if self.waited < interval {
+            return inputs;
+        }
+        let mistake = if self.rng.below(100) < self.strength.mistakes {
+            self.mistake(game)
+        } else {
+            None
+        };
+        let plan = mistake.unwrap_or_else(|| self.bot.plan(game));
+        // a hold only swaps the piece, the time is counted per piece placed
+        if plan.last() == Some(&Action::HardDrop) {
+            self.waited = Duration::from_secs(0);
+        }
+        self.plan = Plan::new(game, plan);
+        inputs.extend(self.plan.inputs(game));
+        inputs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagram;
+    use crate::finesse::Trainer;
+    use crate::game_state::{GameMode, Setup};
+    use crate::movegen::Step;
+    use crate::settings::Settings;
+    use crate::simulation::Simulation;
+    use crate::tetromino::Shape;
+
+    /// Makes the same plan for every piece
+    struct Fixed(Vec<Action>);
+
+    impl Bot for Fixed {
+        fn plan(&mut self, _game: &GameState) -> Vec<Action> {
+            self.0.clone()
+        }
+    }
+
+    fn cells(board: &[Segment]) -> Vec<(i16, i16)> {
+        let mut cells: Vec<(i16, i16)> = board.iter().map(|seg| (seg.x, seg.y)).collect();
+        cells.sort();
+        cells
+    }
+
+    #[test]
+    fn test_autoplay() {
+        let mut sim = Simulation::new(GameMode::Marathon, &Settings::default(), 3);
+        let mut autoplay = Autoplay::new(Box::new(LowestBot));
+        // the lowest stack any placement of the current piece leaves
+        let mut lowest = None;
+        let mut pieces = 0;
+        while pieces < 20 {
+            if lowest.is_none() && ready(sim.game()) {
+                lowest = placements(sim.game())
+                    .iter()
+                    .map(|placement| stack_height(&after(sim.game(), placement)))
+                    .min();
+            }
+            for input in autoplay.inputs(sim.game()) {
+                sim.apply(input);
+            }
+            sim.step();
+            if sim.game().stats().pieces > pieces {
+                pieces = sim.game().stats().pieces;
+                assert_eq!(Some(stack_height(sim.game())), lowest.take());
+            }
+        }
+        assert!(!sim.game().is_over());
+    }
+
+    #[test]
+    fn test_autoplay_tucks() {
+        let setup = Setup {
+            board: diagram::parse("GGG....GGG\n..........\n..........")
+                .unwrap()
+                .board,
+            hold: None,
+            queue: vec![Shape::O],
+        };
+        let mut sim = Simulation::with_setup(GameMode::Sandbox, &Settings::default(), 1, &setup);
+        while !ready(sim.game()) {
+            sim.step();
+        }
+        // under the overhang in the bottom left corner
+        let tuck = placements(sim.game())
+            .into_iter()
+            .find(|placement| {
+                let cells = cells(&placement.piece.body);
+                cells[0] == (0, GRID_SIZE.1 - 2)
+            })
+            .unwrap();
+        assert!(tuck.path.contains(&Step::SoftDrop));
+        let expected = cells(after(sim.game(), &tuck).base());
+
+        let mut autoplay = Autoplay::new(Box::new(Fixed(tuck.actions())));
+        while sim.game().stats().pieces < 1 {
+            for input in autoplay.inputs(sim.game()) {
+                sim.apply(input);
+            }
+            sim.step();
+        }
+        assert_eq!(cells(sim.game().base()), expected);
+    }
+
+    #[test]
+    fn test_features() {
+        let board = diagram::parse("G.........\nGG.GGGGG.G").unwrap().board;
+        let features = Features::new(&board, 1);
+        assert_eq!(
+            features,
+            Features {
+                aggregate_height: 9,
+                holes: 0,
+                bumpiness: 5,
+                wells: 2,
+                row_transitions: 6,
+                column_transitions: 10,
+                lines_cleared: 1,
+            }
+        );
+        let board = diagram::parse("GGG.......\nG.G.......").unwrap().board;
+        assert_eq!(Features::new(&board, 0).holes, 1);
+    }
+
+    #[test]
+    fn test_heuristic_bot() {
+        let mut sim = Simulation::new(GameMode::Marathon, &Settings::default(), 3);
+        let mut autoplay = Autoplay::new(Box::new(HeuristicBot::default()));
+        while sim.game().stats().pieces < 100 {
+            for input in autoplay.inputs(sim.game()) {
+                sim.apply(input);
+            }
+            sim.step();
+        }
+        let features = Features::new(sim.game().base(), 0);
+        assert!(!sim.game().is_over());
+        assert!(sim.game().lines() >= 30);
+        assert!(features.holes <= 2, "{:?}", sim.game());
+    }
+
+    /// Let an opponent play for some seconds, with the pieces it placed
+    fn opponent_game(strength: Strength, seconds: u32) -> Simulation {
+        let mut sim = Simulation::new(GameMode::Versus, &Settings::default(), 8);
+        let mut opponent = Opponent::new(strength, 8);
+        while sim.frame() < seconds * 60 && !sim.game().is_over() {
+            for input in opponent.inputs(sim.game()) {
+                sim.apply(input);
+            }
+            sim.step();
+        }
+        sim
+    }
+
+    #[test]
+    fn test_opponent() {
+        let slow = Strength {
+            pps: 0.3,
+            lookahead: false,
+            mistakes: 0,
+        };
+        let sim = opponent_game(slow, 20);
+        let pieces = sim.game().stats().pieces;
+        assert!((5..=7).contains(&pieces), "{}", pieces);
+        assert_eq!(Features::new(sim.game().base(), 0).holes, 0);
+        let fast = opponent_game(Strength { pps: 5.0, ..slow }, 20);
+        assert!(fast.game().stats().pieces > pieces);
+
+        // placing every piece at random leaves holes
+        let sim = opponent_game(
+            Strength {
+                mistakes: 100,
+                ..slow
+            },
+            20,
+        );
+        assert!(Features::new(sim.game().base(), 0).holes > 0);
+    }
+
+    #[test]
+    fn test_trainer_leaves_computer_alone() {
+        let settings = Settings {
+            finesse_trainer: Trainer::Retry,
+            ..Settings::default()
+        };
+        let mut sim = Simulation::new(GameMode::Marathon, &settings, 3);
+        let mut autoplay = Autoplay::new(Box::new(HeuristicBot::default()));
+        let mut opponent = Opponent::new(Difficulty::Hard.strength(), 8);
+        let mut cpu = Simulation::new(GameMode::Versus, &settings, 8);
+        while sim.game().stats().pieces < 20 || cpu.game().stats().pieces < 20 {
+            for input in autoplay.inputs(sim.game()) {
+                sim.apply(input);
+            }
+            for input in opponent.inputs(cpu.game()) {
+                cpu.apply(input);
+            }
+            sim.step();
+            cpu.step();
+            assert!(sim.frame() < 10 * 60 * 60, "stuck on the current piece");
+        }
+        for game in [sim.game(), cpu.game()].iter() {
+            assert!(game.computer_playing());
+            assert!(!game.flashing());
+            assert_eq!(game.stats().finesse_faults, 0);
+        }
+    }
+}
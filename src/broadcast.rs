@@ -0,0 +1,287 @@
+//! Publishes the game being played to spectators: overlays, dashboards or
+//! another process showing the game. Spectators connect over TCP and get
+//! one JSON object per line, a `Frame` every time the game is updated. A
+//! spectator is never waited for: what it cannot take right away is kept
+//! for the next update, and one that falls too far behind is disconnected.
+use std::io::{self, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::diagram;
+use crate::game_state::{GameMode, GameState};
+use crate::stats::Stats;
+use crate::tetromino::{Shape, GRID_SIZE};
+
+/// Bytes kept for a spectator that is not reading, a few hundred frames
+const MAX_BACKLOG: usize = 256 * 1024;
+
+/// The game as a spectator sees it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Frame {
+    pub mode: GameMode,
+    /// Every row of the well from the top down as in a diagram, see
+    /// `diagram`. The falling piece is not part of it.
+    pub board: Vec<String>,
+    pub piece: Piece,
+    pub queue: Vec<Shape>,
+    pub hold: Option<Shape>,
+    pub score: u64,
+    pub lines: u32,
+    pub pieces: u32,
+    pub elapsed_ms: u64,
+    /// Rows of garbage on their way in versus games
+    pub garbage: u32,
+    pub game_over: bool,
+    /// What happened since the frame before
+    pub events: Vec<Event>,
+}
+
+/// The falling piece
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Piece {
+    pub shape: Shape,
+    /// Cells of the piece as `(x, y)`, counted from the top left of the
+    /// well like the rows of the board. Rows above the well are negative.
+    pub cells: Vec<(i16, i16)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    /// A new game started
+    Started,
+    /// Pieces were locked into the stack, usually one
+    Locked {
+        shape: Shape,
+        lines: u32,
+        tspin: bool,
+        perfect_clear: bool,
+        combo: u32,
+        back_to_back: bool,
+    },
+    /// The game ended, by topping out or reaching the goal of the mode
+    Ended { goal_reached: bool },
+}
+
+/// What happened between two states of a game, told apart by its stats
+fn events(before: Option<(&Stats, bool)>, game: &GameState) -> Vec<Event> {
+    let stats = game.stats();
+    let (before, was_over) = match before {
+        Some((before, was_over)) if before.elapsed <= stats.elapsed => (before, was_over),
+        _ => return vec![Event::Started],
+    };
+    let mut events = Vec::new();
+    if stats.pieces > before.pieces {
+        let shape = stats
+            .shapes
+            .iter()
+            .zip(before.shapes.iter())
+            .zip(Shape::ALL.iter())
+            .find(|((now, then), _)| now > then)
+            .map_or(game.current().shape(), |(_, &shape)| shape);
+        events.push(Event::Locked {
+            shape,
+            lines: stats.lines - before.lines,
+            tspin: stats.tspins > before.tspins,
+            perfect_clear: stats.perfect_clears > before.perfect_clears,
+            combo: stats.combo,
+            back_to_back: stats.back_to_back,
+        });
+    }
+    if game.is_over() && !was_over {
+        events.push(Event::Ended {
+            goal_reached: game.goal_reached(),
+        });
+    }
+    events
+}
+
+impl Frame {
+    fn new(game: &GameState, events: Vec<Event>) -> Self {
+        // diagrams start at the highest filled row
+        let stack = diagram::print(game.base(), None);
+        let empty = ".".repeat(GRID_SIZE.0 as usize);
+        let mut board = vec![empty; (GRID_SIZE.1 as usize).saturating_sub(stack.lines().count())];
+        board.extend(stack.lines().map(String::from));
+        Self {
+            mode: game.mode(),
+            board,
+            piece: Piece {
+                shape: game.current().shape(),
+                cells: game
+                    .current()
+                    .clone_body()
+                    .iter()
+                    .map(|seg| (seg.x, seg.y))
+                    .collect(),
+            },
+            queue: game.next().collect(),
+            hold: game.hold(),
+            score: game.points(),
+            lines: game.lines(),
+            pieces: game.stats().pieces,
+            elapsed_ms: game.elapsed().as_millis() as u64,
+            garbage: game.garbage().total(),
+            game_over: game.is_over(),
+            events,
+        }
+    }
+}
+
+/// A connected spectator with what it has not taken yet
+struct Spectator {
+    stream: TcpStream,
+    backlog: Vec<u8>,
+}
+
+impl Spectator {
+    /// Write as much of the backlog as the connection takes without
+    /// waiting. Returns false once the spectator is gone or too far behind.
+    fn flush(&mut self) -> bool {
+        while !self.backlog.is_empty() {
+            match self.stream.write(&self.backlog) {
+                Ok(0) => return false,
+                Ok(written) => {
+                    self.backlog.drain(..written);
+                }
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => (),
+                Err(_) => return false,
+            }
+        }
+        self.backlog.len() <= MAX_BACKLOG
+    }
+}
+
+/// Accepts spectators and sends them the game
+pub struct Broadcaster {
+    listener: TcpListener,
+    spectators: Vec<Spectator>,
+    /// Stats of the game last published and whether it was over, to tell
+    /// what happened since
+    last: Option<(Stats, bool)>,
+}
+
+impl Broadcaster {
+    pub fn bind<A: ToSocketAddrs>(address: A) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            listener,
+            spectators: Vec::new(),
+            last: None,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn spectators(&self) -> usize {
+        self.spectators.len()
+    }
+
+    /// Let new spectators in and send everyone the game as it is now
+    pub fn publish(&mut self, game: &GameState) {
+        while let Ok((stream, _)) = self.listener.accept() {
+            // writing to a spectator must never hold up the game
+            if stream.set_nonblocking(true).is_ok() {
+                self.spectators.push(Spectator {
+                    stream,
+                    backlog: Vec::new(),
+                });
+            }
+        }
+        let last = self.last.as_ref().map(|(stats, over)| (stats, *over));
+        let events = events(last, game);
+        self.last = Some((game.stats().clone(), game.is_over()));
+        if self.spectators.is_empty() {
+            return;
+        }
+        let line = match serde_json::to_string(&Frame::new(game, events)) {
+            Ok(line) => line + "\n",
+            Err(err) => {
+                eprintln!("broadcast: {}", err);
+                return;
+            }
+        };
+        // frames are only ever sent whole, the part of a line a spectator
+        // could not take waits in its backlog
+        for spectator in self.spectators.iter_mut() {
+            spectator.backlog.extend_from_slice(line.as_bytes());
+        }
+        self.spectators.retain_mut(Spectator::flush);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action::Action;
+    use crate::settings::Settings;
+    use crate::simulation::{Input, Simulation};
+    use std::io::{BufRead, BufReader};
+
+    #[test]
+    fn test_broadcast() {
+        let mut broadcaster = Broadcaster::bind("127.0.0.1:0").unwrap();
+        let spectator = TcpStream::connect(broadcaster.local_addr().unwrap()).unwrap();
+        let mut lines = BufReader::new(spectator).lines();
+
+        let mut sim = Simulation::new(GameMode::Marathon, &Settings::default(), 4);
+        while broadcaster.spectators() == 0 {
+            broadcaster.publish(sim.game());
+        }
+        sim.step();
+        sim.apply(Input::Press(Action::HardDrop));
+        sim.step();
+        broadcaster.publish(sim.game());
+
+        let read = |lines: &mut io::Lines<_>| -> Frame {
+            let line: String = lines.next().unwrap().unwrap();
+            serde_json::from_str(&line).unwrap()
+        };
+        let first = read(&mut lines);
+        assert_eq!(first.events, vec![Event::Started]);
+        assert_eq!(first.board.len(), 20);
+        assert_eq!(first.pieces, 0);
+        let second = read(&mut lines);
+        assert_eq!(second.pieces, 1);
+        assert_eq!(second.piece.cells.len(), 4);
+        match second.events.as_slice() {
+            [Event::Locked { lines: 0, .. }] => (),
+            events => panic!("expected a lock, got {:?}", events),
+        }
+    }
+
+    #[test]
+    fn test_slow_spectator() {
+        let mut broadcaster = Broadcaster::bind("127.0.0.1:0").unwrap();
+        let spectator = TcpStream::connect(broadcaster.local_addr().unwrap()).unwrap();
+        let sim = Simulation::new(GameMode::Marathon, &Settings::default(), 4);
+        while broadcaster.spectators() == 0 {
+            broadcaster.publish(sim.game());
+        }
+        // nothing is read until the connection is full and the spectator
+        // is dropped
+        let mut published = 1;
+        while broadcaster.spectators() > 0 {
+            broadcaster.publish(sim.game());
+            published += 1;
+            assert!(published < 1_000_000);
+        }
+        // every line that arrived is a whole frame
+        let mut frames = 0;
+        let mut reader = BufReader::new(spectator);
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap_or(0) > 0 {
+            if line.ends_with('\n') {
+                serde_json::from_str::<Frame>(&line).unwrap();
+                frames += 1;
+            }
+            line.clear();
+        }
+        assert!(frames > 0 && frames < published);
+    }
+}
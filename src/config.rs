@@ -0,0 +1,13 @@
+//! Locations of the files the game reads and writes between sessions
+use std::path::PathBuf;
+
+/// Directory for user editable configuration such as key bindings. This is
+/// the same directory ggez uses for the game's config files.
+pub fn config_dir() -> Option<PathBuf> {
+    directories::ProjectDirs::from("", "me", "tetris").map(|dirs| dirs.config_dir().to_path_buf())
+}
+
+/// Directory for files the game writes for itself, such as high scores
+pub fn data_dir() -> Option<PathBuf> {
+    directories::ProjectDirs::from("", "me", "tetris").map(|dirs| dirs.data_dir().to_path_buf())
+}
@@ -0,0 +1,218 @@
+//! Boards written as text, one line per row of the well from the top down:
+//!
+//! ```text
+//! ....zz....
+//! .....zz...
+//! G.OOIIII..
+//! GGOO.TTTJJ
+//! ```
+//!
+//! `.` is an empty cell, the letters `LOSZITJ` are cells of the stack left
+//! by that shape and `G` is garbage. The falling piece is written in lower
+//! case. Diagrams with fewer rows than the well are its bottom rows, rows
+//! above the well can be written by giving more.
+//!
+//! The I, S and Z pieces look the same after half a turn, a diagram reads
+//! them in the first of their orientations that matches.
+use std::fmt;
+
+use crate::bitboard::Board;
+use crate::tetromino::{Motion, Segment, Shape, Tetromino, GARBAGE_COLOR, GRID_SIZE};
+
+const EMPTY: char = '.';
+const GARBAGE: char = 'G';
+
+/// Why a diagram could not be read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiagramError {
+    /// A row is not as wide as the well
+    RowWidth(usize),
+    UnknownCell(char),
+    /// The falling piece is drawn with cells of different shapes
+    MixedPiece,
+    /// The cells in lower case do not form the shape they are named after
+    NotAPiece(Shape),
+}
+
+impl fmt::Display for DiagramError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DiagramError::RowWidth(width) => {
+                write!(f, "row is {} cells wide instead of {}", width, GRID_SIZE.0)
+            }
+            DiagramError::UnknownCell(c) => write!(f, "unknown cell {:?}", c),
+            DiagramError::MixedPiece => write!(f, "the piece has cells of different shapes"),
+            DiagramError::NotAPiece(shape) => {
+                write!(f, "the {:?} cells do not form a piece", shape)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DiagramError {}
+
+/// A board read from a diagram
+#[derive(Debug, Clone)]
+pub struct Diagram {
+    pub board: Vec<Segment>,
+    pub piece: Option<Tetromino>,
+}
+
+/// Offsets of cells from the top left corner of their bounding box, sorted
+fn normalized(cells: &[(i16, i16)]) -> Vec<(i16, i16)> {
+    let min_x = cells.iter().map(|&(x, _)| x).min().unwrap_or(0);
+    let min_y = cells.iter().map(|&(_, y)| y).min().unwrap_or(0);
+    let mut offsets: Vec<(i16, i16)> = cells.iter().map(|&(x, y)| (x - min_x, y - min_y)).collect();
+    offsets.sort();
+    offsets
+}
+
+/// The piece covering the given cells. Its body is built by rotating the
+/// spawned piece, so it turns around the same center as a piece that got
+/// there in a game.
+fn piece_at(shape: Shape, cells: &[(i16, i16)]) -> Result<Tetromino, DiagramError> {
+    let target = normalized(cells);
+    let mut piece = Tetromino::from(shape);
+    for _ in 0..4 {
+        let body: Vec<(i16, i16)> = piece.body.iter().map(|seg| (seg.x, seg.y)).collect();
+        if normalized(&body) == target {
+            let min = |cells: &[(i16, i16)]| {
+                (
+                    cells.iter().map(|&(x, _)| x).min().unwrap_or(0),
+                    cells.iter().map(|&(_, y)| y).min().unwrap_or(0),
+                )
+            };
+            let (from, to) = (min(&body), min(cells));
+            piece.translate(to.0 - from.0, to.1 - from.1);
+            return Ok(piece);
+        }
+        piece.move_to(Motion::RotateCw, &Board::default());
+    }
+    Err(DiagramError::NotAPiece(shape))
+}
+
+/// Read a diagram. Blank lines and whitespace around rows are ignored, so
+/// diagrams can be indented in tests.
+pub fn parse(text: &str) -> Result<Diagram, DiagramError> {
+    let rows: Vec<&str> = text
+        .lines()
+        .map(str::trim)
+        .filter(|row| !row.is_empty())
+        .collect();
+    let top = GRID_SIZE.1 - rows.len() as i16;
+    let mut board = Vec::new();
+    let mut piece_shape = None;
+    let mut piece_cells = Vec::new();
+    for (row, line) in rows.iter().enumerate() {
+        let width = line.chars().count();
+        if width != GRID_SIZE.0 as usize {
+            return Err(DiagramError::RowWidth(width));
+        }
+        let y = top + row as i16;
+        for (x, c) in line.chars().enumerate() {
+            let x = x as i16;
+            match (c, Shape::from_letter(c)) {
+                (EMPTY, _) => (),
+                (GARBAGE, _) => board.push(Segment::new((x, y), GARBAGE_COLOR)),
+                (_, Some(shape)) if c.is_ascii_uppercase() => {
+                    board.push(Segment::new((x, y), (&shape).into()))
+                }
+                (_, Some(shape)) => {
+                    if piece_shape.get_or_insert(shape) != &shape {
+                        return Err(DiagramError::MixedPiece);
+                    }
+                    piece_cells.push((x, y));
+                }
+                (_, None) => return Err(DiagramError::UnknownCell(c)),
+            }
+        }
+    }
+    let piece = match piece_shape {
+        Some(shape) => Some(piece_at(shape, &piece_cells)?),
+        None => None,
+    };
+    Ok(Diagram { board, piece })
+}
+
+/// Write a board and the falling piece as a diagram, from the highest
+/// filled row down to the floor
+pub fn print(board: &[Segment], piece: Option<&Tetromino>) -> String {
+    let piece_body: &[Segment] = match piece {
+        Some(piece) => &piece.body,
+        None => &[],
+    };
+    let top = board
+        .iter()
+        .chain(piece_body.iter())
+        .map(|seg| seg.y)
+        .min()
+        .unwrap_or(GRID_SIZE.1);
+    let mut text = String::new();
+    for y in top..GRID_SIZE.1 {
+        for x in 0..GRID_SIZE.0 {
+            let at = |seg: &&Segment| seg.x == x && seg.y == y;
+            let c = if piece_body.iter().any(|seg| at(&seg)) {
+                piece
+                    .map(|piece| piece.shape().letter().to_ascii_lowercase())
+                    .unwrap_or(EMPTY)
+            } else {
+                match board.iter().find(at) {
+                    Some(seg) => Shape::from_color(seg.color).map_or(GARBAGE, Shape::letter),
+                    None => EMPTY,
+                }
+            };
+            text.push(c);
+        }
+        text.push('\n');
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let text = "\
+            ....zz....\n\
+            .....zz...\n\
+            G.OOIIII..\n\
+            GGOO.TTTJJ\n";
+        let diagram = parse(text).unwrap();
+        assert_eq!(diagram.board.len(), 16);
+        let piece = diagram.piece.as_ref().unwrap();
+        assert_eq!(piece.shape(), Shape::Z);
+        assert!(piece.body.iter().all(|seg| seg.y == 16 || seg.y == 17));
+        assert_eq!(print(&diagram.board, diagram.piece.as_ref()), text);
+        assert_eq!(print(&[], None), "");
+    }
+
+    #[test]
+    fn test_every_rotation() {
+        for &shape in Shape::ALL.iter() {
+            let mut piece = Tetromino::from(shape);
+            piece.translate(0, 5);
+            for _ in 0..4 {
+                let text = print(&[], Some(&piece));
+                let parsed = parse(&text).unwrap().piece.unwrap();
+                assert_eq!(print(&[], Some(&parsed)), text);
+                piece.move_to(Motion::RotateCw, &Board::default());
+            }
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(parse("....").unwrap_err(), DiagramError::RowWidth(4));
+        assert_eq!(
+            parse("....x.....").unwrap_err(),
+            DiagramError::UnknownCell('x')
+        );
+        assert_eq!(parse("..ll.t....").unwrap_err(), DiagramError::MixedPiece);
+        assert_eq!(
+            parse("..tttt....").unwrap_err(),
+            DiagramError::NotAPiece(Shape::T)
+        );
+    }
+}
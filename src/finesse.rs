@@ -0,0 +1,168 @@
+//! Finesse: placing every piece with as few key presses as possible. The
+//! game counts the presses that moved each piece and compares them with
+//! the fewest that reach the same place, found by `movegen`. Every press
+//! over that is a fault.
+use serde_derive::{Deserialize, Serialize};
+
+use crate::game_state::GameState;
+use crate::movegen::fewest_presses;
+use crate::tetromino::{Shape, Tetromino};
+
+/// What the game does when a piece is placed with a fault
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Trainer {
+    /// Only count the faults
+    #[default]
+    Off,
+    /// Flash the well
+    Flash,
+    /// Flash the well and give the piece back to place it again
+    Retry,
+}
+
+impl Trainer {
+    pub const ALL: [Trainer; 3] = [Trainer::Off, Trainer::Flash, Trainer::Retry];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Trainer::Off => "Off",
+            Trainer::Flash => "Flash",
+            Trainer::Retry => "Retry",
+        }
+    }
+}
+
+/// How a piece was placed, compared with the fewest key presses needed.
+/// Hard drops are not counted, a piece can also lock on its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Finesse {
+    pub shape: Shape,
+    pub presses: u32,
+    pub fewest: u32,
+}
+
+impl Finesse {
+    /// Key presses that were not needed
+    pub fn faults(&self) -> u32 {
+        self.presses.saturating_sub(self.fewest)
+    }
+}
+
+/// Judge the placement of a piece that is about to lock where `piece` is,
+/// moved with `presses` key presses since it spawned. A piece placed
+/// without pressing anything cannot have wasted a press, so the search is
+/// skipped. There is no judgement for places only gravity can take a piece
+/// to, by moving it while it falls.
+pub fn check(game: &GameState, piece: &Tetromino, presses: u32) -> Option<Finesse> {
+    let shape = piece.shape();
+    if presses == 0 {
+        return Some(Finesse {
+            shape,
+            presses,
+            fewest: 0,
+        });
+    }
+    let mut target: Vec<(i16, i16)> = piece.body.iter().map(|seg| (seg.x, seg.y)).collect();
+    target.sort();
+    fewest_presses(game, shape)
+        .iter()
+        .filter(|placement| {
+            let mut cells: Vec<(i16, i16)> = placement
+                .piece
+                .body
+                .iter()
+                .map(|seg| (seg.x, seg.y))
+                .collect();
+            cells.sort();
+            cells == target
+        })
+        .map(|placement| placement.path.len() as u32 - 1)
+        .min()
+        .map(|fewest| Finesse {
+            shape,
+            presses,
+            fewest,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action::Action;
+    use crate::game_state::{GameMode, Setup};
+    use crate::settings::Settings;
+    use crate::simulation::{Input, Simulation};
+
+    /// Play the inputs on a piece of the shape in an empty well, with the
+    /// given trainer, until it is locked or given back
+    fn play(shape: Shape, trainer: Trainer, actions: &[Action]) -> Simulation {
+        let setup = Setup {
+            board: Vec::new(),
+            hold: None,
+            queue: vec![shape, Shape::O],
+        };
+        let settings = Settings {
+            finesse_trainer: trainer,
+            ..Settings::default()
+        };
+        let mut sim = Simulation::with_setup(GameMode::Sandbox, &settings, 1, &setup);
+        while !crate::bot::ready(sim.game()) {
+            sim.step();
+        }
+        for &action in actions {
+            sim.apply(Input::Press(action));
+            sim.apply(Input::Release(action));
+            sim.step();
+        }
+        sim
+    }
+
+    #[test]
+    fn test_faults() {
+        // two rotations instead of one
+        let sim = play(
+            Shape::T,
+            Trainer::Off,
+            &[Action::RotateCw, Action::RotateCw, Action::HardDrop],
+        );
+        let finesse = sim.game().last_finesse().unwrap();
+        assert_eq!(
+            (finesse.presses, finesse.fewest, finesse.faults()),
+            (2, 1, 1)
+        );
+        assert_eq!(sim.game().stats().finesse_faults, 1);
+        assert_eq!(sim.game().stats().pieces, 1);
+
+        let sim = play(
+            Shape::T,
+            Trainer::Off,
+            &[Action::Rotate180, Action::HardDrop],
+        );
+        assert_eq!(sim.game().last_finesse().unwrap().faults(), 0);
+
+        // left and back right, where nothing was needed at all
+        let sim = play(
+            Shape::T,
+            Trainer::Off,
+            &[Action::MoveLeft, Action::MoveRight, Action::HardDrop],
+        );
+        let finesse = sim.game().last_finesse().unwrap();
+        assert_eq!((finesse.presses, finesse.fewest), (2, 0));
+    }
+
+    #[test]
+    fn test_trainer() {
+        let actions = [Action::RotateCw, Action::RotateCcw, Action::HardDrop];
+        let sim = play(Shape::T, Trainer::Flash, &actions);
+        assert!(sim.game().flashing());
+        assert_eq!(sim.game().stats().pieces, 1);
+
+        let sim = play(Shape::T, Trainer::Retry, &actions);
+        assert!(sim.game().flashing());
+        assert_eq!(sim.game().stats().pieces, 0);
+        assert_eq!(sim.game().stats().finesse_faults, 2);
+        assert_eq!(sim.game().current().shape(), Shape::T);
+        assert!(sim.game().base().is_empty());
+    }
+}
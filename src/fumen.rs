@@ -0,0 +1,925 @@
+//! Reading and writing fumen, the format boards and setups are shared in.
+//!
+//! A fumen string such as `v115@bhI8KeAgH` is a list of pages. Every page
+//! holds a field of 10 columns by 23 rows plus a garbage row below the
+//! floor, optionally a piece placed on it, and a comment. The data is
+//! written with a base64 alphabet as little endian numbers:
+//!
+//! - the field as runs of cells that changed by the same amount since the
+//!   previous page, two characters per run, with one extra character that
+//!   repeats an unchanged field for the following pages
+//! - the piece and page flags in three characters
+//! - the comment, if it changed, as its length followed by groups of four
+//!   characters packed into five
+//!
+//! Queues are carried in comments with the quiz syntax `#Q=[H](C)NEXT`,
+//! where `H` is the held piece, `C` the current one and `NEXT` the rest.
+use std::fmt;
+
+use crate::game_state::Setup;
+use crate::tetromino::{Segment, Shape, GARBAGE_COLOR, GRID_SIZE};
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const VERSION_PREFIXES: [&str; 3] = ["v115@", "m115@", "d115@"];
+
+pub const FIELD_WIDTH: usize = 10;
+/// Rows above the garbage row
+pub const FIELD_HEIGHT: usize = 23;
+/// Cells of the field including the garbage row
+const FIELD_CELLS: usize = FIELD_WIDTH * (FIELD_HEIGHT + 1);
+
+/// Characters that can appear in comments, after escaping
+const COMMENT_CHARS: u32 = 96;
+const MAX_COMMENT_LENGTH: usize = 4095;
+
+/// Why a fumen string could not be read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FumenError {
+    UnknownVersion,
+    InvalidCharacter(char),
+    UnexpectedEnd,
+    /// The runs of a page add up to more cells than the field has
+    FieldOverflow,
+    UnknownPiece(u32),
+    /// A cell is filled above the top of the well
+    BoardTooTall,
+}
+
+impl fmt::Display for FumenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FumenError::UnknownVersion => write!(f, "not a v115 fumen"),
+            FumenError::InvalidCharacter(c) => write!(f, "invalid character {:?}", c),
+            FumenError::UnexpectedEnd => write!(f, "fumen ends in the middle of a page"),
+            FumenError::FieldOverflow => write!(f, "field data does not fit the field"),
+            FumenError::UnknownPiece(n) => write!(f, "unknown piece {}", n),
+            FumenError::BoardTooTall => write!(
+                f,
+                "board is taller than the {} rows of the well",
+                GRID_SIZE.1
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FumenError {}
+
+/// Content of a field cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cell {
+    Empty,
+    Block(Shape),
+    Garbage,
+}
+
+impl Cell {
+    fn to_number(self) -> u32 {
+        match self {
+            Cell::Empty => 0,
+            Cell::Block(shape) => shape_to_number(shape),
+            Cell::Garbage => 8,
+        }
+    }
+
+    fn from_number(n: u32) -> Result<Cell, FumenError> {
+        match n {
+            0 => Ok(Cell::Empty),
+            8 => Ok(Cell::Garbage),
+            _ => number_to_shape(n).map(Cell::Block),
+        }
+    }
+}
+
+fn shape_to_number(shape: Shape) -> u32 {
+    match shape {
+        Shape::I => 1,
+        Shape::L => 2,
+        Shape::O => 3,
+        Shape::Z => 4,
+        Shape::T => 5,
+        Shape::J => 6,
+        Shape::S => 7,
+    }
+}
+
+fn number_to_shape(n: u32) -> Result<Shape, FumenError> {
+    match n {
+        1 => Ok(Shape::I),
+        2 => Ok(Shape::L),
+        3 => Ok(Shape::O),
+        4 => Ok(Shape::Z),
+        5 => Ok(Shape::T),
+        6 => Ok(Shape::J),
+        7 => Ok(Shape::S),
+        _ => Err(FumenError::UnknownPiece(n)),
+    }
+}
+
+/// The field of a page. Rows are counted from the floor up, row -1 is the
+/// garbage row below the floor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    /// Cells in the order they are written: top row first, garbage row last
+    cells: Vec<Cell>,
+}
+
+impl Default for Field {
+    fn default() -> Self {
+        Self {
+            cells: vec![Cell::Empty; FIELD_CELLS],
+        }
+    }
+}
+
+impl Field {
+    fn index(x: usize, y: i32) -> usize {
+        (FIELD_HEIGHT as i32 - 1 - y) as usize * FIELD_WIDTH + x
+    }
+
+    pub fn get(&self, x: usize, y: i32) -> Cell {
+        self.cells[Self::index(x, y)]
+    }
+
+    pub fn set(&mut self, x: usize, y: i32, cell: Cell) {
+        self.cells[Self::index(x, y)] = cell;
+    }
+
+    fn row_full(&self, y: i32) -> bool {
+        (0..FIELD_WIDTH).all(|x| self.get(x, y) != Cell::Empty)
+    }
+
+    /// Put the blocks of a piece into the field, blocks outside are dropped
+    fn fill(&mut self, piece: &Piece) {
+        for (x, y) in piece.blocks().iter().cloned() {
+            if x >= 0 && (x as usize) < FIELD_WIDTH && y >= 0 && y < FIELD_HEIGHT as i32 {
+                self.set(x as usize, y, Cell::Block(piece.shape));
+            }
+        }
+    }
+
+    /// Remove full rows above the floor and let the rest fall
+    fn clear_lines(&mut self) {
+        let mut target = 0;
+        for y in 0..FIELD_HEIGHT as i32 {
+            if self.row_full(y) {
+                continue;
+            }
+            if target != y {
+                for x in 0..FIELD_WIDTH {
+                    let cell = self.get(x, y);
+                    self.set(x, target, cell);
+                }
+            }
+            target += 1;
+        }
+        for y in target..FIELD_HEIGHT as i32 {
+            for x in 0..FIELD_WIDTH {
+                self.set(x, y, Cell::Empty);
+            }
+        }
+    }
+
+    /// Push the field up by a row, the garbage row becomes the bottom row
+    fn rise(&mut self) {
+        for y in (0..FIELD_HEIGHT as i32).rev() {
+            for x in 0..FIELD_WIDTH {
+                let cell = self.get(x, y - 1);
+                self.set(x, y, cell);
+            }
+        }
+        for x in 0..FIELD_WIDTH {
+            self.set(x, -1, Cell::Empty);
+        }
+    }
+
+    fn mirror(&mut self) {
+        for y in 0..FIELD_HEIGHT as i32 {
+            for x in 0..FIELD_WIDTH / 2 {
+                let (left, right) = (self.get(x, y), self.get(FIELD_WIDTH - 1 - x, y));
+                self.set(x, y, right);
+                self.set(FIELD_WIDTH - 1 - x, y, left);
+            }
+        }
+    }
+
+    /// The stack of the well as a field, taking the shape of every block
+    /// from its color
+    pub fn from_board(board: &[Segment]) -> Self {
+        let mut field = Field::default();
+        for seg in board {
+            let y = GRID_SIZE.1 - 1 - seg.y;
+            if seg.x < 0 || seg.x as usize >= FIELD_WIDTH || y < 0 {
+                continue;
+            }
+            let cell = Shape::from_color(seg.color).map_or(Cell::Garbage, Cell::Block);
+            field.set(seg.x as usize, i32::from(y), cell);
+        }
+        field
+    }
+
+    /// The field as the stack of the well. Fails if anything is above the
+    /// top of the well, the garbage row is left out.
+    pub fn to_board(&self) -> Result<Vec<Segment>, FumenError> {
+        let mut board = Vec::new();
+        for y in 0..FIELD_HEIGHT as i32 {
+            for x in 0..FIELD_WIDTH {
+                let color = match self.get(x, y) {
+                    Cell::Empty => continue,
+                    Cell::Block(shape) => (&shape).into(),
+                    Cell::Garbage => GARBAGE_COLOR,
+                };
+                if y >= i32::from(GRID_SIZE.1) {
+                    return Err(FumenError::BoardTooTall);
+                }
+                board.push(Segment::new((x as i16, GRID_SIZE.1 - 1 - y as i16), color));
+            }
+        }
+        Ok(board)
+    }
+}
+
+/// Orientation of a piece, named as in fumen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    Spawn,
+    Right,
+    Reverse,
+    Left,
+}
+
+impl Rotation {
+    fn to_number(self) -> u32 {
+        match self {
+            Rotation::Reverse => 0,
+            Rotation::Right => 1,
+            Rotation::Spawn => 2,
+            Rotation::Left => 3,
+        }
+    }
+
+    fn from_number(n: u32) -> Rotation {
+        match n % 4 {
+            0 => Rotation::Reverse,
+            1 => Rotation::Right,
+            2 => Rotation::Spawn,
+            _ => Rotation::Left,
+        }
+    }
+}
+
+/// A piece on a page, at the position of its rotation center with y
+/// counted from the floor up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Piece {
+    pub shape: Shape,
+    pub rotation: Rotation,
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Piece {
+    /// Cells covered by the piece
+    pub fn blocks(&self) -> [(i32, i32); 4] {
+        let spawn = match self.shape {
+            Shape::I => [(0, 0), (-1, 0), (1, 0), (2, 0)],
+            Shape::T => [(0, 0), (-1, 0), (1, 0), (0, 1)],
+            Shape::O => [(0, 0), (1, 0), (0, 1), (1, 1)],
+            Shape::L => [(0, 0), (-1, 0), (1, 0), (1, 1)],
+            Shape::J => [(0, 0), (-1, 0), (1, 0), (-1, 1)],
+            Shape::S => [(0, 0), (-1, 0), (0, 1), (1, 1)],
+            Shape::Z => [(0, 0), (1, 0), (0, 1), (-1, 1)],
+        };
+        let mut blocks = [(0, 0); 4];
+        for (block, &(dx, dy)) in blocks.iter_mut().zip(spawn.iter()) {
+            let (dx, dy) = match self.rotation {
+                Rotation::Spawn => (dx, dy),
+                Rotation::Right => (dy, -dx),
+                Rotation::Reverse => (-dx, -dy),
+                Rotation::Left => (-dy, dx),
+            };
+            *block = (self.x + dx, self.y + dy);
+        }
+        blocks
+    }
+
+    /// Fumen stores some pieces relative to a different cell than their
+    /// rotation center, this is the offset from the stored cell to it
+    fn center_offset(shape: Shape, rotation: Rotation) -> (i32, i32) {
+        match (shape, rotation) {
+            (Shape::O, Rotation::Left) => (1, -1),
+            (Shape::O, Rotation::Reverse) => (1, 0),
+            (Shape::O, Rotation::Spawn) => (0, -1),
+            (Shape::I, Rotation::Reverse) => (1, 0),
+            (Shape::I, Rotation::Left) => (0, -1),
+            (Shape::S, Rotation::Spawn) => (0, -1),
+            (Shape::S, Rotation::Right) => (-1, 0),
+            (Shape::Z, Rotation::Spawn) => (0, -1),
+            (Shape::Z, Rotation::Left) => (1, 0),
+            _ => (0, 0),
+        }
+    }
+}
+
+/// One page of a fumen
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Page {
+    pub field: Field,
+    pub piece: Option<Piece>,
+    pub comment: String,
+    /// Place the piece and clear lines before the next page
+    pub lock: bool,
+    /// Raise the garbage row into the field before the next page
+    pub rise: bool,
+    /// Mirror the field before the next page
+    pub mirror: bool,
+}
+
+impl Page {
+    pub fn new(field: Field) -> Self {
+        Self {
+            field,
+            piece: None,
+            comment: String::new(),
+            lock: true,
+            rise: false,
+            mirror: false,
+        }
+    }
+
+    /// The field the next page starts from
+    fn next_field(&self) -> Field {
+        let mut field = self.field.clone();
+        if self.lock {
+            if let Some(ref piece) = self.piece {
+                field.fill(piece);
+            }
+            field.clear_lines();
+            if self.rise {
+                field.rise();
+            }
+            if self.mirror {
+                field.mirror();
+            }
+        }
+        field
+    }
+}
+
+fn push_value(out: &mut Vec<u32>, mut value: u32, count: usize) {
+    for _ in 0..count {
+        out.push(value % 64);
+        value /= 64;
+    }
+}
+
+/// Encode pages into a fumen string
+pub fn encode(pages: &[Page]) -> String {
+    let mut values = Vec::new();
+    let mut prev_field = Field::default();
+    let mut prev_comment = String::new();
+    // index of the repeat count of the last unchanged field, if it can
+    // still be increased
+    let mut repeat: Option<usize> = None;
+
+    for (index, page) in pages.iter().enumerate() {
+        // field
+        let diffs: Vec<u32> = page
+            .field
+            .cells
+            .iter()
+            .zip(prev_field.cells.iter())
+            .map(|(cell, prev)| cell.to_number() + 8 - prev.to_number())
+            .collect();
+        let unchanged = diffs.iter().all(|&diff| diff == 8);
+        match repeat {
+            Some(at) if unchanged && values[at] < 63 => values[at] += 1,
+            _ => {
+                let mut start = 0;
+                while start < diffs.len() {
+                    let diff = diffs[start];
+                    let mut end = start + 1;
+                    while end < diffs.len() && diffs[end] == diff {
+                        end += 1;
+                    }
+                    let run = (end - start) as u32;
+                    push_value(&mut values, diff * FIELD_CELLS as u32 + run - 1, 2);
+                    start = end;
+                }
+                repeat = if unchanged {
+                    values.push(0);
+                    Some(values.len() - 1)
+                } else {
+                    None
+                };
+            }
+        }
+
+        // piece and flags
+        let (shape, rotation, position) = match page.piece {
+            Some(piece) => {
+                let (dx, dy) = Piece::center_offset(piece.shape, piece.rotation);
+                let (x, y) = (piece.x - dx, piece.y - dy);
+                let position = (FIELD_HEIGHT as i32 - 1 - y) * FIELD_WIDTH as i32 + x;
+                (
+                    shape_to_number(piece.shape),
+                    piece.rotation.to_number(),
+                    position as u32,
+                )
+            }
+            None => (0, Rotation::Reverse.to_number(), 0),
+        };
+        let comment = escape(&page.comment);
+        let comment_changed = page.comment != prev_comment;
+        let flags = [
+            !page.lock,
+            comment_changed,
+            // guideline colors, only read from the first page
+            index == 0,
+            page.mirror,
+            page.rise,
+        ];
+        let flags = flags
+            .iter()
+            .fold(0, |value, &flag| value * 2 + u32::from(flag));
+        let action = ((flags * FIELD_CELLS as u32 + position) * 4 + rotation) * 8 + shape;
+        push_value(&mut values, action, 3);
+
+        // comment
+        if comment_changed {
+            let chars: Vec<u32> = comment
+                .bytes()
+                .take(MAX_COMMENT_LENGTH)
+                .map(|b| u32::from(b) - 32)
+                .collect();
+            push_value(&mut values, chars.len() as u32, 2);
+            for group in chars.chunks(4) {
+                let value = group
+                    .iter()
+                    .rev()
+                    .fold(0, |value, &c| value * COMMENT_CHARS + c);
+                push_value(&mut values, value, 5);
+            }
+            prev_comment = page.comment.clone();
+        }
+
+        prev_field = page.next_field();
+    }
+
+    let data: String = values.iter().map(|&v| BASE64[v as usize] as char).collect();
+    // fumen breaks the data up with a '?' after 42 characters and every
+    // 47 after that
+    let mut out = String::from(VERSION_PREFIXES[0]);
+    for (i, c) in data.chars().enumerate() {
+        if i >= 42 && (i - 42) % 47 == 0 {
+            out.push('?');
+        }
+        out.push(c);
+    }
+    out
+}
+
+struct Reader {
+    values: Vec<u32>,
+    pos: usize,
+}
+
+impl Reader {
+    fn poll(&mut self, count: usize) -> Result<u32, FumenError> {
+        let mut value = 0;
+        for i in 0..count {
+            let v = *self
+                .values
+                .get(self.pos + i)
+                .ok_or(FumenError::UnexpectedEnd)?;
+            value += v << (6 * i);
+        }
+        self.pos += count;
+        Ok(value)
+    }
+
+    fn is_end(&self) -> bool {
+        self.pos >= self.values.len()
+    }
+}
+
+/// Decode a fumen string. Anything before the version, such as the address
+/// of the fumen site, is skipped.
+pub fn decode(text: &str) -> Result<Vec<Page>, FumenError> {
+    let text = text.trim();
+    let data = VERSION_PREFIXES
+        .iter()
+        .filter_map(|prefix| text.find(prefix).map(|at| &text[at + prefix.len()..]))
+        .next()
+        .ok_or(FumenError::UnknownVersion)?;
+    let values = data
+        .chars()
+        .filter(|&c| c != '?')
+        .map(|c| {
+            BASE64
+                .iter()
+                .position(|&b| b as char == c)
+                .map(|v| v as u32)
+                .ok_or(FumenError::InvalidCharacter(c))
+        })
+        .collect::<Result<Vec<u32>, FumenError>>()?;
+    let mut reader = Reader { values, pos: 0 };
+
+    let mut pages = Vec::new();
+    let mut prev_field = Field::default();
+    let mut prev_comment = String::new();
+    let mut repeat = 0;
+    while !reader.is_end() {
+        // field
+        let mut field = prev_field.clone();
+        if repeat > 0 {
+            repeat -= 1;
+        } else {
+            let mut index = 0;
+            let mut unchanged = false;
+            while index < FIELD_CELLS {
+                let value = reader.poll(2)?;
+                let diff = value / FIELD_CELLS as u32;
+                let run = value % FIELD_CELLS as u32 + 1;
+                if diff == 8 && run == FIELD_CELLS as u32 {
+                    unchanged = true;
+                }
+                for _ in 0..run {
+                    let cell = field
+                        .cells
+                        .get_mut(index)
+                        .ok_or(FumenError::FieldOverflow)?;
+                    *cell = Cell::from_number((cell.to_number() + diff).wrapping_sub(8))?;
+                    index += 1;
+                }
+            }
+            if unchanged {
+                repeat = reader.poll(1)?;
+            }
+        }
+
+        // piece and flags
+        let mut action = reader.poll(3)?;
+        let shape = action % 8;
+        action /= 8;
+        let rotation = Rotation::from_number(action % 4);
+        action /= 4;
+        let position = (action % FIELD_CELLS as u32) as i32;
+        action /= FIELD_CELLS as u32;
+        let rise = action % 2 == 1;
+        let mirror = (action >> 1) % 2 == 1;
+        let comment_changed = (action >> 3) % 2 == 1;
+        let lock = (action >> 4) % 2 == 0;
+        let piece = if shape == 0 {
+            None
+        } else {
+            let shape = number_to_shape(shape)?;
+            let (dx, dy) = Piece::center_offset(shape, rotation);
+            Some(Piece {
+                shape,
+                rotation,
+                x: position % FIELD_WIDTH as i32 + dx,
+                y: FIELD_HEIGHT as i32 - 1 - position / FIELD_WIDTH as i32 + dy,
+            })
+        };
+
+        // comment
+        if comment_changed {
+            let length = reader.poll(2)? as usize;
+            let mut escaped = String::new();
+            while escaped.len() < length {
+                let mut value = reader.poll(5)?;
+                for _ in 0..4 {
+                    escaped.push((value % COMMENT_CHARS + 32) as u8 as char);
+                    value /= COMMENT_CHARS;
+                }
+            }
+            escaped.truncate(length);
+            prev_comment = unescape(&escaped);
+        }
+
+        let page = Page {
+            field,
+            piece,
+            comment: prev_comment.clone(),
+            lock,
+            rise,
+            mirror,
+        };
+        prev_field = page.next_field();
+        pages.push(page);
+    }
+    Ok(pages)
+}
+
+/// Escape a comment the way JavaScript's `escape` does, which is how fumen
+/// stores comments
+fn escape(text: &str) -> String {
+    let mut out = String::new();
+    for c in text.chars() {
+        match c {
+            'A'..='Z' | 'a'..='z' | '0'..='9' | '@' | '*' | '_' | '+' | '-' | '.' | '/' => {
+                out.push(c)
+            }
+            _ if (c as u32) < 256 => out.push_str(&format!("%{:02X}", c as u32)),
+            _ => {
+                let mut units = [0; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    out.push_str(&format!("%u{:04X}", unit));
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Undo `escape`. Malformed escapes are kept as they are.
+fn unescape(text: &str) -> String {
+    let mut units: Vec<u16> = Vec::new();
+    let chars: Vec<char> = text.chars().collect();
+    let hex = |digits: &[char]| -> Option<u16> {
+        let digits: String = digits.iter().collect();
+        u16::from_str_radix(&digits, 16).ok()
+    };
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] == '%' {
+            if chars.get(i + 1) == Some(&'u') && i + 6 <= chars.len() {
+                if let Some(unit) = hex(&chars[i + 2..i + 6]) {
+                    units.push(unit);
+                    i += 6;
+                    continue;
+                }
+            } else if i + 3 <= chars.len() {
+                if let Some(unit) = hex(&chars[i + 1..i + 3]) {
+                    units.push(unit);
+                    i += 3;
+                    continue;
+                }
+            }
+        }
+        let mut buf = [0; 2];
+        units.extend_from_slice(chars[i].encode_utf16(&mut buf));
+        i += 1;
+    }
+    String::from_utf16_lossy(&units)
+}
+
+/// The hold and queue of a quiz comment, `#Q=[H](C)NEXT`. The current
+/// piece comes first in the returned queue.
+fn parse_quiz(comment: &str) -> Option<(Option<Shape>, Vec<Shape>)> {
+    let comment = comment.trim();
+    if !comment.starts_with("#Q=[") {
+        return None;
+    }
+    let quiz = &comment[4..];
+    let hold_end = quiz.find(']')?;
+    let hold = quiz[..hold_end].chars().next().and_then(Shape::from_letter);
+    let rest = &quiz[hold_end + 1..];
+    if !rest.starts_with('(') {
+        return None;
+    }
+    let current_end = rest.find(')')?;
+    let queue = rest[1..current_end]
+        .chars()
+        .chain(rest[current_end + 1..].chars())
+        .take_while(|&c| Shape::from_letter(c).is_some())
+        .filter_map(Shape::from_letter)
+        .collect();
+    Some((hold, queue))
+}
+
+/// Read the starting position of a game from the first page of a fumen:
+/// the field becomes the stack, a quiz comment gives the hold and queue
+pub fn decode_setup(text: &str) -> Result<Setup, FumenError> {
+    let pages = decode(text)?;
+    let page = pages.first().ok_or(FumenError::UnexpectedEnd)?;
+    let (hold, queue) = parse_quiz(&page.comment).unwrap_or((None, Vec::new()));
+    Ok(Setup {
+        board: page.field.to_board()?,
+        hold,
+        queue,
+    })
+}
+
+/// Write a position as a single page fumen, with the hold and queue as a
+/// quiz comment
+pub fn encode_setup(setup: &Setup) -> String {
+    let mut page = Page::new(Field::from_board(&setup.board));
+    let mut queue = setup.queue.iter().map(|&shape| shape.letter());
+    if let Some(current) = queue.next() {
+        page.comment = format!(
+            "#Q=[{}]({}){}",
+            setup
+                .hold
+                .map(Shape::letter)
+                .map(String::from)
+                .unwrap_or_default(),
+            current,
+            queue.collect::<String>()
+        );
+    }
+    encode(&[page])
+}
+
+/// Write boards as the pages of a fumen, e.g. the stack after every piece
+/// of a game
+pub fn encode_boards(boards: &[Vec<Segment>]) -> String {
+    let pages: Vec<Page> = boards
+        .iter()
+        .map(|board| Page::new(Field::from_board(board)))
+        .collect();
+    encode(&pages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty() {
+        let pages = decode("v115@vhAAgH").unwrap();
+        assert_eq!(pages, vec![Page::new(Field::default())]);
+        assert_eq!(encode(&pages), "v115@vhAAgH");
+        // an unchanged field is repeated instead of written again
+        assert_eq!(
+            encode(&[pages[0].clone(), pages[0].clone()]),
+            "v115@vhBAgHAAA"
+        );
+    }
+
+    #[test]
+    fn test_known_field() {
+        // the bottom row filled with garbage except for the last column
+        let text = "v115@bhI8KeAgH";
+        let pages = decode(text).unwrap();
+        assert_eq!(pages.len(), 1);
+        let field = &pages[0].field;
+        assert!((0..9).all(|x| field.get(x, 0) == Cell::Garbage));
+        assert_eq!(field.get(9, 0), Cell::Empty);
+        assert_eq!(field.get(0, 1), Cell::Empty);
+        assert_eq!(encode(&pages), text);
+    }
+
+    /// Fumens of a piece placed on the floor in all four orientations, one
+    /// page each, then a page without a piece showing the stack they left
+    const ROTATION_FIXTURES: [(Shape, &str, &str); 5] = [
+        (
+            Shape::T,
+            "v115@vhE1OJtqBFsB9tBAAA",
+            "...T.....T\n.T.TTTTTTT\nTTTT..T..T\n",
+        ),
+        (
+            Shape::L,
+            "v115@vhEyOJqqBCsB6tBAAA",
+            "...L....LL\n..LL.LLL.L\nLLLLLL...L\n",
+        ),
+        (
+            Shape::J,
+            "v115@vhE2OJuqBGsB+tBAAA",
+            "...JJ....J\nJ..J.JJJ.J\nJJJJ...JJJ\n",
+        ),
+        (
+            Shape::S,
+            "v115@vhE3JJPrBHsB/tBAAA",
+            "...S....S.\n.SSSS.SSSS\nSS..SSS..S\n",
+        ),
+        (
+            Shape::Z,
+            "v115@vhE0JJsqBEsBctBAAA",
+            "....Z....Z\nZZ.ZZZZ.ZZ\n.ZZZ..ZZZ.\n",
+        ),
+    ];
+
+    const ROTATIONS: [Rotation; 4] = [
+        Rotation::Spawn,
+        Rotation::Right,
+        Rotation::Reverse,
+        Rotation::Left,
+    ];
+
+    fn print_field(field: &Field) -> String {
+        crate::diagram::print(&field.to_board().unwrap(), None)
+    }
+
+    #[test]
+    fn test_decode_rotations() {
+        for &(shape, text, stack) in ROTATION_FIXTURES.iter() {
+            let pages = decode(text).unwrap();
+            assert_eq!(pages.len(), 5, "{}", text);
+            let rotations: Vec<Rotation> = pages
+                .iter()
+                .filter_map(|page| page.piece)
+                .map(|piece| {
+                    assert_eq!(piece.shape, shape);
+                    piece.rotation
+                })
+                .collect();
+            assert_eq!(rotations, ROTATIONS.to_vec(), "{}", text);
+            // every page starts from the stack the pieces before it left
+            assert_eq!(print_field(&pages[0].field), "");
+            assert_eq!(print_field(&pages[4].field), stack, "{}", text);
+            assert_eq!(pages[4].piece, None);
+        }
+    }
+
+    #[test]
+    fn test_encode_rotations() {
+        for &(shape, text, _) in ROTATION_FIXTURES.iter() {
+            // left to right: flat, standing, upside down, standing
+            let centers = [(1, 0), (3, 1), (6, 1), (9, 1)];
+            let mut pages: Vec<Page> = Vec::new();
+            for (&rotation, &(x, y)) in ROTATIONS.iter().zip(centers.iter()) {
+                let field = pages.last().map_or_else(Field::default, Page::next_field);
+                let mut page = Page::new(field);
+                page.piece = Some(Piece {
+                    shape,
+                    rotation,
+                    x,
+                    y,
+                });
+                pages.push(page);
+            }
+            let last = pages.last().unwrap().next_field();
+            pages.push(Page::new(last));
+            assert_eq!(encode(&pages), text, "{:?}", shape);
+        }
+    }
+
+    #[test]
+    fn test_multi_row_field() {
+        // four rows of garbage with a four wide well on the right
+        let text = "v115@9gF8DeF8DeF8DeF8NeAgH";
+        let pages = decode(text).unwrap();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(print_field(&pages[0].field), "GGGGGG....\n".repeat(4));
+        assert_eq!(encode(&pages), text);
+    }
+
+    #[test]
+    fn test_pieces_and_line_clears() {
+        // an I laid flat in the gap of a full row clears it on the next page
+        let mut field = Field::default();
+        for x in 0..6 {
+            field.set(x, 0, Cell::Garbage);
+        }
+        let mut first = Page::new(field);
+        first.piece = Some(Piece {
+            shape: Shape::I,
+            rotation: Rotation::Spawn,
+            x: 7,
+            y: 0,
+        });
+        let second = Page::new(Field::default());
+        let text = encode(&[first.clone(), second.clone()]);
+        assert_eq!(decode(&text).unwrap(), vec![first, second]);
+
+        // every piece in every rotation survives a round trip
+        for &shape in Shape::ALL.iter() {
+            for &rotation in [
+                Rotation::Spawn,
+                Rotation::Right,
+                Rotation::Reverse,
+                Rotation::Left,
+            ]
+            .iter()
+            {
+                let mut page = Page::new(Field::default());
+                page.lock = false;
+                page.piece = Some(Piece {
+                    shape,
+                    rotation,
+                    x: 4,
+                    y: 10,
+                });
+                let decoded = decode(&encode(&[page.clone()])).unwrap();
+                assert_eq!(decoded, vec![page]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_comments() {
+        let mut page = Page::new(Field::default());
+        page.comment = "#Q=[](T)SZ héllo".to_string();
+        let text = encode(&[page.clone(), page.clone()]);
+        assert_eq!(decode(&text).unwrap(), vec![page.clone(), page]);
+        assert_eq!(escape("#Q=[](T)"), "%23Q%3D%5B%5D%28T%29");
+        assert_eq!(unescape("%u3042%"), "\u{3042}%");
+    }
+
+    #[test]
+    fn test_setup() {
+        let setup = Setup {
+            board: vec![
+                Segment::new((0, GRID_SIZE.1 - 1), (&Shape::T).into()),
+                Segment::new((1, GRID_SIZE.1 - 1), GARBAGE_COLOR),
+            ],
+            hold: Some(Shape::I),
+            queue: vec![Shape::T, Shape::S, Shape::Z],
+        };
+        let text = encode_setup(&setup);
+        assert_eq!(decode_setup(&text).unwrap(), setup);
+        let decoded = decode(&text).unwrap();
+        assert_eq!(decoded[0].comment, "#Q=[I](T)SZ");
+    }
+}
@@ -1,86 +1,418 @@
-use ggez::event::{KeyCode, KeyMods};
-
-use ggez::{event, graphics, Context, GameResult};
-use rand::Rng;
-use std::collections::{HashSet, VecDeque};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt;
 
+use crate::action::Action;
+use crate::bitboard::Board;
+use crate::diagram;
+use crate::finesse::{self, Finesse, Trainer};
+use crate::garbage::{Clear, GarbageQueue};
+use crate::movegen::Placement;
+use crate::randomizer::Randomizer;
+use crate::settings::Settings;
+use crate::stats::{GameEvent, Stats};
 use crate::tetromino::*;
-use std::time::{Duration, Instant};
+use std::time::Duration;
+
+/// Game modes selectable from the mode select menu
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GameMode {
+    /// Endless game, speed increases with the score
+    Marathon,
+    /// Clear 40 lines as fast as possible
+    Sprint,
+    /// Score as many points as possible in two minutes
+    Ultra,
+    /// Endless game from a position set up beforehand, without high scores
+    Sandbox,
+    /// Two players on their own boards, each sending garbage to the other
+    /// until one tops out
+    Versus,
+}
+
+pub const SPRINT_LINES: u32 = 40;
+pub const ULTRA_TIME: Duration = Duration::from_secs(120);
+
+impl GameMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            GameMode::Marathon => "Marathon",
+            GameMode::Sprint => "Sprint",
+            GameMode::Ultra => "Ultra",
+            GameMode::Sandbox => "Sandbox",
+            GameMode::Versus => "Versus",
+        }
+    }
+}
+
+/// How the pieces of a game are dealt. Scores are kept separately for
+/// each ruleset since bags make the game a lot more predictable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Ruleset {
+    /// Every piece is picked at random, as in the original game
+    Classic,
+    /// Pieces come in shuffled bags of all seven shapes
+    Guideline,
+}
+
+impl Ruleset {
+    pub const ALL: [Ruleset; 2] = [Ruleset::Classic, Ruleset::Guideline];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Ruleset::Classic => "Classic",
+            Ruleset::Guideline => "Guideline",
+        }
+    }
+}
+
+/// Gravity in rows per second up to a given score
+const SPEED_CURVE: [(u64, f32); 6] = [
+    (100, 1.2),
+    (200, 1.6),
+    (300, 2.0),
+    (400, 3.0),
+    (500, 4.0),
+    (600, 5.0),
+];
+/// Gravity once the score is past the end of the curve
+const TOP_SPEED: f32 = 6.0;
+
+/// Number of upcoming pieces kept ready, more than the queue ever shows
+const BAG_LENGTH: usize = 10;
+
+/// How long the well flashes after a finesse fault with the trainer on
+const FLASH_TIME: Duration = Duration::from_millis(300);
+
+/// A landed piece can be moved this many times before lock delay stops
+/// being reset by moves
+const MAX_LOCK_RESETS: u32 = 15;
+
+/// Format a duration as minutes, seconds and hundredths, e.g. `1:05.42`
+pub fn format_duration(d: Duration) -> String {
+    let centis = d.as_millis() / 10;
+    format!(
+        "{}:{:02}.{:02}",
+        centis / 6000,
+        (centis / 100) % 60,
+        centis % 100
+    )
+}
 
-pub(crate) struct GameState {
+/// A position to start a game from instead of an empty well, e.g. a
+/// puzzle imported from fumen
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Setup {
+    /// The stack, in the coordinates of the well
+    pub board: Vec<Segment>,
+    pub hold: Option<Shape>,
+    /// Pieces to deal before the randomizer takes over, the first one is
+    /// the current piece
+    pub queue: Vec<Shape>,
+}
+
+/// The whole state of a game. It can be serialized, which is how games are
+/// suspended and resumed.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct GameState {
+    mode: GameMode,
+    ruleset: Ruleset,
+    seed: u64,
+    /// Position the game started from, kept to restart it
+    #[serde(default)]
+    setup: Option<Setup>,
+    randomizer: Randomizer,
+    /// The stack with the colors of its cells, for drawing
     base: Vec<Segment>,
-    ghost_layer: HashSet<Segment>,
+    /// The same stack as bit masks, which the pieces move on
+    board: Board,
     bag: VecDeque<Shape>,
     cur_fig: Tetromino,
+    hold: Option<Shape>,
+    /// Hold can only be used once until the next piece is locked
+    hold_used: bool,
     game_over: bool,
-    fall_update: Instant,
+    /// Time since the piece last fell by one row. It only advances while
+    /// the game is updated, so gravity stops when the game is paused.
+    fall_timer: Duration,
+    /// Time the current piece has been resting on the stack
+    lock_timer: Duration,
+    lock_resets: u32,
+    /// The last move of the current piece was a rotation, for T-spins
+    rotated_last: bool,
     points: u64,
     updates_per_second: f32,
-    updates_fast: f32,
     update_slow: f32,
-    paused: bool,
+    stats: Stats,
+    goal_reached: bool,
+    /// The computer played part of the game
+    #[serde(default)]
+    assisted: bool,
+    /// The computer is playing now, the finesse of its pieces is not judged
+    #[serde(default)]
+    computer: bool,
+    /// Key presses that moved the current piece, for finesse
+    #[serde(default)]
+    presses: u32,
+    #[serde(default)]
+    last_finesse: Option<Finesse>,
+    /// Time the well still flashes after a finesse fault
+    #[serde(default)]
+    flash: Duration,
+    /// Garbage sent by the opponent on its way to the stack
+    #[serde(default)]
+    garbage: GarbageQueue,
+    /// Garbage rows this game sent that the opponent has not taken yet
+    #[serde(default)]
+    outgoing: u32,
+    settings: Settings,
 }
 
 /// Represents main part of the game where most of the logic is implemented
 impl GameState {
-    /// Create a new game with default settings
-    pub(crate) fn new() -> Self {
-        let mut rng = rand::thread_rng();
+    /// Create a new game in the given mode. The ruleset is taken from the
+    /// settings, the seed decides which pieces are dealt.
+    pub fn new(mode: GameMode, settings: &Settings, seed: u64) -> Self {
+        let mut randomizer = Randomizer::new(settings.ruleset, seed);
+        let cur_fig = Tetromino::from(randomizer.next_shape());
         // make a bag of pieces that are replenished on the fly
-        let bag: VecDeque<Shape> = (0..10).map(|_| rng.gen_range(0, 7).into()).collect();
+        let bag: VecDeque<Shape> = (0..BAG_LENGTH).map(|_| randomizer.next_shape()).collect();
         Self {
+            mode,
+            ruleset: settings.ruleset,
+            seed,
+            setup: None,
+            randomizer,
             base: Vec::new(),
-            ghost_layer: HashSet::new(),
+            board: Board::default(),
             bag,
-            cur_fig: Tetromino::new(),
+            cur_fig,
+            hold: None,
+            hold_used: false,
             game_over: false,
-            fall_update: Instant::now(),
+            fall_timer: Duration::from_secs(0),
+            lock_timer: Duration::from_secs(0),
+            lock_resets: 0,
+            rotated_last: false,
             points: 0,
             updates_per_second: 2.0,
-            updates_fast: 40.0,
             update_slow: 1.5,
-            paused: false,
+            stats: Stats::default(),
+            goal_reached: false,
+            assisted: false,
+            computer: false,
+            presses: 0,
+            last_finesse: None,
+            flash: Duration::from_secs(0),
+            // both players of a versus game get the same holes as well
+            garbage: GarbageQueue::new(!seed),
+            outgoing: 0,
+            settings: settings.clone(),
         }
     }
 
-    fn cur_fig_landed(&self) -> bool {
-        self.cur_fig.body.iter().any(|elem| {
-            elem.y == GRID_SIZE.1 - 1
-                || self
-                    .ghost_layer
-                    .iter()
-                    .any(|g_elem| g_elem.x == elem.x && g_elem.y == elem.y)
-        })
+    /// Create a new game that starts from a set up position. The pieces of
+    /// its queue are dealt first, then the randomizer continues.
+    pub fn with_setup(mode: GameMode, settings: &Settings, seed: u64, setup: &Setup) -> Self {
+        let mut game = Self::new(mode, settings, seed);
+        game.setup = Some(setup.clone());
+        game.base = setup.board.clone();
+        game.board = Board::new(&game.base);
+        game.hold = setup.hold;
+        let mut queue: VecDeque<Shape> = setup.queue.iter().cloned().collect();
+        if let Some(first) = queue.pop_front() {
+            game.bag
+                .truncate(BAG_LENGTH.saturating_sub(queue.len() + 1));
+            queue.push_back(game.cur_fig.shape());
+            queue.extend(game.bag.drain(..));
+            game.bag = queue;
+            game.cur_fig = Tetromino::from(first);
+        }
+        game
     }
 
-    fn add_shape_to_bag(&mut self) {
-        let mut rng = rand::thread_rng();
-        self.bag.push_back(rng.gen_range(0, 7).into());
+    /// The current position: the stack, the held piece and the current
+    /// piece followed by as many upcoming pieces as the queue shows
+    pub fn to_setup(&self) -> Setup {
+        let mut queue = vec![self.cur_fig.shape()];
+        queue.extend(self.bag.iter().take(self.settings.next_queue as usize));
+        Setup {
+            board: self.base.clone(),
+            hold: self.hold,
+            queue,
+        }
     }
 
-    fn update_ghost_layer(&mut self) {
-        for seg in self.cur_fig.body.iter() {
-            self.ghost_layer.insert(seg.add_ghost_layer());
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// Use changed settings, they take effect with the next frame
+    pub fn set_settings(&mut self, settings: &Settings) {
+        self.settings = settings.clone();
+    }
+
+    pub fn mode(&self) -> GameMode {
+        self.mode
+    }
+
+    pub fn ruleset(&self) -> Ruleset {
+        self.ruleset
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// The stack of locked cells
+    pub fn base(&self) -> &[Segment] {
+        &self.base
+    }
+
+    /// The stack as bit masks, the rules of moving pieces work on it
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    /// The falling piece
+    pub fn current(&self) -> &Tetromino {
+        &self.cur_fig
+    }
+
+    /// Where the falling piece would land, if the ghost is shown
+    pub fn ghost(&self) -> Option<Tetromino> {
+        if self.settings.ghost && !self.is_over() {
+            Some(self.drop_position())
+        } else {
+            None
         }
     }
 
+    pub fn hold(&self) -> Option<Shape> {
+        self.hold
+    }
+
+    /// The upcoming pieces shown in the next queue
+    pub fn next(&self) -> impl Iterator<Item = Shape> + '_ {
+        self.bag
+            .iter()
+            .cloned()
+            .take(self.settings.next_queue as usize)
+    }
+
+    pub fn setup(&self) -> Option<&Setup> {
+        self.setup.as_ref()
+    }
+
+    pub fn points(&self) -> u64 {
+        self.points
+    }
+
+    /// Whether hold can be used for the current piece
+    pub fn can_hold(&self) -> bool {
+        !self.hold_used
+    }
+
+    /// Whether the computer took over at some point of the game
+    pub fn assisted(&self) -> bool {
+        self.assisted
+    }
+
+    /// Whether the computer is playing the game now
+    pub fn computer_playing(&self) -> bool {
+        self.computer
+    }
+
+    /// How the last piece was placed, compared with the fewest key
+    /// presses needed
+    pub fn last_finesse(&self) -> Option<Finesse> {
+        self.last_finesse
+    }
+
+    /// Whether the well flashes for a finesse fault
+    pub fn flashing(&self) -> bool {
+        self.flash > Duration::from_secs(0)
+    }
+
+    /// Garbage sent by the opponent that has not been put under the stack
+    /// yet
+    pub fn garbage(&self) -> &GarbageQueue {
+        &self.garbage
+    }
+
+    /// Queue garbage sent by the opponent, see the `garbage` module
+    pub fn receive_garbage(&mut self, rows: u32) {
+        self.garbage.receive(rows);
+    }
+
+    /// Garbage rows sent by clearing lines since the last call, for the
+    /// opponent. Rows that cancelled incoming garbage are not included.
+    pub fn take_attack(&mut self) -> u32 {
+        std::mem::replace(&mut self.outgoing, 0)
+    }
+
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    pub fn lines(&self) -> u32 {
+        self.stats.lines
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.stats.elapsed
+    }
+
+    fn emit(&mut self, event: GameEvent) {
+        self.stats.record(event);
+    }
+
+    /// The game is over either because the stack hit the ceiling or
+    /// because the goal of the mode has been reached
+    pub fn is_over(&self) -> bool {
+        self.game_over || self.goal_reached
+    }
+
+    /// Whether the game ended by completing the goal of its mode
+    pub fn goal_reached(&self) -> bool {
+        self.goal_reached
+    }
+
+    fn check_goal(&mut self) {
+        self.goal_reached = match self.mode {
+            GameMode::Marathon | GameMode::Sandbox | GameMode::Versus => false,
+            GameMode::Sprint => self.stats.lines >= SPRINT_LINES,
+            GameMode::Ultra => self.stats.elapsed >= ULTRA_TIME,
+        };
+    }
+
+    fn cur_fig_landed(&self) -> bool {
+        self.landed(&self.cur_fig)
+    }
+
+    fn landed(&self, piece: &Tetromino) -> bool {
+        self.board.landed(&piece.cells())
+    }
+
+    fn add_shape_to_bag(&mut self) {
+        let shape = self.randomizer.next_shape();
+        self.bag.push_back(shape);
+    }
+
     fn hit_ceiling(&self) -> bool {
-        self.cur_fig.body.iter().any(|seg| seg.y == 0)
-            && self.ghost_layer.iter().any(|g_elem| {
-                self.cur_fig
-                    .body
-                    .iter()
-                    .any(|seg| g_elem.x == seg.x && g_elem.y == seg.y)
-            })
+        self.board.hit_ceiling(&self.cur_fig.cells())
     }
 
     /// Check if any rows are full and burn them. Add points based on how many
-    /// rows were burnt, extra rows give bonus points.
-    fn burn_full_rows(&mut self) {
-        let mut burned = 0;
+    /// rows were burnt, extra rows give bonus points. Returns the number of
+    /// rows burnt.
+    fn burn_full_rows(&mut self) -> u32 {
+        let full = self.board.clear_full_rows();
+        let burned = u64::from(full.count_ones());
         for y_coord in 0..GRID_SIZE.1 {
-            if self.base.iter().filter(|seg| seg.y == y_coord).count() == 10 {
-                burned += 1;
+            if full & 1 << y_coord != 0 {
                 self.base.retain(|seg| seg.y != y_coord);
                 self.base.iter_mut().for_each(|seg| {
                     if seg.y < y_coord {
@@ -100,195 +432,394 @@ impl GameState {
                 _ => 25,
             };
             self.points += burned * 10 + bonus;
-            self.ghost_layer = (0..GRID_SIZE.0)
-                .filter_map(|x| {
-                    match self
-                        .base
-                        .iter()
-                        .filter(|seg| seg.x == x)
-                        .min_by_key(|elem| elem.y)
-                    {
-                        Some(c) => Some(Segment::new((c.x, c.y - 1), c.color)),
-                        None => None,
-                    }
-                })
-                .collect();
         }
+        burned as u32
+    }
+
+    /// A T piece that was rotated into place with at least three of the four
+    /// cells diagonal to its center filled is a T-spin
+    fn is_tspin(&self) -> bool {
+        self.cur_fig.shape() == Shape::T
+            && self.rotated_last
+            && self.board.tspin_corners(&self.cur_fig.cells())
+    }
+
+    /// Settle the landed piece into the base and spawn the next one. With
+    /// the finesse trainer set to retry, a piece the player placed with a
+    /// fault is given back instead.
+    fn lock_piece(&mut self) {
+        let shape = self.cur_fig.shape();
+        let judged = if self.computer {
+            None
+        } else {
+            finesse::check(self, &self.cur_fig, self.presses)
+        };
+        if let Some(finesse) = judged {
+            self.presses = 0;
+            self.last_finesse = Some(finesse);
+            let faults = finesse.faults();
+            self.emit(GameEvent::Finesse { faults });
+            if faults > 0 && self.settings.finesse_trainer != Trainer::Off {
+                self.flash = FLASH_TIME;
+            }
+            if faults > 0 && self.settings.finesse_trainer == Trainer::Retry {
+                self.respawn(shape);
+                return;
+            }
+        }
+        let tspin = self.is_tspin();
+        let back_to_back = self.stats.back_to_back;
+        self.board.lock(&self.cur_fig.cells());
+        self.base.extend(self.cur_fig.clone_body());
+        let lines = self.burn_full_rows();
+        let perfect_clear = lines > 0 && self.board.is_empty();
+        self.emit(GameEvent::Locked {
+            shape,
+            lines,
+            tspin,
+            perfect_clear,
+        });
+        if lines > 0 {
+            let clear = Clear {
+                lines,
+                tspin,
+                combo: self.stats.combo,
+                back_to_back: back_to_back && self.stats.back_to_back,
+                perfect_clear,
+            };
+            self.outgoing += self.garbage.cancel(clear.attack());
+        } else {
+            self.add_garbage();
+        }
+        self.check_goal();
+        self.updates_per_second = self.update_slow;
+        let next = self.bag.pop_front().unwrap_or_default();
+        self.add_shape_to_bag();
+        self.hold_used = false;
+        self.respawn(next);
+    }
+
+    /// Push the stack up by the garbage rows that arrived. The game is lost
+    /// if that pushes any of it out of the well.
+    fn add_garbage(&mut self) {
+        let holes = self.garbage.take_ready(self.settings.garbage_messiness);
+        if holes.is_empty() {
+            return;
+        }
+        let bottom = GRID_SIZE.1 - 1;
+        for hole in holes {
+            if !self.board.push_garbage(hole) {
+                self.game_over = true;
+            }
+            for seg in self.base.iter_mut() {
+                *seg = Segment::new((seg.x, seg.y - 1), seg.color);
+            }
+            for x in (0..GRID_SIZE.0).filter(|&x| x != hole) {
+                self.base.push(Segment::new((x, bottom), GARBAGE_COLOR));
+            }
+        }
+    }
+
+    /// Start over with a new piece of the shape at the top
+    fn respawn(&mut self, shape: Shape) {
+        self.cur_fig = Tetromino::from(shape);
+        self.lock_timer = Duration::from_secs(0);
+        self.lock_resets = 0;
+        self.rotated_last = false;
+        self.presses = 0;
+    }
+
+    /// Put the current piece aside and continue with the held one, or with
+    /// the next piece from the bag if nothing was held yet
+    fn hold_piece(&mut self) {
+        if self.hold_used {
+            return;
+        }
+        let shape = self.cur_fig.shape();
+        self.cur_fig = match self.hold.replace(shape) {
+            Some(held) => Tetromino::from(held),
+            None => {
+                let next = Tetromino::from(self.bag.pop_front().unwrap_or_default());
+                self.add_shape_to_bag();
+                next
+            }
+        };
+        self.hold_used = true;
+        self.fall_timer = Duration::from_secs(0);
+        self.lock_timer = Duration::from_secs(0);
+        self.lock_resets = 0;
+        self.rotated_last = false;
+        self.presses = 0;
+    }
+
+    /// Where the current piece would end up if it was dropped now
+    pub fn drop_position(&self) -> Tetromino {
+        let mut piece = self.cur_fig.clone();
+        piece.set_cells(&self.board.fall(&piece.cells()));
+        piece
+    }
+
+    /// Lock the current piece where a placement found by `movegen` puts it,
+    /// as if its path had been played. Bots use it to look at the game
+    /// after a placement without simulating the frames it takes.
+    pub fn place(&mut self, placement: &Placement) {
+        if self.is_over() {
+            return;
+        }
+        self.cur_fig = placement.piece.clone();
+        // the last move only matters for T-spins
+        self.rotated_last = placement.tspin;
+        self.presses = 0;
+        self.hard_drop();
+    }
+
+    /// Drop the piece straight down and lock it immediately
+    fn hard_drop(&mut self) {
+        if self.hit_ceiling() {
+            return;
+        }
+        self.cur_fig = self.drop_position();
+        self.lock_piece();
+        self.fall_timer = Duration::from_secs(0);
     }
 
     fn accelerate(&mut self) {
         if self.cur_fig.body.iter().any(|seg| seg.y > 1) && !self.cur_fig_landed() {
-            self.updates_per_second = self.updates_fast;
+            self.updates_per_second = self.update_slow * self.settings.soft_drop_factor as f32;
+        }
+    }
+
+    /// Move or rotate the piece. Moving a landed piece gives the player
+    /// more time before it locks, a limited number of times.
+    fn shift(&mut self, motion: Motion) {
+        let before = self.cur_fig.body.clone();
+        self.cur_fig.move_to(motion, &self.board);
+        if self.cur_fig.body != before {
+            self.rotated_last = match motion {
+                Motion::Left | Motion::Right => false,
+                Motion::RotateCw | Motion::RotateCcw | Motion::Rotate180 => true,
+            };
+        }
+        if self.cur_fig.body != before && self.lock_resets < MAX_LOCK_RESETS {
+            if self.lock_timer > Duration::from_secs(0) {
+                self.lock_resets += 1;
+            }
+            self.lock_timer = Duration::from_secs(0);
         }
     }
 }
 
-/// Implementation of the EventHandler for out GameState
-///
-/// Two mandatory methods (update() and draw()) are implemented along with
-/// handling key presses to rotate and move pieces.
-impl event::EventHandler for GameState {
-    fn update(&mut self, _ctx: &mut Context) -> GameResult {
-        self.update_slow = match self.points {
-            0...100 => 1.2,
-            100...200 => 1.6,
-            200...300 => 2.0,
-            300...400 => 3.0,
-            400...500 => 4.0,
-            500...600 => 5.0,
-            _ => 6.0,
-        };
+/// Frame update, drawing and input handling for the game. These are driven by
+/// a `Simulation`, which steps the game in fixed ticks.
+impl GameState {
+    /// Advance the game by `dt`. The game only depends on the time passed
+    /// in here, so the same inputs at the same times give the same game.
+    pub fn update(&mut self, dt: Duration) {
+        if self.is_over() {
+            return;
+        }
+        self.emit(GameEvent::Tick(dt));
+        self.fall_timer += dt;
+        self.flash = self.flash.checked_sub(dt).unwrap_or_default();
+        self.garbage.update(dt);
+        self.check_goal();
+
+        self.update_slow = SPEED_CURVE
+            .iter()
+            .find(|&&(max_points, _)| self.points <= max_points)
+            .map(|&(_, speed)| speed)
+            .unwrap_or(TOP_SPEED);
+
+        // with a lock delay the landed piece locks once the delay runs out,
+        // otherwise it locks on the next gravity step
+        let lock_delay = self.settings.lock_delay();
+        if lock_delay > Duration::from_secs(0) && self.cur_fig_landed() && !self.hit_ceiling() {
+            self.lock_timer += dt;
+            if self.lock_timer >= lock_delay {
+                self.lock_piece();
+                self.fall_timer = Duration::from_secs(0);
+            }
+            return;
+        }
 
         let millis_per_update: u64 = (1.0 / self.updates_per_second * 1000.0) as u64;
-        if Instant::now() - self.fall_update >= Duration::from_millis(millis_per_update)
-            && !self.game_over
-            && !self.paused
-        {
+        if self.fall_timer >= Duration::from_millis(millis_per_update) && !self.is_over() {
             if self.hit_ceiling() {
-                println!("Hit ceiling");
                 self.game_over = true;
-                return Ok(());
             } else if self.cur_fig_landed() {
-                self.update_ghost_layer();
-                self.base.extend(self.cur_fig.clone_body());
-                self.burn_full_rows();
-                println!("{}", self.points);
-                self.updates_per_second = self.update_slow;
-                self.cur_fig = Tetromino::from(self.bag.pop_front().unwrap_or_default());
-                self.add_shape_to_bag();
+                self.lock_piece();
             } else {
                 self.cur_fig.update();
-                self.fall_update = Instant::now();
+                self.rotated_last = false;
+                self.fall_timer = Duration::from_secs(0);
                 self.updates_per_second = self.update_slow;
             }
-            // self.move_update = Instant::now();
         }
-        Ok(())
     }
 
-    fn draw(&mut self, ctx: &mut Context) -> GameResult {
-        graphics::clear(ctx, [0.0, 0.0, 0.0, 1.0].into());
-        let main_field = graphics::Rect::new_i32(
-            0,
-            0,
-            (GRID_CELL_SIZE.0 * GRID_SIZE.0) as i32,
-            (GRID_CELL_SIZE.1 * GRID_SIZE.1) as i32,
-        );
-        let rectangle = graphics::Mesh::new_rectangle(
-            ctx,
-            graphics::DrawMode::fill(),
-            main_field,
-            (32, 32, 32, 255).into(),
-        )?;
-        graphics::draw(ctx, &rectangle, (ggez::mint::Point2 { x: 0.0, y: 0.0 },))?;
-        // draw a grid
-        for j in 0..GRID_SIZE.1 {
-            if j < GRID_SIZE.0 {
-                let points_vert = [
-                    ggez::mint::Point2 {
-                        x: (j * GRID_CELL_SIZE.0) as f32,
-                        y: 0.0,
-                    },
-                    ggez::mint::Point2 {
-                        x: (j * GRID_CELL_SIZE.0) as f32,
-                        y: (GRID_CELL_SIZE.1 * GRID_SIZE.1) as f32,
-                    },
-                ];
-                let line =
-                    graphics::Mesh::new_line(ctx, &points_vert, 1.0, [0.3, 0.3, 0.3, 0.7].into())?;
-                graphics::draw(ctx, &line, (ggez::mint::Point2 { x: 0.0, y: 0.0 },))?;
-            }
-            let points_hor = [
-                ggez::mint::Point2 {
-                    y: (j * GRID_CELL_SIZE.1) as f32,
-                    x: 0.0,
-                },
-                ggez::mint::Point2 {
-                    y: (j * GRID_CELL_SIZE.1) as f32,
-                    x: (GRID_CELL_SIZE.0 * GRID_SIZE.0) as f32,
-                },
-            ];
-            let line =
-                graphics::Mesh::new_line(ctx, &points_hor, 1.0, [0.3, 0.3, 0.3, 0.7].into())?;
-            graphics::draw(ctx, &line, (ggez::mint::Point2 { x: 0.0, y: 0.0 },))?;
+    /// The live statistics as labels and values, as the side panel shows
+    /// them
+    pub fn stat_rows(&self) -> Vec<(&'static str, String)> {
+        let stats = &self.stats;
+        let time = match self.mode {
+            GameMode::Ultra => (
+                "Left",
+                format_duration(ULTRA_TIME.checked_sub(stats.elapsed).unwrap_or_default()),
+            ),
+            _ => ("Time", format_duration(stats.elapsed)),
+        };
+        let lines = match self.mode {
+            GameMode::Sprint => format!("{}/{}", stats.lines, SPRINT_LINES),
+            _ => stats.lines.to_string(),
+        };
+        vec![
+            time,
+            ("Lines", lines),
+            ("Level", stats.level().to_string()),
+            ("Pieces", stats.pieces.to_string()),
+            ("PPS", format!("{:.2}", stats.pieces_per_second())),
+            ("KPP", format!("{:.2}", stats.keys_per_piece())),
+            ("Faults", stats.finesse_faults.to_string()),
+            ("Single", stats.clears[0].to_string()),
+            ("Double", stats.clears[1].to_string()),
+            ("Triple", stats.clears[2].to_string()),
+            ("Tetris", stats.tetrises().to_string()),
+            ("T-spin", stats.tspins.to_string()),
+            ("PC", stats.perfect_clears.to_string()),
+        ]
+    }
+
+    /// A move pressed by the player, counted for the keys per piece. Moves
+    /// repeated while a key is held go straight to `perform`.
+    pub fn input(&mut self, action: Action) {
+        if !self.is_over() && action != Action::Autoplay {
+            self.emit(GameEvent::KeyPressed);
+        }
+        if action.moves_piece() {
+            self.presses += 1;
         }
+        self.perform(action);
+    }
 
-        self.cur_fig.draw(ctx)?;
-
-        // draw the base
-        for seg in self.base.iter() {
-            let rectangle = graphics::Mesh::new_rectangle(
-                ctx,
-                graphics::DrawMode::fill(),
-                seg.into(),
-                seg.color.into(),
-            )?;
-            graphics::draw(ctx, &rectangle, (ggez::mint::Point2 { x: 0.0, y: 0.0 },))?;
+    /// Perform the motion requested by the player. Pausing and restarting
+    /// are handled by the scene that owns the game, which also lets the
+    /// computer play once autoplay is asked for. The game only remembers
+    /// who plays: autoplay hands the game to the computer and back.
+    pub fn perform(&mut self, action: Action) {
+        if !self.is_over() {
+            match action {
+                Action::MoveLeft => self.shift(Motion::Left),
+                Action::MoveRight => self.shift(Motion::Right),
+                Action::RotateCw => self.shift(Motion::RotateCw),
+                Action::RotateCcw => self.shift(Motion::RotateCcw),
+                Action::Rotate180 => self.shift(Motion::Rotate180),
+                Action::SoftDrop => self.accelerate(),
+                Action::HardDrop => self.hard_drop(),
+                Action::Hold => self.hold_piece(),
+                Action::Autoplay => {
+                    self.assisted = true;
+                    self.computer = !self.computer;
+                }
+                Action::Pause | Action::Restart => (),
+            };
         }
+    }
+}
 
-        // draw Score
-        let title_position = ggez::mint::Point2 {
-            x: (GRID_SIZE.0 * GRID_CELL_SIZE.0 + (7 * GRID_CELL_SIZE.0) / 3) as f32,
-            y: GRID_SIZE.1 as f32 + 2.,
-        };
-        let point_position = ggez::mint::Point2 {
-            x: title_position.x + 16.,
-            y: title_position.y + 32.,
-        };
+/// Shows the board as a diagram, see the `diagram` module
+impl fmt::Debug for GameState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "GameState {{ mode: {:?}, hold: {:?}, next: {:?}, points: {} }}",
+            self.mode,
+            self.hold,
+            self.bag
+                .iter()
+                .take(self.settings.next_queue as usize)
+                .collect::<Vec<_>>(),
+            self.points
+        )?;
+        f.write_str(&diagram::print(&self.base, Some(&self.cur_fig)))
+    }
+}
 
-        // let points_text = graphics::Text::new("Score");
-        let points_text = graphics::Text::new("Score");
-        let points = graphics::Text::new(self.points.to_string());
-        graphics::draw(ctx, &points_text, (title_position,))?;
-        graphics::draw(ctx, &points, (point_position,))?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        // draw next figure
-        let next_fig_text_pos = ggez::mint::Point2 {
-            x: title_position.x + 10.0,
-            y: point_position.y + 70.0,
+    /// A game with the stack and falling piece of a diagram
+    fn game_from(text: &str) -> GameState {
+        let diagram = diagram::parse(text).unwrap();
+        let setup = Setup {
+            board: diagram.board,
+            hold: None,
+            queue: Vec::new(),
         };
-        let next_text = graphics::Text::new("Next");
-        graphics::draw(ctx, &next_text, (next_fig_text_pos,))?;
-        let next_shape = **self.bag.iter().peekable().peek().unwrap();
-        let mut next_fig = Tetromino::from(next_shape);
-        next_fig.translate(8, 8);
-        for seg in next_fig.body.iter() {
-            let rectangle = graphics::Mesh::new_rectangle(
-                ctx,
-                graphics::DrawMode::fill(),
-                seg.into(),
-                seg.color.into(),
-            )?;
-            graphics::draw(ctx, &rectangle, (ggez::mint::Point2 { x: 0.0, y: 0.0 },))?;
-        }
+        let mut game = GameState::with_setup(GameMode::Marathon, &Settings::default(), 1, &setup);
+        game.cur_fig = diagram.piece.unwrap();
+        game
+    }
 
-        graphics::present(ctx)?;
+    #[test]
+    fn test_hard_drop_clears_lines() {
+        let mut game = game_from(
+            "
+            .....i....
+            .....i....
+            .....i....
+            .....i....
+            ..........
+            GGGGG.GGGG
+            GGGGG.GGGG
+            LL...Z....
+            ",
+        );
+        game.perform(Action::HardDrop);
+        assert_eq!(
+            diagram::print(&game.base, None),
+            "\
+            .....I....\n\
+            .....I....\n\
+            LL...Z....\n"
+        );
+        assert_eq!(game.lines(), 2);
+    }
 
-        ggez::timer::yield_now();
+    #[test]
+    fn test_garbage() {
+        let well = "
+            ....i.....
+            ....i.....
+            ....i.....
+            ....i.....
+            ..........
+            GGGG.GGGGG
+            GGGG.GGGGG
+            ";
+        let mut game = game_from(well);
+        game.perform(Action::HardDrop);
+        assert_eq!(game.take_attack(), 1);
+        assert_eq!(game.take_attack(), 0);
 
-        Ok(())
-    }
+        // the double cancels one of two incoming rows, the other arrives
+        // with the next piece that clears nothing
+        let mut game = game_from(well);
+        game.receive_garbage(2);
+        game.garbage.update(crate::garbage::DELAY);
+        game.perform(Action::HardDrop);
+        assert_eq!(game.take_attack(), 0);
+        assert_eq!(game.garbage().total(), 1);
+        game.perform(Action::HardDrop);
+        assert_eq!(game.garbage().total(), 0);
+        let board = diagram::print(&game.base, None);
+        let bottom = board.lines().last().unwrap();
+        assert_eq!(bottom.matches('G').count(), 9);
 
-    /// Listen to key events, if certain keys are pressed perform prescribed motions
-    fn key_down_event(
-        &mut self,
-        _ctx: &mut Context,
-        keycode: KeyCode,
-        _keymod: KeyMods,
-        _repeat: bool,
-    ) {
-        if keycode == KeyCode::Escape {
-            ggez::quit(_ctx);
-        }
-        if !self.game_over {
-            match keycode {
-                KeyCode::Left => self.cur_fig.move_to(Motion::Left, &self.base),
-                KeyCode::Right => self.cur_fig.move_to(Motion::Right, &self.base),
-                KeyCode::Up => self.cur_fig.move_to(Motion::RotateLeft, &self.base),
-                KeyCode::Down => self.accelerate(), //self.updates_per_second = self.updates_fast,
-                KeyCode::Space => self.paused = !self.paused,
-                _ => (),
-            };
-        }
+        // garbage pushing the stack out of the well ends the game
+        game.receive_garbage(20);
+        game.garbage.update(crate::garbage::DELAY);
+        game.perform(Action::HardDrop);
+        assert!(game.is_over());
     }
 }
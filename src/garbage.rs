@@ -0,0 +1,217 @@
+//! Garbage in versus games: how many rows a clear sends to the opponent,
+//! and the rows on their way to a board. Sent rows first cancel garbage
+//! waiting to arrive on the sender's own board, only the rest is sent on.
+//! Received garbage waits for `DELAY` and is put under the stack when the
+//! next piece locks without clearing a row.
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::randomizer::Rng;
+use crate::tetromino::GRID_SIZE;
+
+/// How long received garbage waits before it can arrive, time for the
+/// player to cancel it
+pub const DELAY: Duration = Duration::from_millis(1000);
+
+/// Rows sent for clearing one to four rows at once
+const LINES: [u32; 4] = [0, 1, 2, 4];
+/// Rows sent for clearing one to three rows with a T-spin
+const TSPIN_LINES: [u32; 3] = [2, 4, 6];
+/// Extra rows for a difficult clear right after another one
+const BACK_TO_BACK: u32 = 1;
+/// Extra rows for the clears in a row, the first clear of a combo being at
+/// index 0. Longer combos send as much as the last entry.
+const COMBO: [u32; 11] = [0, 1, 1, 2, 2, 3, 3, 4, 4, 4, 5];
+/// Extra rows for leaving the well empty
+const PERFECT_CLEAR: u32 = 10;
+
+/// A clear, with what decides how many rows it sends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Clear {
+    pub lines: u32,
+    pub tspin: bool,
+    /// Pieces in a row that cleared rows, this one included
+    pub combo: u32,
+    /// This clear and the one before were both Tetrises or T-spins
+    pub back_to_back: bool,
+    pub perfect_clear: bool,
+}
+
+impl Clear {
+    /// Rows of garbage the clear sends
+    pub fn attack(&self) -> u32 {
+        if self.lines == 0 {
+            return 0;
+        }
+        let lines = if self.tspin {
+            TSPIN_LINES[(self.lines.min(3) - 1) as usize]
+        } else {
+            LINES[(self.lines.min(4) - 1) as usize]
+        };
+        let combo = COMBO[(self.combo.max(1) as usize - 1).min(COMBO.len() - 1)];
+        let back_to_back = if self.back_to_back { BACK_TO_BACK } else { 0 };
+        let perfect_clear = if self.perfect_clear { PERFECT_CLEAR } else { 0 };
+        lines + combo + back_to_back + perfect_clear
+    }
+}
+
+/// Rows sent by one attack, and how long until they can arrive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct Incoming {
+    rows: u32,
+    delay: Duration,
+}
+
+/// Garbage on its way to a board, oldest first
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GarbageQueue {
+    incoming: VecDeque<Incoming>,
+    /// Picks the open column of the rows
+    rng: Rng,
+}
+
+impl GarbageQueue {
+    /// The seed decides where the rows are open, players of a versus game
+    /// with the same seed get the same holes
+    pub fn new(seed: u64) -> Self {
+        Self {
+            incoming: VecDeque::new(),
+            rng: Rng::new(seed),
+        }
+    }
+
+    /// Queue an attack from the opponent
+    pub fn receive(&mut self, rows: u32) {
+        if rows > 0 {
+            self.incoming.push_back(Incoming { rows, delay: DELAY });
+        }
+    }
+
+    pub fn update(&mut self, dt: Duration) {
+        for incoming in self.incoming.iter_mut() {
+            incoming.delay = incoming.delay.checked_sub(dt).unwrap_or_default();
+        }
+    }
+
+    /// Cancel queued rows with rows about to be sent, the oldest first.
+    /// Returns the rows left to send to the opponent.
+    pub fn cancel(&mut self, mut rows: u32) -> u32 {
+        while let Some(incoming) = self.incoming.front_mut() {
+            if rows == 0 {
+                break;
+            }
+            let cancelled = rows.min(incoming.rows);
+            incoming.rows -= cancelled;
+            rows -= cancelled;
+            if incoming.rows == 0 {
+                self.incoming.pop_front();
+            }
+        }
+        rows
+    }
+
+    /// All rows on their way
+    pub fn total(&self) -> u32 {
+        self.incoming.iter().map(|incoming| incoming.rows).sum()
+    }
+
+    /// Rows that arrive with the next piece locked
+    pub fn ready(&self) -> u32 {
+        self.incoming
+            .iter()
+            .filter(|incoming| incoming.delay == Duration::from_secs(0))
+            .map(|incoming| incoming.rows)
+            .sum()
+    }
+
+    /// Take the rows that arrived, as the open column of every row in the
+    /// order they go under the stack. The rows of an attack share their
+    /// column, except that every row after the first moves it elsewhere
+    /// with a chance of `messiness` percent.
+    pub fn take_ready(&mut self, messiness: u32) -> Vec<i16> {
+        let width = GRID_SIZE.0 as u32;
+        let mut holes = Vec::new();
+        while let Some(incoming) = self.incoming.front() {
+            if incoming.delay > Duration::from_secs(0) {
+                break;
+            }
+            let rows = incoming.rows;
+            self.incoming.pop_front();
+            let mut hole = self.rng.below(width);
+            for row in 0..rows {
+                if row > 0 && self.rng.below(100) < messiness {
+                    hole = (hole + 1 + self.rng.below(width - 1)) % width;
+                }
+                holes.push(hole as i16);
+            }
+        }
+        holes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clear(lines: u32, tspin: bool, combo: u32, back_to_back: bool) -> Clear {
+        Clear {
+            lines,
+            tspin,
+            combo,
+            back_to_back,
+            perfect_clear: false,
+        }
+    }
+
+    #[test]
+    fn test_attack() {
+        assert_eq!(clear(0, false, 0, false).attack(), 0);
+        assert_eq!(clear(1, false, 1, false).attack(), 0);
+        assert_eq!(clear(2, false, 1, false).attack(), 1);
+        assert_eq!(clear(4, false, 1, false).attack(), 4);
+        assert_eq!(clear(4, false, 1, true).attack(), 5);
+        assert_eq!(clear(2, true, 1, false).attack(), 4);
+        assert_eq!(clear(2, true, 2, true).attack(), 6);
+        assert_eq!(clear(1, false, 5, false).attack(), 2);
+        assert_eq!(clear(1, false, 40, false).attack(), 5);
+        let perfect = Clear {
+            perfect_clear: true,
+            ..clear(4, false, 1, false)
+        };
+        assert_eq!(perfect.attack(), 14);
+    }
+
+    #[test]
+    fn test_queue() {
+        let mut queue = GarbageQueue::new(3);
+        queue.receive(3);
+        queue.update(DELAY / 2);
+        queue.receive(2);
+        assert_eq!((queue.total(), queue.ready()), (5, 0));
+        assert!(queue.take_ready(0).is_empty());
+
+        // cancelling eats into the oldest attack first
+        assert_eq!(queue.cancel(1), 0);
+        queue.update(DELAY / 2);
+        assert_eq!((queue.total(), queue.ready()), (4, 2));
+        let holes = queue.take_ready(0);
+        assert_eq!(holes.len(), 2);
+        assert_eq!(holes[0], holes[1]);
+        assert_eq!(queue.total(), 2);
+        assert_eq!(queue.cancel(5), 3);
+        assert_eq!(queue.total(), 0);
+    }
+
+    #[test]
+    fn test_messiness() {
+        let mut queue = GarbageQueue::new(3);
+        queue.receive(8);
+        queue.update(DELAY);
+        let holes = queue.take_ready(100);
+        assert_eq!(holes.len(), 8);
+        assert!(holes.windows(2).all(|pair| pair[0] != pair[1]));
+        assert!(holes.iter().all(|hole| (0..GRID_SIZE.0).contains(hole)));
+    }
+}
@@ -0,0 +1,232 @@
+//! Turns held movement actions into repeated moves (DAS and ARR).
+//!
+//! Presses are counted, so the same action held on the keyboard and on a
+//! gamepad at once is only released once both devices have let go.
+use std::time::Duration;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::action::Action;
+
+/// Moves needed to cross the whole well, used when ARR is zero
+const INSTANT_REPEATS: u32 = 10;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Handling {
+    /// Delayed auto shift: how long a direction is held before it repeats
+    das: Duration,
+    /// Auto repeat rate: delay between repeated moves, zero moves to the wall
+    arr: Duration,
+    left: u8,
+    right: u8,
+    soft_drop: u8,
+    /// Direction that is currently charging or repeating
+    direction: Option<Action>,
+    timer: Duration,
+    repeating: bool,
+}
+
+impl Handling {
+    pub fn new(das: Duration, arr: Duration) -> Self {
+        Self {
+            das,
+            arr,
+            left: 0,
+            right: 0,
+            soft_drop: 0,
+            direction: None,
+            timer: Duration::from_secs(0),
+            repeating: false,
+        }
+    }
+
+    pub fn set_timing(&mut self, das: Duration, arr: Duration) {
+        self.das = das;
+        self.arr = arr;
+    }
+
+    /// Register a press. The latest direction pressed takes over.
+    pub fn press(&mut self, action: Action) {
+        match action {
+            Action::MoveLeft => self.left += 1,
+            Action::MoveRight => self.right += 1,
+            Action::SoftDrop => self.soft_drop += 1,
+            _ => return,
+        }
+        if action != Action::SoftDrop {
+            self.charge(Some(action));
+        }
+    }
+
+    /// Register a release. If the other direction is still held it starts
+    /// charging again.
+    pub fn release(&mut self, action: Action) {
+        match action {
+            Action::MoveLeft => self.left = self.left.saturating_sub(1),
+            Action::MoveRight => self.right = self.right.saturating_sub(1),
+            Action::SoftDrop => self.soft_drop = self.soft_drop.saturating_sub(1),
+            _ => return,
+        }
+        if self.direction == Some(action) && !self.is_held(action) {
+            let other = if action == Action::MoveLeft {
+                Action::MoveRight
+            } else {
+                Action::MoveLeft
+            };
+            if self.is_held(other) {
+                self.charge(Some(other));
+            } else {
+                self.charge(None);
+            }
+        }
+    }
+
+    /// Forget every held action, e.g. when the game loses input focus
+    pub fn release_all(&mut self) {
+        self.left = 0;
+        self.right = 0;
+        self.soft_drop = 0;
+        self.charge(None);
+    }
+
+    pub fn soft_drop_held(&self) -> bool {
+        self.soft_drop > 0
+    }
+
+    /// Advance the timers and return the direction to move in together
+    /// with the number of repeated moves due this frame
+    pub fn update(&mut self, dt: Duration) -> Option<(Action, u32)> {
+        let direction = self.direction?;
+        self.timer += dt;
+        let mut repeats = 0;
+        if !self.repeating {
+            if self.timer < self.das {
+                return None;
+            }
+            // the piece shifts as soon as DAS is charged
+            self.repeating = true;
+            self.timer -= self.das;
+            repeats = 1;
+        }
+        if self.arr == Duration::from_secs(0) {
+            return Some((direction, INSTANT_REPEATS));
+        }
+        while self.timer >= self.arr {
+            self.timer -= self.arr;
+            repeats += 1;
+        }
+        if repeats > 0 {
+            Some((direction, repeats))
+        } else {
+            None
+        }
+    }
+
+    fn is_held(&self, action: Action) -> bool {
+        match action {
+            Action::MoveLeft => self.left > 0,
+            Action::MoveRight => self.right > 0,
+            _ => false,
+        }
+    }
+
+    fn charge(&mut self, direction: Option<Action>) {
+        self.direction = direction;
+        self.timer = Duration::from_secs(0);
+        self.repeating = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn test_das_and_arr() {
+        let mut handling = Handling::new(ms(100), ms(20));
+        assert_eq!(handling.update(ms(100)), None);
+        handling.press(Action::MoveLeft);
+        assert_eq!(handling.update(ms(99)), None);
+        // the first shift comes exactly when DAS is charged
+        assert_eq!(handling.update(ms(1)), Some((Action::MoveLeft, 1)));
+        assert_eq!(handling.update(ms(19)), None);
+        assert_eq!(handling.update(ms(1)), Some((Action::MoveLeft, 1)));
+        // a long frame repeats as often as ARR fits in it
+        assert_eq!(handling.update(ms(70)), Some((Action::MoveLeft, 3)));
+        assert_eq!(handling.update(ms(10)), Some((Action::MoveLeft, 1)));
+
+        handling.release(Action::MoveLeft);
+        assert_eq!(handling.update(ms(1000)), None);
+
+        // DAS and a frame of ARR at once
+        handling.press(Action::MoveRight);
+        assert_eq!(handling.update(ms(150)), Some((Action::MoveRight, 3)));
+    }
+
+    #[test]
+    fn test_instant_arr() {
+        let mut handling = Handling::new(ms(100), ms(0));
+        handling.press(Action::MoveRight);
+        assert_eq!(handling.update(ms(50)), None);
+        assert_eq!(
+            handling.update(ms(50)),
+            Some((Action::MoveRight, INSTANT_REPEATS))
+        );
+        assert_eq!(
+            handling.update(ms(1)),
+            Some((Action::MoveRight, INSTANT_REPEATS))
+        );
+    }
+
+    #[test]
+    fn test_held_on_both_devices() {
+        let mut handling = Handling::new(ms(100), ms(20));
+        // keyboard, then the gamepad
+        handling.press(Action::MoveLeft);
+        handling.press(Action::MoveLeft);
+        handling.press(Action::SoftDrop);
+        handling.press(Action::SoftDrop);
+        assert_eq!(handling.update(ms(100)), Some((Action::MoveLeft, 1)));
+
+        handling.release(Action::MoveLeft);
+        handling.release(Action::SoftDrop);
+        assert!(handling.soft_drop_held());
+        assert_eq!(handling.update(ms(20)), Some((Action::MoveLeft, 1)));
+
+        handling.release(Action::MoveLeft);
+        handling.release(Action::SoftDrop);
+        assert!(!handling.soft_drop_held());
+        assert_eq!(handling.update(ms(1000)), None);
+        // more releases than presses do not leave a press owed
+        handling.release(Action::MoveLeft);
+        handling.press(Action::MoveLeft);
+        handling.release(Action::MoveLeft);
+        assert_eq!(handling.update(ms(1000)), None);
+    }
+
+    #[test]
+    fn test_newer_direction_released() {
+        let mut handling = Handling::new(ms(100), ms(20));
+        handling.press(Action::MoveLeft);
+        assert_eq!(handling.update(ms(100)), Some((Action::MoveLeft, 1)));
+        handling.press(Action::MoveRight);
+        assert_eq!(handling.update(ms(50)), None);
+        assert_eq!(handling.update(ms(50)), Some((Action::MoveRight, 1)));
+
+        // the left one is still held and charges again from the start
+        handling.release(Action::MoveRight);
+        assert_eq!(handling.update(ms(99)), None);
+        assert_eq!(handling.update(ms(1)), Some((Action::MoveLeft, 1)));
+
+        // releasing the older one leaves the newer one alone
+        handling.press(Action::MoveRight);
+        handling.release(Action::MoveLeft);
+        assert_eq!(handling.update(ms(100)), Some((Action::MoveRight, 1)));
+        handling.release(Action::MoveRight);
+        assert_eq!(handling.update(ms(1000)), None);
+    }
+}
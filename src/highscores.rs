@@ -0,0 +1,279 @@
+//! Local high score tables, one per game mode and ruleset, stored in
+//! `highscores.json` in the data directory.
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{fmt, fs, io};
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::config::data_dir;
+use crate::game_state::{GameMode, GameState, Ruleset};
+
+const FILE_NAME: &str = "highscores.json";
+
+/// Number of entries kept in every table
+pub const TABLE_SIZE: usize = 10;
+/// Longest name that can be entered
+pub const MAX_NAME_LENGTH: usize = 12;
+
+/// A finished game in a high score table
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub name: String,
+    /// Day the game was played, as `YYYY-MM-DD`
+    pub date: String,
+    pub score: u64,
+    /// Length of the game in milliseconds
+    pub time_ms: u64,
+    pub lines: u32,
+    pub level: u32,
+    /// Pieces placed per second
+    pub pps: f32,
+    /// Seed the pieces were dealt from
+    pub seed: u64,
+}
+
+impl Entry {
+    /// The entry a finished game would get, if it can be ranked at all.
+    /// Sprints only count when all lines were cleared, sandbox and versus
+    /// games and games the computer played in never count.
+    pub fn from_game(game: &GameState) -> Option<Self> {
+        if game.assisted() {
+            return None;
+        }
+        let counts = match game.mode() {
+            GameMode::Sprint => game.goal_reached(),
+            GameMode::Marathon | GameMode::Ultra => game.points() > 0,
+            GameMode::Sandbox | GameMode::Versus => false,
+        };
+        if !counts {
+            return None;
+        }
+        Some(Self {
+            name: String::new(),
+            date: today(),
+            score: game.points(),
+            time_ms: game.elapsed().as_millis() as u64,
+            lines: game.lines(),
+            level: game.stats().level(),
+            pps: game.stats().pieces_per_second(),
+            seed: game.seed(),
+        })
+    }
+
+    /// Sprints are ranked by time, the other modes by score
+    fn beats(&self, other: &Entry, mode: GameMode) -> bool {
+        match mode {
+            GameMode::Sprint => self.time_ms < other.time_ms,
+            GameMode::Marathon | GameMode::Ultra | GameMode::Sandbox | GameMode::Versus => {
+                self.score > other.score
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Table {
+    mode: GameMode,
+    ruleset: Ruleset,
+    entries: Vec<Entry>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HighScores {
+    /// Name entered for the last high score, offered for the next one
+    #[serde(default)]
+    pub last_name: String,
+    #[serde(default)]
+    tables: Vec<Table>,
+}
+
+/// Why the high scores could not be loaded or saved
+#[derive(Debug)]
+pub enum HighScoresError {
+    NoDataDir,
+    Io(io::Error),
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for HighScoresError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HighScoresError::NoDataDir => write!(f, "no data directory found"),
+            HighScoresError::Io(err) => write!(f, "{}", err),
+            HighScoresError::Json(err) => write!(f, "invalid high score file: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for HighScoresError {}
+
+impl HighScores {
+    pub fn path() -> Option<PathBuf> {
+        data_dir().map(|dir| dir.join(FILE_NAME))
+    }
+
+    /// Load the high scores, starting with empty tables when there are none
+    /// yet or the file cannot be read
+    pub fn load_or_default() -> Self {
+        match Self::load() {
+            Ok(scores) => scores,
+            Err(HighScoresError::Io(ref err)) if err.kind() == io::ErrorKind::NotFound => {
+                Self::default()
+            }
+            Err(err) => {
+                eprintln!("high scores: {}; starting with empty tables", err);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn load() -> Result<Self, HighScoresError> {
+        let path = Self::path().ok_or(HighScoresError::NoDataDir)?;
+        let text = fs::read_to_string(path).map_err(HighScoresError::Io)?;
+        serde_json::from_str(&text).map_err(HighScoresError::Json)
+    }
+
+    pub fn save(&self) -> Result<(), HighScoresError> {
+        let path = Self::path().ok_or(HighScoresError::NoDataDir)?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(HighScoresError::Io)?;
+        }
+        let text = serde_json::to_string_pretty(self).map_err(HighScoresError::Json)?;
+        fs::write(path, text).map_err(HighScoresError::Io)
+    }
+
+    /// Entries of a table, best first
+    pub fn table(&self, mode: GameMode, ruleset: Ruleset) -> &[Entry] {
+        self.tables
+            .iter()
+            .find(|table| table.mode == mode && table.ruleset == ruleset)
+            .map(|table| table.entries.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Position the entry would take in its table, `None` if it is not good
+    /// enough. Ties go below the entries already in the table.
+    pub fn rank(&self, mode: GameMode, ruleset: Ruleset, entry: &Entry) -> Option<usize> {
+        let entries = self.table(mode, ruleset);
+        let rank = entries
+            .iter()
+            .position(|other| entry.beats(other, mode))
+            .unwrap_or(entries.len());
+        if rank < TABLE_SIZE {
+            Some(rank)
+        } else {
+            None
+        }
+    }
+
+    /// Add the entry to its table, dropping the last one if the table is
+    /// full. Returns the position the entry got.
+    pub fn insert(&mut self, mode: GameMode, ruleset: Ruleset, entry: Entry) -> Option<usize> {
+        let rank = self.rank(mode, ruleset, &entry)?;
+        let index = match self
+            .tables
+            .iter()
+            .position(|table| table.mode == mode && table.ruleset == ruleset)
+        {
+            Some(index) => index,
+            None => {
+                self.tables.push(Table {
+                    mode,
+                    ruleset,
+                    entries: Vec::new(),
+                });
+                self.tables.len() - 1
+            }
+        };
+        let entries = &mut self.tables[index].entries;
+        entries.insert(rank, entry);
+        entries.truncate(TABLE_SIZE);
+        Some(rank)
+    }
+}
+
+/// Today's date in UTC as `YYYY-MM-DD`
+pub fn today() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Turn days since 1970-01-01 into a year, month and day of the Gregorian
+/// calendar. This is Howard Hinnant's `civil_from_days` algorithm.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = if z >= 0 { z } else { z - 146_096 } / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(score: u64, time_ms: u64) -> Entry {
+        Entry {
+            name: "test".to_string(),
+            date: "2000-01-01".to_string(),
+            score,
+            time_ms,
+            lines: 0,
+            level: 1,
+            pps: 1.0,
+            seed: 0,
+        }
+    }
+
+    #[test]
+    fn test_ranking() {
+        let mut scores = HighScores::default();
+        for score in 1..=TABLE_SIZE as u64 {
+            scores.insert(GameMode::Marathon, Ruleset::Classic, entry(score * 10, 0));
+        }
+        let table = scores.table(GameMode::Marathon, Ruleset::Classic);
+        assert_eq!(table.len(), TABLE_SIZE);
+        assert_eq!(table[0].score, 100);
+
+        // ties rank below, the worst entry falls off a full table
+        let rank = scores.insert(GameMode::Marathon, Ruleset::Classic, entry(50, 0));
+        assert_eq!(rank, Some(6));
+        let table = scores.table(GameMode::Marathon, Ruleset::Classic);
+        assert_eq!(table.len(), TABLE_SIZE);
+        assert_eq!(table[TABLE_SIZE - 1].score, 20);
+        assert_eq!(
+            scores.rank(GameMode::Marathon, Ruleset::Classic, &entry(20, 0)),
+            None
+        );
+
+        // sprints rank by time and every table is separate
+        assert_eq!(
+            scores.insert(GameMode::Sprint, Ruleset::Classic, entry(0, 60_000)),
+            Some(0)
+        );
+        assert_eq!(
+            scores.rank(GameMode::Sprint, Ruleset::Classic, &entry(0, 50_000)),
+            Some(0)
+        );
+        assert!(scores
+            .table(GameMode::Sprint, Ruleset::Guideline)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_civil_from_days() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+        assert_eq!(civil_from_days(20_744), (2026, 10, 18));
+    }
+}
@@ -0,0 +1,76 @@
+//! The game's rules and its frontends. The window, opened by `run`, and the
+//! terminal frontend in `src/bin/tetris-tui.rs` play on the same engine
+//! modules.
+use ggez::{event, GameResult};
+
+pub mod action;
+mod bindings;
+pub mod bitboard;
+pub mod bot;
+pub mod broadcast;
+pub mod config;
+pub mod diagram;
+pub mod finesse;
+pub mod fumen;
+pub mod game_state;
+pub mod garbage;
+pub mod handling;
+pub mod highscores;
+pub mod movegen;
+pub mod netplay;
+pub mod randomizer;
+pub mod replay;
+mod scenes;
+pub mod settings;
+pub mod simulation;
+pub mod stats;
+pub mod suspend;
+pub mod tbp;
+pub mod tetromino;
+pub mod theme;
+pub mod tuner;
+
+use crate::tetromino::{GRID_CELL_SIZE, GRID_SIZE};
+use game_state::{GameMode, Setup};
+use scenes::{apply_window_settings, Online, Playing, SceneStack, Title};
+use settings::Settings;
+
+const SCREEN_SIZE: (f32, f32) = (
+    (GRID_SIZE.0 as f32 + 6.5) * GRID_CELL_SIZE.0 as f32,
+    GRID_SIZE.1 as f32 * GRID_CELL_SIZE.1 as f32,
+);
+/// Two boards side by side, for versus games
+const VERSUS_SCREEN_SIZE: (f32, f32) = (2.0 * SCREEN_SIZE.0, SCREEN_SIZE.1);
+
+/// What the window shows first
+pub enum Start {
+    Title,
+    /// A sandbox game from a set up position
+    Sandbox(Setup),
+    /// A versus game over the network, hosted at the address
+    Host(String),
+    /// A versus game over the network, joining the host at the address
+    Join(String),
+}
+
+/// Open the game window and run until it is closed
+pub fn run(start: Start) -> GameResult {
+    let (ctx, events_loop) = &mut ggez::ContextBuilder::new("tetris", "me")
+        .window_setup(ggez::conf::WindowSetup::default().title("Tetris!"))
+        .window_mode(ggez::conf::WindowMode::default().dimensions(SCREEN_SIZE.0, SCREEN_SIZE.1))
+        .build()?;
+
+    let settings = Settings::load_or_default();
+    apply_window_settings(ctx, &settings, SCREEN_SIZE)?;
+
+    let first: Box<dyn scenes::Scene> = match start {
+        Start::Title => Box::new(Title::new()),
+        Start::Sandbox(ref setup) => {
+            Box::new(Playing::new(GameMode::Sandbox, &settings, Some(setup)))
+        }
+        Start::Host(ref address) => Box::new(Online::host(address, &settings)),
+        Start::Join(ref address) => Box::new(Online::join(address, &settings)),
+    };
+    let state = &mut SceneStack::new(first, settings);
+    event::run(ctx, events_loop, state)
+}
@@ -1,21 +1,57 @@
-use ggez::{event, GameResult};
+use std::path::Path;
+use std::{fs, process};
 
-mod game_state;
-mod tetromino;
+use ggez::GameResult;
 
-use crate::tetromino::{GRID_CELL_SIZE, GRID_SIZE};
-use game_state::*;
-const SCREEN_SIZE: (f32, f32) = (
-    (GRID_SIZE.0 as f32 + 6.5) * GRID_CELL_SIZE.0 as f32,
-    GRID_SIZE.1 as f32 * GRID_CELL_SIZE.1 as f32,
-);
+use tetris_ggez::game_state::Setup;
+use tetris_ggez::replay::Replay;
+use tetris_ggez::{diagram, fumen, Start};
 
-fn main() -> GameResult {
-    let (ctx, events_loop) = &mut ggez::ContextBuilder::new("tetris", "me")
-        .window_setup(ggez::conf::WindowSetup::default().title("Tetris!"))
-        .window_mode(ggez::conf::WindowMode::default().dimensions(SCREEN_SIZE.0, SCREEN_SIZE.1))
-        .build()?;
+const USAGE: &str =
+    "usage: tetris [--fumen <fumen> | --board <diagram file> | --replay-fumen <replay file>
+    | --host <address:port> | --join <address:port>]";
+
+fn exit_with(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(2)
+}
 
-    let state = &mut GameState::new();
-    event::run(ctx, events_loop, state)
+/// Read the command line. A fumen or board diagram starts a sandbox game
+/// from its position, a replay is written out as fumen without starting the
+/// game, and an address hosts or joins a versus game over the network.
+fn parse_args() -> Start {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match (args.first().map(String::as_str), args.get(1), args.len()) {
+        (None, _, _) => Start::Title,
+        (Some("--fumen"), Some(text), 2) => match fumen::decode_setup(text) {
+            Ok(setup) => Start::Sandbox(setup),
+            Err(err) => exit_with(&format!("fumen: {}", err)),
+        },
+        (Some("--board"), Some(path), 2) => {
+            let diagram = fs::read_to_string(path)
+                .map_err(|err| err.to_string())
+                .and_then(|text| diagram::parse(&text).map_err(|err| err.to_string()))
+                .unwrap_or_else(|err| exit_with(&format!("board {}: {}", path, err)));
+            // the falling piece of the diagram comes first
+            Start::Sandbox(Setup {
+                board: diagram.board,
+                hold: None,
+                queue: diagram.piece.iter().map(|piece| piece.shape()).collect(),
+            })
+        }
+        (Some("--replay-fumen"), Some(path), 2) => match Replay::load(Path::new(path)) {
+            Ok(replay) => {
+                println!("{}", replay.to_fumen());
+                process::exit(0)
+            }
+            Err(err) => exit_with(&format!("replay {}: {}", path, err)),
+        },
+        (Some("--host"), Some(address), 2) => Start::Host(address.clone()),
+        (Some("--join"), Some(address), 2) => Start::Join(address.clone()),
+        _ => exit_with(USAGE),
+    }
+}
+
+fn main() -> GameResult {
+    tetris_ggez::run(parse_args())
 }
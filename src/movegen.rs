@@ -0,0 +1,455 @@
+//! Finds every place the current piece can be locked in, including tucks
+//! under overhangs and spins, with the shortest sequence of inputs that
+//! gets it there.
+//!
+//! The search moves the piece by the rules of `bitboard::Board`, the same
+//! the game plays by, on the game's own stack: pieces only move sideways
+//! once all of them is inside the well, rotate around their second cell
+//! and are pushed back in from the walls, and stop falling on the stack.
+//! Paths start from where the piece is now, gravity is not simulated while
+//! the inputs are made.
+//!
+//! `fewest_presses` searches the same way but counts key presses instead
+//! of steps, holding a direction until the piece stops being one press, to
+//! tell how many keys a player needed to place a piece.
+use std::collections::{HashSet, VecDeque};
+
+use crate::action::Action;
+use crate::bitboard::{Board, Cells};
+use crate::game_state::GameState;
+use crate::tetromino::{Motion, Shape, Tetromino};
+
+/// Rows a piece reaches above its spawn position when rotated, it has to
+/// be this far down for every rotation to leave it movable
+pub(crate) const ROTATION_MARGIN: i16 = 2;
+
+/// One input of a path
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Step {
+    Left,
+    Right,
+    RotateCw,
+    RotateCcw,
+    Rotate180,
+    /// Hold a direction until the piece stops at a wall or the stack
+    DasLeft,
+    DasRight,
+    /// Let the piece fall until it lands, without locking it
+    SoftDrop,
+    HardDrop,
+}
+
+impl Step {
+    /// Steps tried from every position, in the order ties are broken
+    const MOVES: [Step; 6] = [
+        Step::Left,
+        Step::Right,
+        Step::RotateCw,
+        Step::RotateCcw,
+        Step::Rotate180,
+        Step::SoftDrop,
+    ];
+
+    /// Steps a single key press makes, in the order ties are broken
+    const PRESSES: [Step; 8] = [
+        Step::DasLeft,
+        Step::DasRight,
+        Step::Left,
+        Step::Right,
+        Step::RotateCw,
+        Step::RotateCcw,
+        Step::Rotate180,
+        Step::SoftDrop,
+    ];
+
+    /// Whether the step turns the piece, the last one that moves it has to
+    /// for a T-spin
+    pub fn rotates(self) -> bool {
+        matches!(self, Step::RotateCw | Step::RotateCcw | Step::Rotate180)
+    }
+
+    /// The action that makes the step. Soft drops and the auto shifted
+    /// moves are held until the piece stops, the others are tapped.
+    pub fn action(self) -> Action {
+        match self {
+            Step::Left | Step::DasLeft => Action::MoveLeft,
+            Step::Right | Step::DasRight => Action::MoveRight,
+            Step::RotateCw => Action::RotateCw,
+            Step::RotateCcw => Action::RotateCcw,
+            Step::Rotate180 => Action::Rotate180,
+            Step::SoftDrop => Action::SoftDrop,
+            Step::HardDrop => Action::HardDrop,
+        }
+    }
+}
+
+/// A place the current piece can be locked in
+#[derive(Debug, Clone)]
+pub struct Placement {
+    /// The piece where it locks
+    pub piece: Tetromino,
+    /// Shortest inputs that lock the piece there, ending with a hard drop
+    pub path: Vec<Step>,
+    /// Locking the piece there scores a T-spin
+    pub tspin: bool,
+}
+
+impl Placement {
+    /// The path as the actions of a bot's plan. Where it soft drops, soft
+    /// drop has to be held until the piece lands.
+    pub fn actions(&self) -> Vec<Action> {
+        self.path.iter().map(|step| step.action()).collect()
+    }
+}
+
+/// A position of the piece during the search
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Node {
+    cells: Cells,
+    /// The last step that moved the piece was a rotation
+    rotated: bool,
+}
+
+/// Every distinct place the current piece can be locked in from where it
+/// is now, by any sequence of moves, rotations and soft drops followed by
+/// a hard drop. Places are told apart by the cells of the piece and by
+/// whether they score a T-spin. Placements come in the order of the length
+/// of their paths.
+pub fn placements(game: &GameState) -> Vec<Placement> {
+    if game.is_over() {
+        return Vec::new();
+    }
+    search(game.board(), game.current(), &Step::MOVES)
+}
+
+/// Every distinct place a piece of the shape can be locked in on the
+/// game's stack, from where it has fallen far enough into the well to be
+/// moved anywhere. Bots use it to look at a piece that has only just
+/// spawned, or at the next one.
+pub fn placements_of(game: &GameState, shape: Shape) -> Vec<Placement> {
+    if game.is_over() {
+        return Vec::new();
+    }
+    search(game.board(), &lowered(game.board(), shape), &Step::MOVES)
+}
+
+/// Every distinct place a piece of the shape can be locked in on the
+/// game's stack, with the fewest key presses that get it there from where
+/// it spawns. The piece starts from where it has fallen far enough into
+/// the well to be moved anywhere, as the player can only move it from
+/// there.
+pub fn fewest_presses(game: &GameState, shape: Shape) -> Vec<Placement> {
+    if game.is_over() {
+        return Vec::new();
+    }
+    search(game.board(), &lowered(game.board(), shape), &Step::PRESSES)
+}
+
+/// A new piece of the shape, fallen until it can be moved anywhere or
+/// lands
+fn lowered(board: &Board, shape: Shape) -> Tetromino {
+    let mut piece = Tetromino::from(shape);
+    while piece.body.iter().any(|seg| seg.y < ROTATION_MARGIN) && !board.landed(&piece.cells()) {
+        piece.update();
+    }
+    piece
+}
+
+fn search(board: &Board, piece: &Tetromino, moves: &[Step]) -> Vec<Placement> {
+    let shape = piece.shape();
+    let start = Node {
+        cells: piece.cells(),
+        rotated: false,
+    };
+
+    // breadth first, so every node is reached by a shortest path first
+    let mut nodes = vec![(start, None)];
+    let mut seen = HashSet::new();
+    seen.insert(start);
+    let mut queue = VecDeque::new();
+    queue.push_back(0);
+    let mut found = HashSet::new();
+    let mut placements = Vec::new();
+    while let Some(index) = queue.pop_front() {
+        let node = nodes[index].0;
+        if !board.hit_ceiling(&node.cells) {
+            let cells = board.fall(&node.cells);
+            let tspin = shape == Shape::T && node.rotated && board.tspin_corners(&cells);
+            let mut sorted = cells;
+            sorted.sort();
+            if found.insert((sorted, tspin)) {
+                let mut path = path_to(&nodes, index);
+                path.push(Step::HardDrop);
+                let mut piece = piece.clone();
+                piece.set_cells(&cells);
+                placements.push(Placement { piece, path, tspin });
+            }
+        }
+
+        for &step in moves {
+            let cells = match step {
+                Step::Left => board.shift(&node.cells, -1),
+                Step::Right => board.shift(&node.cells, 1),
+                Step::DasLeft => slide(board, &node.cells, -1),
+                Step::DasRight => slide(board, &node.cells, 1),
+                Step::RotateCw => board.rotate(shape, &node.cells, Motion::RotateCw),
+                Step::RotateCcw => board.rotate(shape, &node.cells, Motion::RotateCcw),
+                Step::Rotate180 => board.rotate(shape, &node.cells, Motion::Rotate180),
+                Step::SoftDrop | Step::HardDrop => board.fall(&node.cells),
+            };
+            if cells == node.cells {
+                continue;
+            }
+            let next = Node {
+                cells,
+                rotated: step.rotates(),
+            };
+            if seen.insert(next) {
+                queue.push_back(nodes.len());
+                nodes.push((next, Some((index, step))));
+            }
+        }
+    }
+    placements
+}
+
+/// Hold a direction until the piece stops at a wall or the stack
+fn slide(board: &Board, cells: &Cells, dx: i16) -> Cells {
+    let mut cells = *cells;
+    loop {
+        let moved = board.shift(&cells, dx);
+        if moved == cells {
+            return cells;
+        }
+        cells = moved;
+    }
+}
+
+fn path_to(nodes: &[(Node, Option<(usize, Step)>)], mut index: usize) -> Vec<Step> {
+    let mut path = Vec::new();
+    while let Some((parent, step)) = nodes[index].1 {
+        path.push(step);
+        index = parent;
+    }
+    path.reverse();
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::ready;
+    use crate::diagram;
+    use crate::game_state::{GameMode, Setup};
+    use crate::settings::Settings;
+    use crate::simulation::TICK;
+    use crate::tetromino::{Segment, GARBAGE_COLOR, GRID_SIZE};
+    use std::time::Duration;
+
+    /// A game with the given stack and the piece lowered far enough to be
+    /// moved anywhere. The lock delay is long enough for soft drops.
+    fn game_on(board: Vec<Segment>, shape: Shape) -> GameState {
+        let setup = Setup {
+            board,
+            hold: None,
+            queue: vec![shape],
+        };
+        let settings = Settings {
+            lock_delay_ms: 2000,
+            ..Settings::default()
+        };
+        let mut game = GameState::with_setup(GameMode::Sandbox, &settings, 1, &setup);
+        while !ready(&game) {
+            game.update(TICK);
+        }
+        game
+    }
+
+    fn board(text: &str) -> Vec<Segment> {
+        diagram::parse(text).unwrap().board
+    }
+
+    /// A ragged stack of garbage with holes and overhangs, from a seed
+    fn garbage(seed: u32) -> Vec<Segment> {
+        let mut state = seed;
+        let mut board = Vec::new();
+        for y in GRID_SIZE.1 - 7..GRID_SIZE.1 {
+            for x in 0..GRID_SIZE.0 {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                // sparser towards the top
+                if (state >> 16) % 10 < (y - GRID_SIZE.1 + 10) as u32 {
+                    board.push(Segment::new((x, y), GARBAGE_COLOR));
+                }
+            }
+        }
+        board
+    }
+
+    fn landed(game: &GameState) -> bool {
+        game.drop_position().body == game.current().body
+    }
+
+    /// Make a step in the real game. Soft drops let gravity pull the piece
+    /// down a row at a time until it lands.
+    fn perform(game: &mut GameState, step: Step) {
+        match step {
+            Step::SoftDrop => {
+                while !landed(game) {
+                    game.update(Duration::from_secs(1));
+                }
+            }
+            _ => game.perform(step.action()),
+        }
+    }
+
+    fn sorted(piece: &Tetromino) -> Vec<(i16, i16)> {
+        let mut cells: Vec<(i16, i16)> = piece.body.iter().map(|seg| (seg.x, seg.y)).collect();
+        cells.sort();
+        cells
+    }
+
+    /// The placements found by trying every step on copies of the game
+    /// itself, far too slow to play with but following the rules by
+    /// definition
+    fn reference(game: &GameState) -> HashSet<(Vec<(i16, i16)>, bool)> {
+        let mut found = HashSet::new();
+        let mut seen = HashSet::new();
+        let mut queue = VecDeque::new();
+        queue.push_back((game.clone(), false));
+        while let Some((game, rotated)) = queue.pop_front() {
+            if !seen.insert((game.current().body.clone(), rotated)) {
+                continue;
+            }
+            let mut dropped = game.clone();
+            let cells = sorted(&dropped.drop_position());
+            let tspins = dropped.stats().tspins;
+            dropped.perform(Action::HardDrop);
+            if dropped.stats().pieces > game.stats().pieces {
+                found.insert((cells, dropped.stats().tspins > tspins));
+            }
+            for &step in Step::MOVES.iter() {
+                let mut next = game.clone();
+                perform(&mut next, step);
+                if next.current().body != game.current().body {
+                    queue.push_back((next, step.rotates()));
+                }
+            }
+        }
+        found
+    }
+
+    /// T-spin double
+    const TSD: &str = "
+        ...G......
+        GGG...GGGG
+        GGGG.GGGGG";
+
+    /// Room under an overhang on both sides
+    const OVERHANGS: &str = "
+        GGG....GGG
+        ..........
+        ..........";
+
+    #[test]
+    fn test_matches_engine() {
+        let deep_well = "GGGG..GGGG\n".repeat(12) + "GGG...GGGG";
+        let mut boards = vec![Vec::new(), board(TSD), board(OVERHANGS), board(&deep_well)];
+        boards.extend((1..6).map(garbage));
+        for board in boards {
+            for &shape in Shape::ALL.iter() {
+                let game = game_on(board.clone(), shape);
+                let placements = placements(&game);
+                let found: HashSet<(Vec<(i16, i16)>, bool)> = placements
+                    .iter()
+                    .map(|placement| (sorted(&placement.piece), placement.tspin))
+                    .collect();
+                assert_eq!(found.len(), placements.len());
+                assert_eq!(found, reference(&game), "{:?} in {:?}", shape, game);
+
+                // every path leads where it says
+                for placement in placements.iter() {
+                    let mut played = game.clone();
+                    let (drop, path) = placement.path.split_last().unwrap();
+                    assert_eq!(*drop, Step::HardDrop);
+                    for &step in path {
+                        perform(&mut played, step);
+                    }
+                    assert_eq!(sorted(&played.drop_position()), sorted(&placement.piece));
+                    let tspins = played.stats().tspins;
+                    played.perform(Action::HardDrop);
+                    assert_eq!(played.stats().tspins > tspins, placement.tspin);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_shortest_paths() {
+        let game = game_on(Vec::new(), Shape::O);
+        let placements = placements(&game);
+        assert_eq!(placements.len(), 9);
+        assert_eq!(placements[0].path, vec![Step::HardDrop]);
+        let left = placements
+            .iter()
+            .find(|placement| placement.piece.body.iter().any(|seg| seg.x == 0))
+            .unwrap();
+        let steps = game.current().body.iter().map(|seg| seg.x).min().unwrap();
+        let mut path = vec![Step::Left; steps as usize];
+        path.push(Step::HardDrop);
+        assert_eq!(left.path, path);
+    }
+
+    #[test]
+    fn test_fewest_presses() {
+        let game = game_on(Vec::new(), Shape::O);
+        let placements = fewest_presses(&game, Shape::O);
+        assert_eq!(placements.len(), 9);
+        let left = placements
+            .iter()
+            .find(|placement| placement.piece.body.iter().any(|seg| seg.x == 0))
+            .unwrap();
+        assert_eq!(left.path, vec![Step::DasLeft, Step::HardDrop]);
+        let longest = placements.iter().map(|placement| placement.path.len());
+        assert_eq!(longest.max(), Some(3));
+
+        // the search starts from a fresh piece, wherever the current one is
+        let mut moved = game.clone();
+        moved.perform(Action::MoveRight);
+        let cells = |placements: Vec<Placement>| -> Vec<_> {
+            placements
+                .iter()
+                .map(|placement| sorted(&placement.piece))
+                .collect()
+        };
+        assert_eq!(cells(fewest_presses(&moved, Shape::O)), cells(placements));
+        // and so does the search for a piece that has only just spawned
+        assert_eq!(
+            cells(placements_of(&moved, Shape::O)),
+            cells(super::placements(&game))
+        );
+    }
+
+    #[test]
+    fn test_spins_and_tucks() {
+        let game = game_on(board(TSD), Shape::T);
+        let tspin = placements(&game)
+            .into_iter()
+            .find(|placement| sorted(&placement.piece) == vec![(3, 18), (4, 18), (4, 19), (5, 18)])
+            .unwrap();
+        assert!(tspin.tspin);
+        let mut played = game.clone();
+        for &step in tspin.path.iter() {
+            perform(&mut played, step);
+        }
+        assert_eq!(played.lines(), 2);
+
+        // the far left corner is only reached by sliding under the overhang
+        let game = game_on(board(OVERHANGS), Shape::O);
+        let tuck = placements(&game)
+            .into_iter()
+            .find(|placement| sorted(&placement.piece)[0] == (0, GRID_SIZE.1 - 2))
+            .unwrap();
+        let soft_drop = tuck.path.iter().position(|&step| step == Step::SoftDrop);
+        assert!(tuck.path[soft_drop.unwrap() + 1..].contains(&Step::Left));
+    }
+}
@@ -0,0 +1,653 @@
+//! Versus games over the network. Two copies of the game connect directly
+//! over TCP, one hosting and the other joining by address, and play in
+//! lockstep: both sides simulate both boards, and a frame is only simulated
+//! once the inputs of both players for it are known. Messages are JSON
+//! objects, one per line:
+//!
+//! 1. the guest sends `hello` with the settings its board is played with
+//! 2. the host answers `start` with the seed, the ruleset both boards are
+//!    played by and the settings of its own board
+//! 3. both send their `inputs` for every frame, `INPUT_DELAY` frames ahead
+//!    of the one they simulate so the other side rarely waits for them
+//! 4. after simulating a frame, each side reports the `garbage` its own
+//!    board sent and, every `CHECKSUM_INTERVAL` frames and when the game
+//!    ends, a `checksum` of that board. The other side compares them with
+//!    its copy of the board, any difference is a desync.
+//! 5. `leave` tells the other side the player is gone
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::time::{Duration, Instant};
+use std::{fmt, mem, thread};
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::finesse::Trainer;
+use crate::game_state::{GameMode, GameState, Ruleset};
+use crate::settings::Settings;
+use crate::simulation::{Input, Simulation};
+use crate::tetromino::Shape;
+
+/// Both sides must speak the same version, and run the same rules
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Frames between pressing a key and the frame it is applied in, on both
+/// boards. Three frames hide a round trip of about 50 ms.
+pub const INPUT_DELAY: u32 = 3;
+
+/// Frames between two checksums of a board
+pub const CHECKSUM_INTERVAL: u32 = 60;
+
+/// How long the end of a game waits for the opponent's last reports
+const FINISH_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The part of a player's settings that decides how their board plays and
+/// looks, all the other side is told about them
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BoardSettings {
+    pub das_ms: u32,
+    pub arr_ms: u32,
+    pub soft_drop_factor: u32,
+    pub lock_delay_ms: u32,
+    pub ghost: bool,
+    pub next_queue: u32,
+    pub finesse_trainer: Trainer,
+    pub garbage_messiness: u32,
+}
+
+impl BoardSettings {
+    pub fn of(settings: &Settings) -> Self {
+        Self {
+            das_ms: settings.das_ms,
+            arr_ms: settings.arr_ms,
+            soft_drop_factor: settings.soft_drop_factor,
+            lock_delay_ms: settings.lock_delay_ms,
+            ghost: settings.ghost,
+            next_queue: settings.next_queue,
+            finesse_trainer: settings.finesse_trainer,
+            garbage_messiness: settings.garbage_messiness,
+        }
+    }
+
+    /// Settings to play the board with, the defaults for everything else
+    fn settings(&self, ruleset: Ruleset) -> Settings {
+        Settings {
+            das_ms: self.das_ms,
+            arr_ms: self.arr_ms,
+            soft_drop_factor: self.soft_drop_factor,
+            lock_delay_ms: self.lock_delay_ms,
+            ghost: self.ghost,
+            next_queue: self.next_queue,
+            finesse_trainer: self.finesse_trainer,
+            garbage_messiness: self.garbage_messiness,
+            ruleset,
+            ..Settings::default()
+        }
+    }
+}
+
+/// Messages both sides send
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Message {
+    Hello {
+        version: u32,
+        settings: BoardSettings,
+    },
+    Start {
+        version: u32,
+        seed: u64,
+        ruleset: Ruleset,
+        settings: BoardSettings,
+    },
+    /// The sender's inputs for a frame, as in replay files
+    Inputs {
+        frame: u32,
+        inputs: Vec<u8>,
+    },
+    /// Rows the sender's board sent in a frame
+    Garbage {
+        frame: u32,
+        rows: u32,
+    },
+    /// Checksum of the sender's board after a frame
+    Checksum {
+        frame: u32,
+        value: u64,
+    },
+    Leave,
+}
+
+#[derive(Debug)]
+pub enum NetError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    /// The connection was closed without a word
+    Closed,
+    /// The opponent left the game
+    Left,
+    /// The opponent speaks another version of the protocol
+    Version(u32),
+    Unexpected(Box<Message>),
+    /// The boards differ since this frame at the latest
+    Desync(u32),
+    /// The opponent stopped answering
+    TimedOut,
+}
+
+impl fmt::Display for NetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetError::Io(err) => write!(f, "{}", err),
+            NetError::Json(err) => write!(f, "invalid message: {}", err),
+            NetError::Closed => write!(f, "the connection was closed"),
+            NetError::Left => write!(f, "the opponent left"),
+            NetError::Version(version) => write!(
+                f,
+                "the opponent runs protocol version {}, this game {}",
+                version, PROTOCOL_VERSION
+            ),
+            NetError::Unexpected(message) => write!(f, "unexpected message {:?}", message),
+            NetError::Desync(frame) => write!(f, "the games went out of sync at frame {}", frame),
+            NetError::TimedOut => write!(f, "the opponent stopped answering"),
+        }
+    }
+}
+
+impl std::error::Error for NetError {}
+
+/// A checksum of what decides how a board plays on: the stack, the falling
+/// and held pieces, the score and the garbage on its way
+pub fn checksum(game: &GameState) -> u64 {
+    // FNV-1a, the same on every machine unlike the standard hasher
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut feed = |value: u64| {
+        for byte in value.to_le_bytes().iter() {
+            hash ^= u64::from(*byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    };
+    let shape = |shape: Option<Shape>| shape.map_or(0, |shape| shape.index() as u64 + 1);
+    let mut cells: Vec<(i16, i16, u64)> = game
+        .base()
+        .iter()
+        .map(|seg| (seg.x, seg.y, shape(Shape::from_color(seg.color))))
+        .collect();
+    cells.sort_unstable();
+    for (x, y, shape) in cells {
+        feed(x as u64);
+        feed(y as u64);
+        feed(shape);
+    }
+    for seg in game.current().clone_body() {
+        feed(seg.x as u64);
+        feed(seg.y as u64);
+    }
+    feed(shape(Some(game.current().shape())));
+    feed(shape(game.hold()));
+    feed(game.points());
+    feed(u64::from(game.lines()));
+    feed(u64::from(game.garbage().total()));
+    feed(u64::from(game.is_over()));
+    hash
+}
+
+/// What a side reports about its own board after a frame, compared with
+/// the other side's copy of it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Report {
+    Garbage { frame: u32, rows: u32 },
+    Checksum { frame: u32, value: u64 },
+}
+
+impl Report {
+    /// Reports about a board after the frame it was simulated in
+    fn of(frame: u32, sent: u32, sim: &Simulation) -> Vec<Report> {
+        let mut reports = Vec::new();
+        if sent > 0 {
+            reports.push(Report::Garbage { frame, rows: sent });
+        }
+        if frame.is_multiple_of(CHECKSUM_INTERVAL) || sim.game().is_over() {
+            let value = checksum(sim.game());
+            reports.push(Report::Checksum { frame, value });
+        }
+        reports
+    }
+
+    fn frame(self) -> u32 {
+        match self {
+            Report::Garbage { frame, .. } | Report::Checksum { frame, .. } => frame,
+        }
+    }
+
+    fn message(self) -> Message {
+        match self {
+            Report::Garbage { frame, rows } => Message::Garbage { frame, rows },
+            Report::Checksum { frame, value } => Message::Checksum { frame, value },
+        }
+    }
+}
+
+/// A connection to the other side. Messages are read on a thread of their
+/// own, so the game can check for them without blocking.
+struct Connection {
+    stream: TcpStream,
+    messages: Receiver<Result<Message, NetError>>,
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> Result<Self, NetError> {
+        stream.set_nodelay(true).map_err(NetError::Io)?;
+        let reader = BufReader::new(stream.try_clone().map_err(NetError::Io)?);
+        let (sender, messages) = mpsc::channel();
+        thread::spawn(move || {
+            for line in reader.lines() {
+                let message = match line {
+                    Ok(ref line) if line.trim().is_empty() => continue,
+                    Ok(line) => serde_json::from_str(&line).map_err(NetError::Json),
+                    Err(err) => Err(NetError::Io(err)),
+                };
+                let failed = message.is_err();
+                if sender.send(message).is_err() || failed {
+                    return;
+                }
+            }
+            let _ = sender.send(Err(NetError::Closed));
+        });
+        Ok(Self { stream, messages })
+    }
+
+    fn send(&mut self, message: &Message) -> Result<(), NetError> {
+        let line = serde_json::to_string(message).map_err(NetError::Json)?;
+        writeln!(self.stream, "{}", line)
+            .and_then(|_| self.stream.flush())
+            .map_err(NetError::Io)
+    }
+
+    /// Wait for the next message
+    fn receive(&mut self) -> Result<Message, NetError> {
+        self.messages.recv().unwrap_or(Err(NetError::Closed))
+    }
+
+    /// The next message if one has arrived
+    fn poll(&mut self) -> Option<Result<Message, NetError>> {
+        match self.messages.try_recv() {
+            Ok(message) => Some(message),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(NetError::Closed)),
+        }
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        // also ends the reading thread
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+/// A versus game with a player on the other end of a connection. The host
+/// is player 1 and the guest player 2 on both sides.
+pub struct Session {
+    connection: Connection,
+    /// Index of the player at this computer
+    local: usize,
+    players: [Simulation; 2],
+    /// Next frame to simulate
+    frame: u32,
+    /// Inputs of both players for the frames from `frame` on, as far as
+    /// they are known
+    inputs: [VecDeque<Vec<Input>>; 2],
+    /// Local inputs for the next frame sent
+    pending: Vec<Input>,
+    /// Reports of the opponent about its board, and what this side's copy
+    /// of it shows, not compared yet
+    reported: VecDeque<Report>,
+    expected: VecDeque<Report>,
+    /// When `finish` was first asked, to give up waiting at some point
+    finishing: Option<Instant>,
+    left: bool,
+}
+
+impl Session {
+    /// Start a game with a player that connected to this side's listener,
+    /// with a new seed and this side's ruleset
+    pub fn host(stream: TcpStream, settings: &Settings) -> Result<Self, NetError> {
+        // accepted from a non-blocking listener the stream may be one too
+        stream.set_nonblocking(false).map_err(NetError::Io)?;
+        let mut connection = Connection::new(stream)?;
+        let (version, guest) = match connection.receive()? {
+            Message::Hello { version, settings } => (version, settings),
+            other => return Err(NetError::Unexpected(Box::new(other))),
+        };
+        let seed = rand::random();
+        let own = BoardSettings::of(settings);
+        connection.send(&Message::Start {
+            version: PROTOCOL_VERSION,
+            seed,
+            ruleset: settings.ruleset,
+            settings: own.clone(),
+        })?;
+        if version != PROTOCOL_VERSION {
+            return Err(NetError::Version(version));
+        }
+        Ok(Self::new(
+            connection,
+            0,
+            seed,
+            settings.ruleset,
+            [own, guest],
+        ))
+    }
+
+    /// Join the game hosted at the address
+    pub fn join<A: ToSocketAddrs>(address: A, settings: &Settings) -> Result<Self, NetError> {
+        let stream = TcpStream::connect(address).map_err(NetError::Io)?;
+        let mut connection = Connection::new(stream)?;
+        let own = BoardSettings::of(settings);
+        connection.send(&Message::Hello {
+            version: PROTOCOL_VERSION,
+            settings: own.clone(),
+        })?;
+        match connection.receive()? {
+            Message::Start { version, .. } if version != PROTOCOL_VERSION => {
+                Err(NetError::Version(version))
+            }
+            Message::Start {
+                seed,
+                ruleset,
+                settings: host,
+                ..
+            } => Ok(Self::new(connection, 1, seed, ruleset, [host, own])),
+            other => Err(NetError::Unexpected(Box::new(other))),
+        }
+    }
+
+    fn new(
+        connection: Connection,
+        local: usize,
+        seed: u64,
+        ruleset: Ruleset,
+        settings: [BoardSettings; 2],
+    ) -> Self {
+        // every player keeps their own handling, the rules are the host's
+        let player = |settings: &BoardSettings| {
+            Simulation::new(GameMode::Versus, &settings.settings(ruleset), seed)
+        };
+        // nobody pressed anything in the frames before the first inputs
+        // arrive
+        let delay = || (0..INPUT_DELAY).map(|_| Vec::new()).collect();
+        Self {
+            connection,
+            local,
+            players: [player(&settings[0]), player(&settings[1])],
+            frame: 0,
+            inputs: [delay(), delay()],
+            pending: Vec::new(),
+            reported: VecDeque::new(),
+            expected: VecDeque::new(),
+            finishing: None,
+            left: false,
+        }
+    }
+
+    /// Index of the player at this computer, 0 for the host
+    pub fn local(&self) -> usize {
+        self.local
+    }
+
+    fn remote(&self) -> usize {
+        1 - self.local
+    }
+
+    /// The game of a player
+    pub fn game(&self, player: usize) -> &GameState {
+        self.players[player].game()
+    }
+
+    /// Number of frames simulated so far
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.players.iter().any(|sim| sim.game().is_over())
+    }
+
+    /// An input of the local player, applied `INPUT_DELAY` frames from the
+    /// next one
+    pub fn input(&mut self, input: Input) {
+        self.pending.push(input);
+    }
+
+    /// Handle the messages that arrived, and simulate the next frame if
+    /// the opponent's inputs for it are there. Returns whether a frame was
+    /// simulated.
+    pub fn update(&mut self) -> Result<bool, NetError> {
+        while let Some(message) = self.connection.poll() {
+            self.handle(message?)?;
+        }
+        if self.is_over() || self.inputs[self.remote()].is_empty() {
+            return Ok(false);
+        }
+
+        let pending = mem::take(&mut self.pending);
+        self.connection.send(&Message::Inputs {
+            frame: self.frame + INPUT_DELAY,
+            inputs: pending.iter().map(|input| input.code()).collect(),
+        })?;
+        self.inputs[self.local].push_back(pending);
+        for (sim, inputs) in self.players.iter_mut().zip(self.inputs.iter_mut()) {
+            for input in inputs.pop_front().unwrap_or_default() {
+                sim.apply(input);
+            }
+            sim.step();
+        }
+        let sent = [self.players[0].take_attack(), self.players[1].take_attack()];
+        self.players[0].receive_garbage(sent[1]);
+        self.players[1].receive_garbage(sent[0]);
+
+        let (local, remote) = (self.local, self.remote());
+        for report in Report::of(self.frame, sent[local], &self.players[local]) {
+            self.connection.send(&report.message())?;
+        }
+        let expected = Report::of(self.frame, sent[remote], &self.players[remote]);
+        self.expected.extend(expected);
+        self.frame += 1;
+        self.compare()?;
+        Ok(true)
+    }
+
+    /// Once the game is over, check the opponent's reports of the last
+    /// frames as they arrive. Returns whether all of them are checked, it
+    /// is asked again on later updates until they are, for up to
+    /// `FINISH_TIMEOUT`.
+    pub fn finish(&mut self) -> Result<bool, NetError> {
+        let since = *self.finishing.get_or_insert_with(Instant::now);
+        while let Some(message) = self.connection.poll() {
+            self.handle(message?)?;
+        }
+        self.compare()?;
+        if self.expected.is_empty() {
+            Ok(true)
+        } else if since.elapsed() >= FINISH_TIMEOUT {
+            Err(NetError::TimedOut)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Tell the opponent the player is gone, also done when the session is
+    /// dropped
+    pub fn leave(&mut self) {
+        if !self.left {
+            self.left = true;
+            let _ = self.connection.send(&Message::Leave);
+        }
+    }
+
+    fn handle(&mut self, message: Message) -> Result<(), NetError> {
+        let remote = self.remote();
+        match message {
+            Message::Inputs { frame, inputs }
+                if frame == self.frame + self.inputs[remote].len() as u32 =>
+            {
+                let inputs = inputs.into_iter().filter_map(Input::from_code).collect();
+                self.inputs[remote].push_back(inputs);
+            }
+            Message::Garbage { frame, rows } => {
+                self.reported.push_back(Report::Garbage { frame, rows })
+            }
+            Message::Checksum { frame, value } => {
+                self.reported.push_back(Report::Checksum { frame, value })
+            }
+            Message::Leave => {
+                self.left = true;
+                return Err(NetError::Left);
+            }
+            other => return Err(NetError::Unexpected(Box::new(other))),
+        }
+        Ok(())
+    }
+
+    /// Compare the reports both sides have made so far, in order
+    fn compare(&mut self) -> Result<(), NetError> {
+        while !self.reported.is_empty() && !self.expected.is_empty() {
+            let theirs = self.reported.pop_front().unwrap();
+            let ours = self.expected.pop_front().unwrap();
+            if theirs != ours {
+                return Err(NetError::Desync(theirs.frame().min(ours.frame())));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.leave();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action::Action;
+    use std::net::TcpListener;
+
+    /// A host and a guest connected over localhost
+    fn pair() -> (Session, Session) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let host = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            Session::host(stream, &Settings::default())
+        });
+        let guest = Session::join(address, &Settings::default()).unwrap();
+        (host.join().unwrap().unwrap(), guest)
+    }
+
+    /// Play both sides up to a frame, the host dropping a piece every
+    /// half second and the guest moving every piece left first. Stops at
+    /// the first error of either side.
+    fn play(sessions: &mut [Session; 2], frames: u32) -> Result<(), NetError> {
+        // the frame inputs were last given in, a side that waits for the
+        // other is updated again in the same frame
+        let mut last = [u32::MAX; 2];
+        while sessions.iter().any(|s| s.frame() < frames && !s.is_over()) {
+            for (player, session) in sessions.iter_mut().enumerate() {
+                let frame = session.frame();
+                if frame >= frames || session.is_over() {
+                    continue;
+                }
+                let period = if player == 0 { 30 } else { 20 };
+                if last[player] != frame && frame % period == period - 1 {
+                    last[player] = frame;
+                    if player == 1 {
+                        session.input(Input::Press(Action::MoveLeft));
+                        session.input(Input::Release(Action::MoveLeft));
+                    }
+                    session.input(Input::Press(Action::HardDrop));
+                    session.input(Input::Release(Action::HardDrop));
+                }
+                session.update()?;
+            }
+            thread::yield_now();
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_lockstep() {
+        let (host, guest) = pair();
+        assert_eq!((host.local(), guest.local()), (0, 1));
+        assert_eq!(host.game(0).seed(), guest.game(0).seed());
+        let mut sessions = [host, guest];
+        play(&mut sessions, 600).unwrap();
+
+        let [host, guest] = &mut sessions;
+        assert_eq!(host.frame(), guest.frame());
+        assert!(host.game(0).stats().pieces > 5);
+        for player in 0..2 {
+            assert_eq!(checksum(host.game(player)), checksum(guest.game(player)));
+        }
+        for session in sessions.iter_mut() {
+            while !session.finish().unwrap() {
+                thread::yield_now();
+            }
+        }
+    }
+
+    #[test]
+    fn test_only_board_settings_are_sent() {
+        let settings = Settings {
+            das_ms: 100,
+            bot_command: "secret-bot --token 1234".to_string(),
+            spectator_address: "10.0.0.1:7462".to_string(),
+            ..Settings::default()
+        };
+        let hello = Message::Hello {
+            version: PROTOCOL_VERSION,
+            settings: BoardSettings::of(&settings),
+        };
+        let line = serde_json::to_string(&hello).unwrap();
+        assert!(!line.contains("secret-bot") && !line.contains("10.0.0.1"));
+        let board = BoardSettings::of(&settings).settings(Ruleset::Classic);
+        assert_eq!((board.das_ms, board.ruleset), (100, Ruleset::Classic));
+        assert!(board.bot_command.is_empty());
+    }
+
+    #[test]
+    fn test_desync() {
+        let (host, guest) = pair();
+        let mut sessions = [host, guest];
+        play(&mut sessions, 30).unwrap();
+        // the guest's copy of the host's board drops a piece the host did not
+        sessions[1].players[0].apply(Input::Press(Action::HardDrop));
+        match play(&mut sessions, 30 + 2 * CHECKSUM_INTERVAL) {
+            Err(NetError::Desync(frame)) => assert!(frame <= 30 + CHECKSUM_INTERVAL),
+            other => panic!("expected a desync, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_version_mismatch() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let host = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            Session::host(stream, &Settings::default())
+        });
+        let mut stream = TcpStream::connect(address).unwrap();
+        let hello = Message::Hello {
+            version: 0,
+            settings: BoardSettings::of(&Settings::default()),
+        };
+        writeln!(stream, "{}", serde_json::to_string(&hello).unwrap()).unwrap();
+        match host.join().unwrap() {
+            Err(NetError::Version(0)) => (),
+            other => panic!("expected a version error, got {:?}", other.err()),
+        }
+    }
+}
@@ -0,0 +1,95 @@
+//! Deterministic piece generation. Games are seeded, and the same seed and
+//! ruleset always deal the same pieces. The generator is implemented here
+//! rather than taken from `rand` so the sequence does not change with the
+//! version of a dependency.
+use serde_derive::{Deserialize, Serialize};
+
+use crate::game_state::Ruleset;
+use crate::tetromino::Shape;
+
+/// SplitMix64, small and fast with good enough statistics for shuffling
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..n`
+    pub fn below(&mut self, n: u32) -> u32 {
+        (self.next_u64() % u64::from(n)) as u32
+    }
+}
+
+/// Deals pieces according to the ruleset: uniformly at random in the
+/// classic ruleset, from shuffled bags of all seven shapes in the
+/// guideline one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Randomizer {
+    ruleset: Ruleset,
+    rng: Rng,
+    bag: Vec<Shape>,
+}
+
+impl Randomizer {
+    pub fn new(ruleset: Ruleset, seed: u64) -> Self {
+        Self {
+            ruleset,
+            rng: Rng::new(seed),
+            bag: Vec::new(),
+        }
+    }
+
+    pub fn next_shape(&mut self) -> Shape {
+        match self.ruleset {
+            Ruleset::Classic => Shape::from(self.rng.below(7) as i32),
+            Ruleset::Guideline => {
+                if self.bag.is_empty() {
+                    self.bag = (0..7).map(Shape::from).collect();
+                    // Fisher-Yates shuffle
+                    for i in (1..self.bag.len()).rev() {
+                        let j = self.rng.below(i as u32 + 1) as usize;
+                        self.bag.swap(i, j);
+                    }
+                }
+                self.bag.pop().unwrap_or_default()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_same_pieces() {
+        let mut first = Randomizer::new(Ruleset::Classic, 42);
+        let mut second = Randomizer::new(Ruleset::Classic, 42);
+        for _ in 0..100 {
+            assert_eq!(first.next_shape(), second.next_shape());
+        }
+    }
+
+    #[test]
+    fn test_bags_hold_every_shape() {
+        let mut randomizer = Randomizer::new(Ruleset::Guideline, 7);
+        for _ in 0..10 {
+            let mut bag: Vec<Shape> = (0..7).map(|_| randomizer.next_shape()).collect();
+            bag.sort_by_key(|shape| *shape as u8);
+            bag.dedup();
+            assert_eq!(bag.len(), 7);
+        }
+    }
+}
@@ -0,0 +1,252 @@
+//! Recorded games, stored as JSON files in the `replays` folder of the data
+//! directory. A replay holds what is needed to simulate the game again: the
+//! mode, seed and settings, and every input with the frame it came in.
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{fmt, fs, io};
+
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::config::data_dir;
+use crate::fumen;
+use crate::game_state::{GameMode, Setup};
+use crate::highscores::today;
+use crate::settings::Settings;
+use crate::simulation::{Input, Simulation};
+
+/// Version of the replay format written by this build. Replays are only
+/// played back by builds with the same version, since any change to the
+/// game logic makes old inputs play out differently.
+pub const REPLAY_VERSION: u32 = 4;
+
+const DIR_NAME: &str = "replays";
+
+impl Serialize for Input {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u8(self.code())
+    }
+}
+
+impl<'de> Deserialize<'de> for Input {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let code = u8::deserialize(deserializer)?;
+        Input::from_code(code)
+            .ok_or_else(|| de::Error::custom(format!("unknown input code {}", code)))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    /// Day the game was played, as `YYYY-MM-DD`
+    pub date: String,
+    pub mode: GameMode,
+    pub seed: u64,
+    /// Settings at the start of the game
+    pub settings: Settings,
+    /// Position the game started from, if not an empty well
+    #[serde(default)]
+    pub setup: Option<Setup>,
+    /// Settings changed from the pause menu, with the frame they took
+    /// effect in
+    #[serde(default)]
+    pub settings_changes: Vec<(u32, Settings)>,
+    /// Inputs with the frame they were applied before
+    pub inputs: Vec<(u32, Input)>,
+    /// Number of frames the game lasted
+    pub frames: u32,
+    pub score: u64,
+    pub lines: u32,
+}
+
+/// Why a replay could not be loaded or saved
+#[derive(Debug)]
+pub enum ReplayError {
+    NoDataDir,
+    Io(io::Error),
+    Json(serde_json::Error),
+    UnsupportedVersion(u32),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::NoDataDir => write!(f, "no data directory found"),
+            ReplayError::Io(err) => write!(f, "{}", err),
+            ReplayError::Json(err) => write!(f, "invalid replay: {}", err),
+            ReplayError::UnsupportedVersion(version) => write!(
+                f,
+                "replay version {} cannot be played by this game (version {})",
+                version, REPLAY_VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl Replay {
+    /// Start recording a game
+    pub fn new(mode: GameMode, seed: u64, settings: &Settings) -> Self {
+        Self {
+            version: REPLAY_VERSION,
+            date: today(),
+            mode,
+            seed,
+            settings: settings.clone(),
+            setup: None,
+            settings_changes: Vec::new(),
+            inputs: Vec::new(),
+            frames: 0,
+            score: 0,
+            lines: 0,
+        }
+    }
+
+    /// Apply an input to the simulation and record it
+    pub fn record(&mut self, sim: &mut Simulation, input: Input) {
+        self.inputs.push((sim.frame(), input));
+        sim.apply(input);
+    }
+
+    /// Change the settings of the simulation and record the change
+    pub fn record_settings(&mut self, sim: &mut Simulation, settings: &Settings) {
+        self.settings_changes.push((sim.frame(), settings.clone()));
+        sim.set_settings(settings);
+    }
+
+    /// The simulation as it was when recording started
+    pub fn start(&self) -> Simulation {
+        match self.setup {
+            Some(ref setup) => Simulation::with_setup(self.mode, &self.settings, self.seed, setup),
+            None => Simulation::new(self.mode, &self.settings, self.seed),
+        }
+    }
+
+    /// Simulate the whole game again, calling `on_frame` after every frame.
+    /// Returns the simulation at the end.
+    pub fn play(&self, mut on_frame: impl FnMut(&Simulation)) -> Simulation {
+        let mut sim = self.start();
+        let (mut next_input, mut next_setting) = (0, 0);
+        while sim.frame() < self.frames {
+            let frame = sim.frame();
+            while let Some((at, settings)) = self.settings_changes.get(next_setting) {
+                if *at != frame {
+                    break;
+                }
+                sim.set_settings(settings);
+                next_setting += 1;
+            }
+            while let Some(&(at, input)) = self.inputs.get(next_input) {
+                if at != frame {
+                    break;
+                }
+                sim.apply(input);
+                next_input += 1;
+            }
+            sim.step();
+            on_frame(&sim);
+        }
+        sim
+    }
+
+    /// Play the replay through and write the stack as it was at the start
+    /// and after every piece as the pages of a fumen
+    pub fn to_fumen(&self) -> String {
+        let mut boards = vec![self.start().game().to_setup().board];
+        let mut pieces = 0;
+        self.play(|sim| {
+            if sim.game().stats().pieces != pieces {
+                pieces = sim.game().stats().pieces;
+                boards.push(sim.game().to_setup().board);
+            }
+        });
+        fumen::encode_boards(&boards)
+    }
+
+    /// Take the final result from the finished simulation
+    pub fn finish(&mut self, sim: &Simulation) {
+        self.frames = sim.frame();
+        self.score = sim.game().points();
+        self.lines = sim.game().lines();
+    }
+
+    pub fn dir() -> Option<PathBuf> {
+        data_dir().map(|dir| dir.join(DIR_NAME))
+    }
+
+    /// Paths of the saved replays, newest first
+    pub fn list() -> Result<Vec<PathBuf>, ReplayError> {
+        let dir = Self::dir().ok_or(ReplayError::NoDataDir)?;
+        let mut paths: Vec<PathBuf> = match fs::read_dir(dir) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().and_then(|ext| ext.to_str()) == Some("json"))
+                .collect(),
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(ReplayError::Io(err)),
+        };
+        // file names start with the time the game was saved
+        paths.sort();
+        paths.reverse();
+        Ok(paths)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ReplayError> {
+        let text = fs::read_to_string(path).map_err(ReplayError::Io)?;
+        let replay: Replay = serde_json::from_str(&text).map_err(ReplayError::Json)?;
+        if replay.version != REPLAY_VERSION {
+            return Err(ReplayError::UnsupportedVersion(replay.version));
+        }
+        Ok(replay)
+    }
+
+    /// Save the replay under a new name and return its path
+    pub fn save(&self) -> Result<PathBuf, ReplayError> {
+        let dir = Self::dir().ok_or(ReplayError::NoDataDir)?;
+        fs::create_dir_all(&dir).map_err(ReplayError::Io)?;
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let name = format!(
+            "{}-{}-{:016x}.json",
+            secs,
+            self.mode.name().to_lowercase(),
+            self.seed
+        );
+        let path = dir.join(name);
+        self.save_to(&path)?;
+        Ok(path)
+    }
+
+    /// Save the replay to the given file
+    pub fn save_to(&self, path: &Path) -> Result<(), ReplayError> {
+        let text = serde_json::to_string(self).map_err(ReplayError::Json)?;
+        fs::write(path, text).map_err(ReplayError::Io)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action::Action;
+
+    #[test]
+    fn test_round_trip() {
+        let mut sim = Simulation::new(GameMode::Sprint, &Settings::default(), 99);
+        let mut replay = Replay::new(GameMode::Sprint, 99, &Settings::default());
+        replay.record(&mut sim, Input::Press(Action::HardDrop));
+        sim.step();
+        replay.record(&mut sim, Input::ReleaseAll);
+        replay.finish(&sim);
+
+        let text = serde_json::to_string(&replay).unwrap();
+        assert!(text.contains("\"inputs\":[[0,7],[1,0]]"));
+        let loaded: Replay = serde_json::from_str(&text).unwrap();
+        assert_eq!(loaded.inputs, replay.inputs);
+        assert_eq!(loaded.frames, 1);
+    }
+}
@@ -0,0 +1,481 @@
+use std::collections::HashMap;
+
+use ggez::graphics::spritebatch::SpriteBatch;
+use ggez::graphics::{
+    self, Color, DrawMode, DrawParam, Mesh, MeshBuilder, Rect, Scale, Text, TextFragment,
+};
+use ggez::mint::{Point2, Vector2};
+use ggez::{Context, GameResult};
+
+use super::skin::Skin;
+use crate::game_state::GameState;
+use crate::tetromino::{Segment, Shape, Tetromino, GRID_CELL_SIZE, GRID_SIZE};
+use crate::theme::{Block, GridStyle};
+use crate::SCREEN_SIZE;
+
+/// Text size and row height of the statistics in the side panel
+const STATS_SCALE: f32 = 13.0;
+const STATS_SPACING: f32 = 15.0;
+
+/// Width of the incoming garbage meter beside the well
+const GARBAGE_METER_WIDTH: f32 = 6.0;
+
+/// Color of the well for a moment after a finesse fault
+const FLASH_COLOR: (u8, u8, u8, u8) = (96, 24, 24, 255);
+
+/// Opacity of the outline drawn as the ghost when the theme has no ghost
+/// texture
+const GHOST_ALPHA: f32 = 160.0 / 255.0;
+
+const ORIGIN: Point2<f32> = Point2 { x: 0.0, y: 0.0 };
+
+/// Cells gathered to be drawn with a few draw calls instead of one each:
+/// plain cells go into a single mesh, textured ones into a sprite batch
+/// per texture
+struct Cells<'a> {
+    skin: &'a Skin,
+    builder: MeshBuilder,
+    empty: bool,
+    sprites: HashMap<Block, SpriteBatch>,
+}
+
+impl<'a> Cells<'a> {
+    fn new(skin: &'a Skin) -> Self {
+        Self {
+            skin,
+            builder: MeshBuilder::new(),
+            empty: true,
+            sprites: HashMap::new(),
+        }
+    }
+
+    /// A plain rectangle, whatever the theme
+    fn add(&mut self, mode: DrawMode, rect: Rect, color: Color) {
+        self.builder.rectangle(mode, rect, color);
+        self.empty = false;
+    }
+
+    /// A block in the texture the theme has for it, or as a plain cell of
+    /// its color. The ghost is tinted with the color instead.
+    fn add_block(&mut self, block: Block, rect: Rect, color: Color) {
+        let image = match self.skin.texture(block) {
+            Some(image) => image,
+            None if block == Block::Ghost => {
+                let outline = Color {
+                    a: GHOST_ALPHA,
+                    ..color
+                };
+                return self.add(DrawMode::stroke(2.0), rect, outline);
+            }
+            None => return self.add(DrawMode::fill(), rect, color),
+        };
+        let tint = if block == Block::Ghost {
+            color
+        } else {
+            graphics::WHITE
+        };
+        let scale = Vector2 {
+            x: rect.w / f32::from(image.width()),
+            y: rect.h / f32::from(image.height()),
+        };
+        self.sprites
+            .entry(block)
+            .or_insert_with(|| SpriteBatch::new(image.clone()))
+            .add(
+                DrawParam::new()
+                    .dest(Point2 {
+                        x: rect.x,
+                        y: rect.y,
+                    })
+                    .scale(scale)
+                    .color(tint),
+            );
+    }
+
+    /// The segments of a piece or of the stack, in the well
+    fn add_segments<'b, I>(&mut self, segments: I)
+    where
+        I: IntoIterator<Item = &'b Segment>,
+    {
+        for seg in segments {
+            self.add_block(Block::of(seg), seg.into(), seg.color.into());
+        }
+    }
+
+    /// The ghost of the piece, where it will land
+    fn add_ghost(&mut self, ghost: &Tetromino) {
+        for seg in ghost.clone_body().iter() {
+            self.add_block(Block::Ghost, seg.into(), seg.color.into());
+        }
+    }
+
+    /// A piece outside of the well with its top left corner at `origin` and
+    /// cells scaled by `scale`. Returns its height.
+    fn add_piece_at(&mut self, shape: Shape, origin: Point2<f32>, scale: f32) -> f32 {
+        let body = Tetromino::from(shape).clone_body();
+        let min_x = body.iter().map(|seg| seg.x).min().unwrap_or(0);
+        let min_y = body.iter().map(|seg| seg.y).min().unwrap_or(0);
+        let max_y = body.iter().map(|seg| seg.y).max().unwrap_or(0);
+        let cell = (
+            GRID_CELL_SIZE.0 as f32 * scale,
+            GRID_CELL_SIZE.1 as f32 * scale,
+        );
+        for seg in body.iter() {
+            let rect = Rect::new(
+                origin.x + (seg.x - min_x) as f32 * cell.0,
+                origin.y + (seg.y - min_y) as f32 * cell.1,
+                cell.0,
+                cell.1,
+            );
+            self.add_block(Block::Piece(shape), rect, seg.color.into());
+        }
+        (max_y - min_y + 1) as f32 * cell.1
+    }
+
+    fn build(self, ctx: &mut Context) -> GameResult<Batch> {
+        // meshes cannot be empty
+        let mesh = if self.empty {
+            None
+        } else {
+            Some(self.builder.build(ctx)?)
+        };
+        Ok(Batch {
+            mesh,
+            sprites: self.sprites.into_values().collect(),
+        })
+    }
+}
+
+/// Cells ready to be drawn, see `Cells`
+struct Batch {
+    mesh: Option<Mesh>,
+    sprites: Vec<SpriteBatch>,
+}
+
+impl Batch {
+    fn draw(&self, ctx: &mut Context) -> GameResult {
+        if let Some(ref mesh) = self.mesh {
+            graphics::draw(ctx, mesh, (ORIGIN,))?;
+        }
+        for batch in self.sprites.iter() {
+            graphics::draw(ctx, batch, (ORIGIN,))?;
+        }
+        Ok(())
+    }
+}
+
+/// What never changes while a theme is used
+struct Well {
+    /// Behind the well and the side panel
+    background: Mesh,
+    /// The well, white to be tinted when it flashes
+    field: Mesh,
+    /// Drawn over the stack, none if the theme turns it off
+    grid: Option<Mesh>,
+}
+
+impl Well {
+    fn new(ctx: &mut Context, skin: &Skin) -> GameResult<Self> {
+        let manifest = &skin.manifest;
+        let screen = Rect::new(0.0, 0.0, SCREEN_SIZE.0, SCREEN_SIZE.1);
+        let background =
+            Mesh::new_rectangle(ctx, DrawMode::fill(), screen, manifest.background.into())?;
+        let width = (GRID_CELL_SIZE.0 * GRID_SIZE.0) as f32;
+        let height = (GRID_CELL_SIZE.1 * GRID_SIZE.1) as f32;
+        let field = Mesh::new_rectangle(
+            ctx,
+            DrawMode::fill(),
+            Rect::new(0.0, 0.0, width, height),
+            graphics::WHITE,
+        )?;
+
+        let grid = &manifest.grid;
+        let color: Color = grid.color.into();
+        let mut builder = MeshBuilder::new();
+        match grid.style {
+            GridStyle::Lines => {
+                for column in 0..GRID_SIZE.0 {
+                    let x = (column * GRID_CELL_SIZE.0) as f32;
+                    let points = [Point2 { x, y: 0.0 }, Point2 { x, y: height }];
+                    builder.line(&points, grid.width, color)?;
+                }
+                for row in 0..GRID_SIZE.1 {
+                    let y = (row * GRID_CELL_SIZE.1) as f32;
+                    let points = [Point2 { x: 0.0, y }, Point2 { x: width, y }];
+                    builder.line(&points, grid.width, color)?;
+                }
+            }
+            GridStyle::Dots => {
+                for column in 1..GRID_SIZE.0 {
+                    for row in 1..GRID_SIZE.1 {
+                        let x = (column * GRID_CELL_SIZE.0) as f32 - grid.width / 2.0;
+                        let y = (row * GRID_CELL_SIZE.1) as f32 - grid.width / 2.0;
+                        let dot = Rect::new(x, y, grid.width, grid.width);
+                        builder.rectangle(DrawMode::fill(), dot, color);
+                    }
+                }
+            }
+            GridStyle::None => (),
+        }
+        let grid = if grid.style == GridStyle::None {
+            None
+        } else {
+            Some(builder.build(ctx)?)
+        };
+        Ok(Self {
+            background,
+            field,
+            grid,
+        })
+    }
+}
+
+/// What the side panel shows as pieces, it is only rebuilt when one of
+/// them changes
+#[derive(Debug, Clone, PartialEq)]
+struct PanelPieces {
+    hold: Option<Shape>,
+    next: Vec<Shape>,
+}
+
+/// Draws a game in the look of the theme: the well, the pieces and the
+/// side panel. Drawing every cell on its own costs a draw call each, so
+/// the cells are batched instead. The well and grid are built once per
+/// theme, the stack and the panel pieces are rebuilt when they change, and
+/// only the falling piece, its ghost and the garbage meter are built every
+/// frame. Every board on screen needs its own view.
+pub(super) struct BoardView {
+    /// The theme everything was built for
+    theme: String,
+    well: Option<Well>,
+    /// The stack last drawn, with its cells
+    stack: Option<(Vec<Segment>, Batch)>,
+    panel: Option<(PanelPieces, Batch)>,
+}
+
+impl BoardView {
+    pub(super) fn new() -> Self {
+        Self {
+            theme: String::new(),
+            well: None,
+            stack: None,
+            panel: None,
+        }
+    }
+
+    /// Draw the game. Clearing and presenting the frame is left to the
+    /// scene stack.
+    pub(super) fn draw(&mut self, ctx: &mut Context, skin: &Skin, game: &GameState) -> GameResult {
+        if self.theme != skin.name {
+            *self = Self::new();
+            self.theme = skin.name.clone();
+        }
+        // the stack lies on the well with the grid drawn over it, the
+        // ghost, the falling piece and the garbage meter go on top. Each is
+        // a batch of its own, so a texture in one layer never ends up above
+        // the next one.
+        self.draw_well(ctx, skin, game)?;
+
+        if self.stack.as_ref().map(|(base, _)| base.as_slice()) != Some(game.base()) {
+            let mut stack = Cells::new(skin);
+            stack.add_segments(game.base());
+            self.stack = Some((game.base().to_vec(), stack.build(ctx)?));
+        }
+        if let Some((_, ref stack)) = self.stack {
+            stack.draw(ctx)?;
+        }
+        if let Some(Well {
+            grid: Some(ref grid),
+            ..
+        }) = self.well
+        {
+            graphics::draw(ctx, grid, (ORIGIN,))?;
+        }
+
+        let mut ghost = Cells::new(skin);
+        if let Some(ref piece) = game.ghost() {
+            ghost.add_ghost(piece);
+        }
+        ghost.build(ctx)?.draw(ctx)?;
+        let mut piece = Cells::new(skin);
+        piece.add_segments(game.current().clone_body().iter());
+        piece.build(ctx)?.draw(ctx)?;
+        let mut meter = Cells::new(skin);
+        add_garbage_meter(&mut meter, game);
+        meter.build(ctx)?.draw(ctx)?;
+
+        self.draw_panel(ctx, skin, game)
+    }
+
+    fn draw_well(&mut self, ctx: &mut Context, skin: &Skin, game: &GameState) -> GameResult {
+        if self.well.is_none() {
+            self.well = Some(Well::new(ctx, skin)?);
+        }
+        let well = match self.well {
+            Some(ref well) => well,
+            None => return Ok(()),
+        };
+        graphics::draw(ctx, &well.background, (ORIGIN,))?;
+        if let Some(ref image) = skin.background_image {
+            let scale = Vector2 {
+                x: SCREEN_SIZE.0 / f32::from(image.width()),
+                y: SCREEN_SIZE.1 / f32::from(image.height()),
+            };
+            graphics::draw(ctx, image, DrawParam::new().dest(ORIGIN).scale(scale))?;
+        }
+        let color = if game.flashing() {
+            FLASH_COLOR
+        } else {
+            skin.manifest.well
+        };
+        graphics::draw(ctx, &well.field, (ORIGIN, color.into()))
+    }
+
+    /// Draw the side panel: score and progress with the held piece in the
+    /// left column, the next queue in the right one
+    fn draw_panel(&mut self, ctx: &mut Context, skin: &Skin, game: &GameState) -> GameResult {
+        let left_column = (GRID_SIZE.0 * GRID_CELL_SIZE.0) as f32 + 12.0;
+        let right_column = left_column + 3.3 * GRID_CELL_SIZE.0 as f32;
+
+        // draw Score
+        let title_position = Point2 {
+            x: left_column,
+            y: 10.0,
+        };
+        let point_position = Point2 {
+            x: title_position.x + 8.,
+            y: title_position.y + 20.,
+        };
+        graphics::draw(ctx, &text(skin, "Score".into()), (title_position,))?;
+        let points = text(skin, game.points().to_string().into());
+        graphics::draw(ctx, &points, (point_position,))?;
+
+        // the held figure goes below the score
+        let hold_text_pos = Point2 {
+            x: title_position.x,
+            y: point_position.y + 30.0,
+        };
+        graphics::draw(ctx, &text(skin, "Hold".into()), (hold_text_pos,))?;
+        let stats_top = hold_text_pos.y + 100.0;
+        draw_stats(ctx, skin, game, left_column, stats_top)?;
+
+        // the next figures go in the right column, the first one in full size
+        let next_text_pos = Point2 {
+            x: right_column,
+            y: title_position.y,
+        };
+        graphics::draw(ctx, &text(skin, "Next".into()), (next_text_pos,))?;
+
+        let pieces = PanelPieces {
+            hold: game.hold(),
+            next: game.next().collect(),
+        };
+        if self.panel.as_ref().map(|(shown, _)| shown) != Some(&pieces) {
+            let mut cells = Cells::new(skin);
+            if let Some(shape) = pieces.hold {
+                let hold_pos = Point2 {
+                    x: hold_text_pos.x + 4.0,
+                    y: hold_text_pos.y + 24.0,
+                };
+                cells.add_piece_at(shape, hold_pos, 0.6);
+            }
+            let mut next_pos = Point2 {
+                x: right_column + 4.0,
+                y: next_text_pos.y + 24.0,
+            };
+            for (i, &shape) in pieces.next.iter().enumerate() {
+                let scale = if i == 0 { 1.0 } else { 0.6 };
+                next_pos.y += cells.add_piece_at(shape, next_pos, scale) + 12.0;
+            }
+            add_shape_squares(&mut cells, left_column, shapes_top(game, stats_top));
+            self.panel = Some((pieces, cells.build(ctx)?));
+        }
+        if let Some((_, ref panel)) = self.panel {
+            panel.draw(ctx)?;
+        }
+        Ok(())
+    }
+}
+
+/// The incoming garbage as a red bar along the right edge of the well, a
+/// row of the bar for every row. Rows that arrive with the next piece are
+/// bright, the ones still delayed dark.
+fn add_garbage_meter(cells: &mut Cells, game: &GameState) {
+    let garbage = game.garbage();
+    let cell = GRID_CELL_SIZE.1 as f32;
+    let x = (GRID_SIZE.0 * GRID_CELL_SIZE.0) as f32;
+    let bottom = GRID_SIZE.1 as f32 * cell;
+    let bars = [
+        (garbage.total(), (120, 20, 20, 255)),
+        (garbage.ready(), (230, 30, 30, 255)),
+    ];
+    for &(rows, color) in bars.iter().filter(|(rows, _)| *rows > 0) {
+        let height = rows.min(GRID_SIZE.1 as u32) as f32 * cell;
+        let bar = Rect::new(x + 1.0, bottom - height, GARBAGE_METER_WIDTH, height);
+        cells.add(DrawMode::fill(), bar, color.into());
+    }
+}
+
+/// Text of the side panel, in the font of the theme
+fn text(skin: &Skin, fragment: TextFragment) -> Text {
+    match skin.font {
+        Some(font) => Text::new(fragment.font(font)),
+        None => Text::new(fragment),
+    }
+}
+
+fn small(skin: &Skin, value: &str) -> Text {
+    text(
+        skin,
+        TextFragment::new(value).scale(Scale::uniform(STATS_SCALE)),
+    )
+}
+
+/// Where the piece distribution starts, below the rows of statistics
+fn shapes_top(game: &GameState, top: f32) -> f32 {
+    top + (game.stat_rows().len() as f32 + 0.5) * STATS_SPACING
+}
+
+/// Where the count of the `i`th shape of the piece distribution goes, in
+/// two columns
+fn shape_position(i: usize, x: f32, top: f32) -> Point2<f32> {
+    Point2 {
+        x: x + (i % 2) as f32 * 44.0,
+        y: top + (i / 2) as f32 * STATS_SPACING,
+    }
+}
+
+/// The colored squares of the piece distribution, they stay where they are
+/// and go with the panel pieces
+fn add_shape_squares(cells: &mut Cells, x: f32, top: f32) {
+    for (i, shape) in Shape::ALL.iter().enumerate() {
+        let pos = shape_position(i, x, top);
+        let color: (u8, u8, u8, u8) = shape.into();
+        cells.add(
+            DrawMode::fill(),
+            Rect::new(pos.x, pos.y + 2.0, 9.0, 9.0),
+            color.into(),
+        );
+    }
+}
+
+/// Draw the live statistics as rows of labels and values, followed by the
+/// number of pieces of every shape next to its square
+fn draw_stats(ctx: &mut Context, skin: &Skin, game: &GameState, x: f32, top: f32) -> GameResult {
+    let mut y = top;
+    for (label, value) in game.stat_rows().iter() {
+        graphics::draw(ctx, &small(skin, label), (Point2 { x, y },))?;
+        graphics::draw(ctx, &small(skin, value), (Point2 { x: x + 44.0, y },))?;
+        y += STATS_SPACING;
+    }
+    let top = shapes_top(game, top);
+    for (i, count) in game.stats().shapes.iter().enumerate() {
+        let pos = shape_position(i, x, top);
+        let text_pos = Point2 {
+            x: pos.x + 13.0,
+            y: pos.y,
+        };
+        graphics::draw(ctx, &small(skin, &count.to_string()), (text_pos,))?;
+    }
+    Ok(())
+}
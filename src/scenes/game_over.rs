@@ -0,0 +1,131 @@
+use ggez::graphics::{Scale, Text, TextFragment};
+use ggez::{Context, GameResult};
+
+use super::high_scores::draw_table;
+use super::menu::{draw_centered, draw_shade, Menu, MenuEvent};
+use super::name_entry::NameEntry;
+use super::playing::Playing;
+use super::title::Title;
+use super::{MenuInput, Scene, SharedState, Transition};
+use crate::game_state::{format_duration, GameMode, GameState, Ruleset, Setup};
+use crate::highscores::{Entry, HighScores};
+
+const RETRY: usize = 0;
+const TITLE: usize = 1;
+
+/// Shown on top of the final board once a game has ended, with the high
+/// score table of the mode. A game that makes it into the table first asks
+/// for the player's name.
+pub(crate) struct GameOver {
+    mode: GameMode,
+    ruleset: Ruleset,
+    seed: u64,
+    setup: Option<Setup>,
+    summary: String,
+    /// Statistics of the game below the summary
+    details: String,
+    /// Place of this game in the table
+    rank: Option<usize>,
+    /// Waiting for a name before it goes into the table
+    pending: Option<Entry>,
+    menu: Menu,
+}
+
+impl GameOver {
+    pub(crate) fn new(game: &GameState, high_scores: &HighScores) -> Self {
+        let title = if game.goal_reached() {
+            "Finished!"
+        } else {
+            "Game Over"
+        };
+        let summary = match game.mode() {
+            GameMode::Sprint if game.goal_reached() => {
+                format!("Time: {}", format_duration(game.elapsed()))
+            }
+            GameMode::Sprint => format!("Lines: {}", game.lines()),
+            _ => format!("Score: {}  Lines: {}", game.points(), game.lines()),
+        };
+        let stats = game.stats();
+        let details = format!(
+            "Pieces {}  PPS {:.2}  KPP {:.2}  Faults {}\nTetris {}  T-spin {}  PC {}",
+            stats.pieces,
+            stats.pieces_per_second(),
+            stats.keys_per_piece(),
+            stats.finesse_faults,
+            stats.tetrises(),
+            stats.tspins,
+            stats.perfect_clears
+        );
+        let entry = Entry::from_game(game);
+        let rank = entry
+            .as_ref()
+            .and_then(|entry| high_scores.rank(game.mode(), game.ruleset(), entry));
+        Self {
+            mode: game.mode(),
+            ruleset: game.ruleset(),
+            seed: game.seed(),
+            setup: game.setup().cloned(),
+            summary,
+            details,
+            rank,
+            pending: if rank.is_some() { entry } else { None },
+            menu: Menu::new(title, &["Retry", "Title"]),
+        }
+    }
+}
+
+impl Scene for GameOver {
+    fn update(&mut self, _ctx: &mut Context, shared: &mut SharedState) -> GameResult<Transition> {
+        if let (Some(entry), Some(rank)) = (self.pending.take(), self.rank) {
+            return Ok(Transition::Push(Box::new(NameEntry::new(
+                self.mode,
+                self.ruleset,
+                rank,
+                entry,
+                &shared.high_scores.last_name,
+            ))));
+        }
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, shared: &SharedState) -> GameResult {
+        draw_shade(ctx, 0.85)?;
+        self.menu.draw_title(ctx, 30.0)?;
+        let summary = Text::new(self.summary.as_str());
+        draw_centered(ctx, &summary, 80.0)?;
+        let details =
+            Text::new(TextFragment::new(self.details.as_str()).scale(Scale::uniform(14.0)));
+        draw_centered(ctx, &details, 102.0)?;
+        let entries = shared.high_scores.table(self.mode, self.ruleset);
+        // the game is only highlighted once it was entered, not if the name
+        // entry was skipped
+        let highlight = self
+            .rank
+            .filter(|&rank| entries.get(rank).map(|e| e.seed) == Some(self.seed));
+        draw_table(ctx, entries, self.mode, highlight, 145.0)?;
+        self.menu.draw_entries(ctx, 390.0)
+    }
+
+    fn menu_input(
+        &mut self,
+        _ctx: &mut Context,
+        shared: &mut SharedState,
+        input: MenuInput,
+    ) -> Transition {
+        match self.menu.input(input) {
+            MenuEvent::Confirm(RETRY) => Transition::Reset(Box::new(Playing::new(
+                self.mode,
+                &shared.settings,
+                self.setup.as_ref(),
+            ))),
+            MenuEvent::Confirm(TITLE) | MenuEvent::Back => {
+                Transition::Reset(Box::new(Title::new()))
+            }
+            _ => Transition::None,
+        }
+    }
+
+    fn is_overlay(&self) -> bool {
+        true
+    }
+}
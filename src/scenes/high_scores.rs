@@ -0,0 +1,142 @@
+use ggez::graphics::{self, Scale, Text, TextFragment};
+use ggez::{Context, GameResult};
+
+use super::menu::draw_centered;
+use super::{MenuInput, Scene, SharedState, Transition};
+use crate::game_state::{format_duration, GameMode, Ruleset};
+use crate::highscores::{Entry, TABLE_SIZE};
+use std::time::Duration;
+
+const MODES: [GameMode; 3] = [GameMode::Marathon, GameMode::Sprint, GameMode::Ultra];
+
+const ROW_SCALE: f32 = 14.0;
+const ROW_SPACING: f32 = 20.0;
+/// Left edge of the rank, name, score or time, lines, level, PPS and date
+/// columns
+const COLUMNS: [f32; 7] = [10.0, 34.0, 138.0, 214.0, 262.0, 302.0, 352.0];
+
+/// Draw a high score table with a header row, the entry at `highlight` in
+/// the menu highlight color. Sprints show the time instead of the score.
+pub(crate) fn draw_table(
+    ctx: &mut Context,
+    entries: &[Entry],
+    mode: GameMode,
+    highlight: Option<usize>,
+    top: f32,
+) -> GameResult {
+    let result = if mode == GameMode::Sprint {
+        "Time"
+    } else {
+        "Score"
+    };
+    let header = ["#", "Name", result, "Lines", "Lv", "PPS", "Date"];
+    let gray = graphics::Color::new(0.6, 0.6, 0.6, 1.0);
+    draw_row(ctx, &header, gray, top)?;
+
+    for rank in 0..TABLE_SIZE {
+        let y = top + (rank + 1) as f32 * ROW_SPACING;
+        let entry = match entries.get(rank) {
+            Some(entry) => entry,
+            None => {
+                draw_row(ctx, &[&format!("{}", rank + 1), "---"], gray, y)?;
+                continue;
+            }
+        };
+        let result = if mode == GameMode::Sprint {
+            format_duration(Duration::from_millis(entry.time_ms))
+        } else {
+            entry.score.to_string()
+        };
+        let cells = [
+            format!("{}", rank + 1),
+            entry.name.clone(),
+            result,
+            entry.lines.to_string(),
+            entry.level.to_string(),
+            format!("{:.2}", entry.pps),
+            entry.date.clone(),
+        ];
+        let cells: Vec<&str> = cells.iter().map(String::as_str).collect();
+        let color = if highlight == Some(rank) {
+            graphics::Color::new(1.0, 0.85, 0.0, 1.0)
+        } else {
+            graphics::WHITE
+        };
+        draw_row(ctx, &cells, color, y)?;
+    }
+    Ok(())
+}
+
+fn draw_row(ctx: &mut Context, cells: &[&str], color: graphics::Color, y: f32) -> GameResult {
+    for (&x, &cell) in COLUMNS.iter().zip(cells.iter()) {
+        let text = Text::new(
+            TextFragment::new(cell)
+                .scale(Scale::uniform(ROW_SCALE))
+                .color(color),
+        );
+        graphics::draw(ctx, &text, (ggez::mint::Point2 { x, y },))?;
+    }
+    Ok(())
+}
+
+/// Browse the high score tables, left and right switch the mode, up and
+/// down the ruleset
+pub(crate) struct ScoreBoard {
+    mode: usize,
+    ruleset: usize,
+}
+
+impl ScoreBoard {
+    pub(crate) fn new(ruleset: Ruleset) -> Self {
+        Self {
+            mode: 0,
+            ruleset: Ruleset::ALL.iter().position(|&r| r == ruleset).unwrap_or(0),
+        }
+    }
+}
+
+impl Scene for ScoreBoard {
+    fn update(&mut self, _ctx: &mut Context, _shared: &mut SharedState) -> GameResult<Transition> {
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, shared: &SharedState) -> GameResult {
+        let mode = MODES[self.mode];
+        let ruleset = Ruleset::ALL[self.ruleset];
+        let title = Text::new(TextFragment::new("High Scores").scale(Scale::uniform(40.0)));
+        draw_centered(ctx, &title, 40.0)?;
+        let table_name = Text::new(
+            TextFragment::new(format!("< {} - {} >", mode.name(), ruleset.name()))
+                .scale(Scale::uniform(22.0)),
+        );
+        draw_centered(ctx, &table_name, 100.0)?;
+        draw_table(
+            ctx,
+            shared.high_scores.table(mode, ruleset),
+            mode,
+            None,
+            150.0,
+        )?;
+        let hint = Text::new(
+            TextFragment::new("Left/Right: mode   Up/Down: ruleset").scale(Scale::uniform(14.0)),
+        );
+        draw_centered(ctx, &hint, 480.0)
+    }
+
+    fn menu_input(
+        &mut self,
+        _ctx: &mut Context,
+        _shared: &mut SharedState,
+        input: MenuInput,
+    ) -> Transition {
+        let rulesets = Ruleset::ALL.len();
+        match input {
+            MenuInput::Left => self.mode = (self.mode + MODES.len() - 1) % MODES.len(),
+            MenuInput::Right => self.mode = (self.mode + 1) % MODES.len(),
+            MenuInput::Up => self.ruleset = (self.ruleset + rulesets - 1) % rulesets,
+            MenuInput::Down => self.ruleset = (self.ruleset + 1) % rulesets,
+            MenuInput::Confirm | MenuInput::Back => return Transition::Pop,
+        }
+        Transition::None
+    }
+}
@@ -0,0 +1,127 @@
+use ggez::graphics::{self, Scale, Text, TextFragment};
+use ggez::{Context, GameResult};
+
+use super::MenuInput;
+
+const TITLE_SCALE: f32 = 40.0;
+const ENTRY_SCALE: f32 = 22.0;
+const ENTRY_SPACING: f32 = 34.0;
+const COMPACT_SPACING: f32 = 28.0;
+
+/// Outcome of navigating a menu
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MenuEvent {
+    None,
+    /// The entry with the given index was chosen
+    Confirm(usize),
+    /// The value of the entry was stepped left (-1) or right (+1)
+    Adjust(usize, i8),
+    /// The menu was dismissed
+    Back,
+}
+
+/// A vertical list of entries navigated with the arrow keys or a gamepad
+pub(crate) struct Menu {
+    title: String,
+    entries: Vec<String>,
+    selected: usize,
+    /// Distance between the tops of two entries
+    spacing: f32,
+}
+
+impl Menu {
+    pub(crate) fn new(title: &str, entries: &[&str]) -> Self {
+        Self {
+            title: title.to_string(),
+            entries: entries.iter().map(|e| e.to_string()).collect(),
+            selected: 0,
+            spacing: ENTRY_SPACING,
+        }
+    }
+
+    /// Put the entries closer together, for long menus
+    pub(crate) fn compact(mut self) -> Self {
+        self.spacing = COMPACT_SPACING;
+        self
+    }
+
+    /// Change the label of an entry, used to display current option values
+    pub(crate) fn set_entry(&mut self, index: usize, label: String) {
+        self.entries[index] = label;
+    }
+
+    pub(crate) fn input(&mut self, input: MenuInput) -> MenuEvent {
+        let count = self.entries.len();
+        match input {
+            MenuInput::Up => {
+                self.selected = (self.selected + count - 1) % count;
+                MenuEvent::None
+            }
+            MenuInput::Down => {
+                self.selected = (self.selected + 1) % count;
+                MenuEvent::None
+            }
+            MenuInput::Left => MenuEvent::Adjust(self.selected, -1),
+            MenuInput::Right => MenuEvent::Adjust(self.selected, 1),
+            MenuInput::Confirm => MenuEvent::Confirm(self.selected),
+            MenuInput::Back => MenuEvent::Back,
+        }
+    }
+
+    /// Draw the title and the entries centered horizontally, starting at `top`
+    pub(crate) fn draw(&self, ctx: &mut Context, top: f32) -> GameResult {
+        self.draw_title(ctx, top)?;
+        self.draw_entries(ctx, top + TITLE_SCALE * 2.0)
+    }
+
+    /// Draw only the title, for screens that put more between the title
+    /// and the entries
+    pub(crate) fn draw_title(&self, ctx: &mut Context, y: f32) -> GameResult {
+        let title =
+            Text::new(TextFragment::new(self.title.as_str()).scale(Scale::uniform(TITLE_SCALE)));
+        draw_centered(ctx, &title, y)
+    }
+
+    /// Draw only the entries, the first one at `top`
+    pub(crate) fn draw_entries(&self, ctx: &mut Context, top: f32) -> GameResult {
+        for (i, entry) in self.entries.iter().enumerate() {
+            let color = if i == self.selected {
+                graphics::Color::new(1.0, 0.85, 0.0, 1.0)
+            } else {
+                graphics::WHITE
+            };
+            let label = if i == self.selected {
+                format!("> {} <", entry)
+            } else {
+                entry.clone()
+            };
+            let text = Text::new(
+                TextFragment::new(label)
+                    .scale(Scale::uniform(ENTRY_SCALE))
+                    .color(color),
+            );
+            let y = top + i as f32 * self.spacing;
+            draw_centered(ctx, &text, y)?;
+        }
+        Ok(())
+    }
+}
+
+/// Draw a piece of text centered horizontally on the screen, however wide
+/// the screen is
+pub(crate) fn draw_centered(ctx: &mut Context, text: &Text, y: f32) -> GameResult {
+    let screen = graphics::screen_coordinates(ctx);
+    let x = screen.x + (screen.w - text.width(ctx) as f32) / 2.0;
+    graphics::draw(ctx, text, (ggez::mint::Point2 { x, y },))
+}
+
+/// Darken everything drawn so far, used behind overlay menus
+pub(crate) fn draw_shade(ctx: &mut Context, alpha: f32) -> GameResult {
+    let shade = graphics::Mesh::new_rectangle(
+        ctx,
+        graphics::DrawMode::fill(),
+        graphics::screen_coordinates(ctx),
+        [0.0, 0.0, 0.0, alpha].into(),
+    )?;
+    graphics::draw(ctx, &shade, (ggez::mint::Point2 { x: 0.0, y: 0.0 },))
+}
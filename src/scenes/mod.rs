@@ -0,0 +1,597 @@
+//! Scenes are the separate screens of the game (menus, the playfield and the
+//! overlays drawn on top of it). They are kept on a stack: the topmost scene
+//! receives input and updates, and tells the stack what to do next through
+//! a `Transition`.
+mod board;
+mod game_over;
+mod high_scores;
+mod menu;
+mod mode_select;
+mod name_entry;
+mod online;
+mod options;
+mod paused;
+mod playing;
+mod replay;
+mod skin;
+mod title;
+mod versus;
+
+pub(crate) use self::online::Online;
+pub(crate) use self::options::apply_window_settings;
+pub(crate) use self::playing::Playing;
+pub(crate) use self::title::Title;
+
+use self::skin::Skin;
+
+use std::collections::HashMap;
+
+use ggez::event::{self, Axis, Button, GamepadId, KeyCode, KeyMods};
+use ggez::{graphics, Context, GameResult};
+
+use crate::action::Action;
+use crate::bindings::Bindings;
+use crate::broadcast::Broadcaster;
+use crate::game_state::GameState;
+use crate::highscores::HighScores;
+use crate::settings::Settings;
+use crate::SCREEN_SIZE;
+
+/// State shared by all scenes, it lives as long as the scene stack
+pub(crate) struct SharedState {
+    pub(crate) settings: Settings,
+    pub(crate) bindings: Bindings,
+    pub(crate) high_scores: HighScores,
+    /// Size of the screen the window is fitted to, in drawing coordinates
+    pub(crate) screen_size: (f32, f32),
+    /// The theme of the settings, loaded on the next update when it changes
+    pub(crate) skin: Skin,
+}
+
+impl SharedState {
+    pub(crate) fn new(settings: Settings) -> Self {
+        Self {
+            settings,
+            bindings: Bindings::load_or_default(),
+            high_scores: HighScores::load_or_default(),
+            screen_size: SCREEN_SIZE,
+            skin: Skin::default(),
+        }
+    }
+}
+
+/// What the scene stack should do after a scene handled an event
+pub(crate) enum Transition {
+    /// Keep the current scene
+    None,
+    /// Put a new scene on top of the current one
+    Push(Box<dyn Scene>),
+    /// Remove the current scene and return to the one below
+    Pop,
+    /// Swap the current scene for a new one
+    Replace(Box<dyn Scene>),
+    /// Drop every scene and start over from the given one
+    Reset(Box<dyn Scene>),
+    /// Close the game
+    Quit,
+}
+
+/// Menu navigation, the same for every keyboard layout and gamepad
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MenuInput {
+    Up,
+    Down,
+    Left,
+    Right,
+    Confirm,
+    Back,
+}
+
+/// A single screen of the game
+pub(crate) trait Scene {
+    fn update(&mut self, ctx: &mut Context, shared: &mut SharedState) -> GameResult<Transition>;
+
+    fn draw(&mut self, ctx: &mut Context, shared: &SharedState) -> GameResult;
+
+    /// Navigation input, sent to scenes that do not take actions
+    fn menu_input(
+        &mut self,
+        _ctx: &mut Context,
+        _shared: &mut SharedState,
+        _input: MenuInput,
+    ) -> Transition {
+        Transition::None
+    }
+
+    /// Bound actions being pressed or released, sent instead of menu input
+    /// to scenes that return true from `takes_actions`
+    fn action(
+        &mut self,
+        _ctx: &mut Context,
+        _shared: &mut SharedState,
+        _action: Action,
+        _pressed: bool,
+    ) -> Transition {
+        Transition::None
+    }
+
+    fn takes_actions(&self) -> bool {
+        false
+    }
+
+    /// The bound pause key or button, sent to scenes that do not take
+    /// actions. Scenes that return `None` get the menu input it stands for
+    /// instead, if any.
+    fn pause(&mut self, _ctx: &mut Context, _shared: &mut SharedState) -> Option<Transition> {
+        None
+    }
+
+    /// Number of players in the game. Scenes with more than one get the
+    /// actions of the players' keys and gamepads through `player_action`,
+    /// and only the other bound keys through `action`.
+    fn players(&self) -> usize {
+        1
+    }
+
+    /// A bound action of one of the players being pressed or released
+    fn player_action(
+        &mut self,
+        _ctx: &mut Context,
+        _shared: &mut SharedState,
+        _player: usize,
+        _action: Action,
+        _pressed: bool,
+    ) -> Transition {
+        Transition::None
+    }
+
+    /// Typed characters, sent to scenes that return true from `takes_text`.
+    /// Those scenes only get `Confirm` and `Back` as menu input from the
+    /// keyboard so typing is not mistaken for navigation, and backspace is
+    /// sent as `'\u{8}'`.
+    fn text_input(
+        &mut self,
+        _ctx: &mut Context,
+        _shared: &mut SharedState,
+        _ch: char,
+    ) -> Transition {
+        Transition::None
+    }
+
+    fn takes_text(&self) -> bool {
+        false
+    }
+
+    /// Called when the game is closed, scenes with progress worth keeping
+    /// save it here
+    fn suspend(&mut self) {}
+
+    /// Overlays return true so the scenes below them are drawn first
+    fn is_overlay(&self) -> bool {
+        false
+    }
+
+    /// Size of the screen the scene draws on, the window is resized to fit
+    /// it while the scene is shown
+    fn screen_size(&self) -> (f32, f32) {
+        SCREEN_SIZE
+    }
+
+    /// The game spectators are shown while the scene is on screen, the
+    /// local player's in versus games
+    fn spectated(&self) -> Option<&GameState> {
+        None
+    }
+}
+
+fn menu_input_for_key(keycode: KeyCode) -> Option<MenuInput> {
+    match keycode {
+        KeyCode::Up => Some(MenuInput::Up),
+        KeyCode::Down => Some(MenuInput::Down),
+        KeyCode::Left => Some(MenuInput::Left),
+        KeyCode::Right => Some(MenuInput::Right),
+        KeyCode::Return | KeyCode::Space => Some(MenuInput::Confirm),
+        KeyCode::Escape | KeyCode::Back => Some(MenuInput::Back),
+        _ => None,
+    }
+}
+
+fn menu_input_for_button(button: Button) -> Option<MenuInput> {
+    match button {
+        Button::DPadUp => Some(MenuInput::Up),
+        Button::DPadDown => Some(MenuInput::Down),
+        Button::DPadLeft => Some(MenuInput::Left),
+        Button::DPadRight => Some(MenuInput::Right),
+        Button::South | Button::Start => Some(MenuInput::Confirm),
+        Button::East | Button::Select => Some(MenuInput::Back),
+        _ => None,
+    }
+}
+
+/// Direction a stick is pushed in, -1, 0 or 1 on each axis. The y axis
+/// points up, as reported by the gamepad.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct StickState {
+    x: i8,
+    y: i8,
+}
+
+impl StickState {
+    /// The actions held by each axis, moving on the x axis and soft
+    /// dropping on the y axis, so a diagonal does both
+    fn actions(self) -> [Option<Action>; 2] {
+        let shift = match self.x {
+            -1 => Some(Action::MoveLeft),
+            1 => Some(Action::MoveRight),
+            _ => None,
+        };
+        let drop = if self.y == -1 {
+            Some(Action::SoftDrop)
+        } else {
+            None
+        };
+        [shift, drop]
+    }
+
+    fn menu_input(self) -> Option<MenuInput> {
+        match (self.x, self.y) {
+            (-1, _) => Some(MenuInput::Left),
+            (1, _) => Some(MenuInput::Right),
+            (_, 1) => Some(MenuInput::Up),
+            (_, -1) => Some(MenuInput::Down),
+            _ => None,
+        }
+    }
+}
+
+/// Owns the scenes and dispatches ggez events to the topmost one
+pub(crate) struct SceneStack {
+    scenes: Vec<Box<dyn Scene>>,
+    shared: SharedState,
+    sticks: HashMap<GamepadId, StickState>,
+    /// Gamepads in the order they were first used, the first one belongs
+    /// to the first player
+    pads: Vec<GamepadId>,
+    broadcaster: Option<Broadcaster>,
+}
+
+impl SceneStack {
+    pub(crate) fn new(first: Box<dyn Scene>, settings: Settings) -> Self {
+        let address = settings.spectator_address.trim();
+        let broadcaster = if address.is_empty() {
+            None
+        } else {
+            Broadcaster::bind(address)
+                .map_err(|err| eprintln!("spectators at {}: {}", address, err))
+                .ok()
+        };
+        Self {
+            scenes: vec![first],
+            shared: SharedState::new(settings),
+            sticks: HashMap::new(),
+            pads: Vec::new(),
+            broadcaster,
+        }
+    }
+
+    /// The scene at the bottom of what is drawn, overlays are drawn on top
+    /// of it
+    fn first_visible(&self) -> usize {
+        self.scenes
+            .iter()
+            .rposition(|scene| !scene.is_overlay())
+            .unwrap_or(0)
+    }
+
+    /// Resize the window if the scene now shown draws on a screen of a
+    /// different size
+    fn fit_window(&mut self, ctx: &mut Context) {
+        let size = match self.scenes.get(self.first_visible()) {
+            Some(scene) => scene.screen_size(),
+            None => return,
+        };
+        if size != self.shared.screen_size {
+            self.shared.screen_size = size;
+            if let Err(err) = apply_window_settings(ctx, &self.shared.settings, size) {
+                eprintln!("cannot change the window: {}", err);
+            }
+        }
+    }
+
+    fn apply(&mut self, ctx: &mut Context, transition: Transition) {
+        match transition {
+            Transition::None => (),
+            Transition::Push(scene) => self.scenes.push(scene),
+            Transition::Pop => {
+                self.scenes.pop();
+            }
+            Transition::Replace(scene) => {
+                self.scenes.pop();
+                self.scenes.push(scene);
+            }
+            Transition::Reset(scene) => {
+                self.scenes.clear();
+                self.scenes.push(scene);
+            }
+            Transition::Quit => self.suspend_all(),
+        }
+        if self.scenes.is_empty() {
+            ggez::quit(ctx);
+        } else {
+            self.fit_window(ctx);
+        }
+    }
+
+    /// Let every scene save its progress and drop them all
+    fn suspend_all(&mut self) {
+        for scene in self.scenes.iter_mut() {
+            scene.suspend();
+        }
+        self.scenes.clear();
+    }
+
+    fn takes_actions(&self) -> bool {
+        match self.scenes.last() {
+            Some(scene) => scene.takes_actions(),
+            None => false,
+        }
+    }
+
+    fn players(&self) -> usize {
+        match self.scenes.last() {
+            Some(scene) => scene.players(),
+            None => 1,
+        }
+    }
+
+    /// The player a gamepad belongs to, gamepads are handed out in the
+    /// order they are first used
+    fn pad_player(&mut self, id: GamepadId) -> usize {
+        match self.pads.iter().position(|&pad| pad == id) {
+            Some(player) => player,
+            None => {
+                self.pads.push(id);
+                self.pads.len() - 1
+            }
+        }
+    }
+
+    fn takes_text(&self) -> bool {
+        match self.scenes.last() {
+            Some(scene) => scene.takes_text(),
+            None => false,
+        }
+    }
+
+    fn send_text(&mut self, ctx: &mut Context, ch: char) {
+        let transition = match self.scenes.last_mut() {
+            Some(scene) => scene.text_input(ctx, &mut self.shared, ch),
+            None => Transition::None,
+        };
+        self.apply(ctx, transition);
+    }
+
+    fn send_action(&mut self, ctx: &mut Context, action: Action, pressed: bool) {
+        let transition = match self.scenes.last_mut() {
+            Some(scene) => scene.action(ctx, &mut self.shared, action, pressed),
+            None => Transition::None,
+        };
+        self.apply(ctx, transition);
+    }
+
+    fn send_player_action(
+        &mut self,
+        ctx: &mut Context,
+        player: usize,
+        action: Action,
+        pressed: bool,
+    ) {
+        if player >= self.players() {
+            return;
+        }
+        let transition = match self.scenes.last_mut() {
+            Some(scene) => scene.player_action(ctx, &mut self.shared, player, action, pressed),
+            None => Transition::None,
+        };
+        self.apply(ctx, transition);
+    }
+
+    /// Send an action from a gamepad, to its player if the game has more
+    /// than one
+    fn send_pad_action(&mut self, ctx: &mut Context, id: GamepadId, action: Action, pressed: bool) {
+        if self.players() > 1 {
+            let player = self.pad_player(id);
+            self.send_player_action(ctx, player, action, pressed);
+        } else {
+            self.send_action(ctx, action, pressed);
+        }
+    }
+
+    /// Send the action bound to a key, to the player it belongs to if the
+    /// game has more than one
+    fn send_key_action(&mut self, ctx: &mut Context, keycode: KeyCode, pressed: bool) {
+        if self.players() > 1 {
+            if let Some((player, action)) = self.shared.bindings.player_action(keycode) {
+                self.send_player_action(ctx, player, action, pressed);
+                return;
+            }
+        }
+        if let Some(action) = self.shared.bindings.action(keycode) {
+            self.send_action(ctx, action, pressed);
+        }
+    }
+
+    /// Send the pause key or button to the topmost scene, false if it did
+    /// not take it
+    fn send_pause(&mut self, ctx: &mut Context) -> bool {
+        let transition = match self.scenes.last_mut() {
+            Some(scene) => scene.pause(ctx, &mut self.shared),
+            None => None,
+        };
+        match transition {
+            Some(transition) => {
+                self.apply(ctx, transition);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn send_menu_input(&mut self, ctx: &mut Context, input: MenuInput) {
+        let transition = match self.scenes.last_mut() {
+            Some(scene) => scene.menu_input(ctx, &mut self.shared, input),
+            None => Transition::None,
+        };
+        self.apply(ctx, transition);
+    }
+}
+
+impl event::EventHandler for SceneStack {
+    fn update(&mut self, ctx: &mut Context) -> GameResult {
+        if self.shared.skin.name != self.shared.settings.theme {
+            self.shared.skin = Skin::load(ctx, &self.shared.settings.theme);
+        }
+        let transition = match self.scenes.last_mut() {
+            Some(scene) => scene.update(ctx, &mut self.shared)?,
+            None => Transition::None,
+        };
+        self.apply(ctx, transition);
+        let first_visible = self.first_visible();
+        if let Some(ref mut broadcaster) = self.broadcaster {
+            let game = self.scenes[first_visible..]
+                .iter()
+                .rev()
+                .find_map(|scene| scene.spectated());
+            if let Some(game) = game {
+                broadcaster.publish(game);
+            }
+        }
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        graphics::clear(ctx, [0.0, 0.0, 0.0, 1.0].into());
+        // start from the topmost scene that is not an overlay
+        let first_visible = self.first_visible();
+        for scene in self.scenes[first_visible..].iter_mut() {
+            scene.draw(ctx, &self.shared)?;
+        }
+        graphics::present(ctx)?;
+
+        ggez::timer::yield_now();
+
+        Ok(())
+    }
+
+    fn key_down_event(
+        &mut self,
+        ctx: &mut Context,
+        keycode: KeyCode,
+        _keymod: KeyMods,
+        repeat: bool,
+    ) {
+        if self.takes_actions() {
+            // held keys are repeated by the game's own DAS and ARR
+            if !repeat {
+                self.send_key_action(ctx, keycode, true);
+            }
+        } else if self.takes_text() {
+            match keycode {
+                KeyCode::Return => self.send_menu_input(ctx, MenuInput::Confirm),
+                KeyCode::Escape => self.send_menu_input(ctx, MenuInput::Back),
+                KeyCode::Back => self.send_text(ctx, '\u{8}'),
+                _ => (),
+            }
+        } else {
+            let paused = !repeat
+                && self.shared.bindings.action(keycode) == Some(Action::Pause)
+                && self.send_pause(ctx);
+            if let (false, Some(input)) = (paused, menu_input_for_key(keycode)) {
+                self.send_menu_input(ctx, input);
+            }
+        }
+    }
+
+    /// The window is being closed
+    fn quit_event(&mut self, _ctx: &mut Context) -> bool {
+        self.suspend_all();
+        false
+    }
+
+    fn text_input_event(&mut self, ctx: &mut Context, ch: char) {
+        // control characters such as backspace come as key events instead
+        if self.takes_text() && !ch.is_control() {
+            self.send_text(ctx, ch);
+        }
+    }
+
+    fn key_up_event(&mut self, ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods) {
+        if self.takes_actions() {
+            self.send_key_action(ctx, keycode, false);
+        }
+    }
+
+    fn gamepad_button_down_event(&mut self, ctx: &mut Context, btn: Button, id: GamepadId) {
+        if self.takes_actions() {
+            if let Some(action) = self.shared.bindings.button_action(btn) {
+                self.send_pad_action(ctx, id, action, true);
+            }
+        } else {
+            let paused = self.shared.bindings.button_action(btn) == Some(Action::Pause)
+                && self.send_pause(ctx);
+            if let (false, Some(input)) = (paused, menu_input_for_button(btn)) {
+                self.send_menu_input(ctx, input);
+            }
+        }
+    }
+
+    fn gamepad_button_up_event(&mut self, ctx: &mut Context, btn: Button, id: GamepadId) {
+        if let (Some(action), true) = (
+            self.shared.bindings.button_action(btn),
+            self.takes_actions(),
+        ) {
+            self.send_pad_action(ctx, id, action, false);
+        }
+    }
+
+    /// The left stick and d-pad axes act like the d-pad buttons once pushed
+    /// past the deadzone
+    fn gamepad_axis_event(&mut self, ctx: &mut Context, axis: Axis, value: f32, id: GamepadId) {
+        let deadzone = self.shared.bindings.deadzone();
+        let direction = if value < -deadzone {
+            -1
+        } else if value > deadzone {
+            1
+        } else {
+            0
+        };
+        let old = self.sticks.get(&id).cloned().unwrap_or_default();
+        let mut new = old;
+        match axis {
+            Axis::LeftStickX | Axis::DPadX => new.x = direction,
+            Axis::LeftStickY | Axis::DPadY => new.y = direction,
+            _ => return,
+        }
+        if new == old {
+            return;
+        }
+        self.sticks.insert(id, new);
+
+        if self.takes_actions() {
+            for (&before, &after) in old.actions().iter().zip(new.actions().iter()) {
+                if before == after {
+                    continue;
+                }
+                if let Some(action) = before {
+                    self.send_pad_action(ctx, id, action, false);
+                }
+                if let Some(action) = after {
+                    self.send_pad_action(ctx, id, action, true);
+                }
+            }
+        } else if let (Some(input), true) = (new.menu_input(), new.menu_input() != old.menu_input())
+        {
+            self.send_menu_input(ctx, input);
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use ggez::{Context, GameResult};
+
+use super::menu::{Menu, MenuEvent};
+use super::playing::Playing;
+use super::versus::Versus;
+use super::{MenuInput, Scene, SharedState, Transition};
+use crate::game_state::GameMode;
+
+const MODES: [GameMode; 3] = [GameMode::Marathon, GameMode::Sprint, GameMode::Ultra];
+
+/// Lets the player pick a game mode before starting, or a versus game for
+/// two players or against the computer
+pub(crate) struct ModeSelect {
+    menu: Menu,
+}
+
+impl ModeSelect {
+    pub(crate) fn new() -> Self {
+        let mut entries: Vec<&str> = MODES.iter().map(|mode| mode.name()).collect();
+        entries.push(GameMode::Versus.name());
+        entries.push("Versus CPU");
+        entries.push("Back");
+        Self {
+            menu: Menu::new("Mode", &entries),
+        }
+    }
+}
+
+impl Scene for ModeSelect {
+    fn update(&mut self, _ctx: &mut Context, _shared: &mut SharedState) -> GameResult<Transition> {
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, _shared: &SharedState) -> GameResult {
+        self.menu.draw(ctx, 100.0)
+    }
+
+    fn menu_input(
+        &mut self,
+        _ctx: &mut Context,
+        shared: &mut SharedState,
+        input: MenuInput,
+    ) -> Transition {
+        match self.menu.input(input) {
+            MenuEvent::Confirm(i) if i < MODES.len() => {
+                Transition::Replace(Box::new(Playing::new(MODES[i], &shared.settings, None)))
+            }
+            MenuEvent::Confirm(i) if i == MODES.len() || i == MODES.len() + 1 => {
+                let computer = i == MODES.len() + 1;
+                Transition::Replace(Box::new(Versus::new(&shared.settings, [0, 0], computer)))
+            }
+            MenuEvent::Confirm(_) | MenuEvent::Back => Transition::Pop,
+            _ => Transition::None,
+        }
+    }
+}
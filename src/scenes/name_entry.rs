@@ -0,0 +1,110 @@
+use ggez::graphics::{Scale, Text, TextFragment};
+use ggez::{Context, GameResult};
+
+use super::menu::draw_centered;
+use super::{MenuInput, Scene, SharedState, Transition};
+use crate::game_state::{GameMode, Ruleset};
+use crate::highscores::{Entry, MAX_NAME_LENGTH};
+
+/// Asks for a name after a game that made it into the high score table.
+/// Confirming saves the entry, backing out leaves the table as it was.
+pub(crate) struct NameEntry {
+    mode: GameMode,
+    ruleset: Ruleset,
+    rank: usize,
+    entry: Entry,
+    name: String,
+}
+
+impl NameEntry {
+    pub(crate) fn new(
+        mode: GameMode,
+        ruleset: Ruleset,
+        rank: usize,
+        entry: Entry,
+        last_name: &str,
+    ) -> Self {
+        Self {
+            mode,
+            ruleset,
+            rank,
+            entry,
+            name: last_name.to_string(),
+        }
+    }
+
+    fn save(&mut self, shared: &mut SharedState) {
+        let name = self.name.trim();
+        let name = if name.is_empty() { "Player" } else { name };
+        let mut entry = self.entry.clone();
+        entry.name = name.to_string();
+        shared.high_scores.last_name = entry.name.clone();
+        shared.high_scores.insert(self.mode, self.ruleset, entry);
+        if let Err(err) = shared.high_scores.save() {
+            eprintln!("high scores: {}", err);
+        }
+    }
+}
+
+impl Scene for NameEntry {
+    fn update(&mut self, _ctx: &mut Context, _shared: &mut SharedState) -> GameResult<Transition> {
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, _shared: &SharedState) -> GameResult {
+        let title = Text::new(TextFragment::new("New high score!").scale(Scale::uniform(40.0)));
+        draw_centered(ctx, &title, 100.0)?;
+        let rank = Text::new(
+            TextFragment::new(format!(
+                "#{} in {} - {}",
+                self.rank + 1,
+                self.mode.name(),
+                self.ruleset.name()
+            ))
+            .scale(Scale::uniform(22.0)),
+        );
+        draw_centered(ctx, &rank, 170.0)?;
+        let name = Text::new(
+            TextFragment::new(format!("Name: {}_", self.name)).scale(Scale::uniform(28.0)),
+        );
+        draw_centered(ctx, &name, 250.0)?;
+        let hint = Text::new(
+            TextFragment::new("Enter to save, Escape to skip").scale(Scale::uniform(16.0)),
+        );
+        draw_centered(ctx, &hint, 330.0)
+    }
+
+    fn menu_input(
+        &mut self,
+        _ctx: &mut Context,
+        shared: &mut SharedState,
+        input: MenuInput,
+    ) -> Transition {
+        match input {
+            MenuInput::Confirm => {
+                self.save(shared);
+                Transition::Pop
+            }
+            MenuInput::Back => Transition::Pop,
+            _ => Transition::None,
+        }
+    }
+
+    fn text_input(
+        &mut self,
+        _ctx: &mut Context,
+        _shared: &mut SharedState,
+        ch: char,
+    ) -> Transition {
+        if ch == '\u{8}' {
+            self.name.pop();
+        } else if self.name.chars().count() < MAX_NAME_LENGTH {
+            self.name.push(ch);
+        }
+        Transition::None
+    }
+
+    fn takes_text(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,243 @@
+use std::io;
+use std::net::TcpListener;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+use ggez::graphics::Text;
+use ggez::{timer, Context, GameResult};
+
+use super::board::BoardView;
+use super::menu::draw_centered;
+use super::title::Title;
+use super::versus::{draw_boards, VersusOver};
+use super::{Scene, SharedState, Transition};
+use crate::action::Action;
+use crate::game_state::GameState;
+use crate::netplay::{NetError, Session};
+use crate::settings::Settings;
+use crate::simulation::{Input, TICK};
+use crate::VERSUS_SCREEN_SIZE;
+
+/// Frames simulated at most per drawn frame, as in a single player game
+const MAX_STEPS: u32 = 10;
+
+/// Where the connection to the opponent is at
+enum Link {
+    /// Hosting, until a player connects
+    Listening(TcpListener),
+    /// Shaking hands on a thread, joining can take a while to fail
+    Connecting(Receiver<Result<Session, NetError>>),
+    Playing(Box<Session>),
+}
+
+/// A versus game against a player at another computer, see `netplay`. The
+/// host's board is on the left on both sides. Games over the network
+/// cannot be paused, the pause key leaves them.
+pub(crate) struct Online {
+    link: Link,
+    /// Shown until the game starts
+    status: String,
+    settings: Settings,
+    /// Real time not simulated yet
+    lag: Duration,
+    views: [BoardView; 2],
+}
+
+impl Online {
+    /// Wait for a player to join at the address
+    pub(crate) fn host(address: &str, settings: &Settings) -> Self {
+        let listener = TcpListener::bind(address).and_then(|listener| {
+            listener.set_nonblocking(true)?;
+            Ok(listener)
+        });
+        let (link, status) = match listener {
+            Ok(listener) => {
+                let status = format!("Waiting for an opponent on {}", address);
+                (Link::Listening(listener), status)
+            }
+            Err(err) => (Self::failed(NetError::Io(err)), String::new()),
+        };
+        Self {
+            link,
+            status,
+            settings: settings.clone(),
+            lag: Duration::from_secs(0),
+            views: [BoardView::new(), BoardView::new()],
+        }
+    }
+
+    /// Join the game hosted at the address
+    pub(crate) fn join(address: &str, settings: &Settings) -> Self {
+        let (address, own) = (address.to_string(), settings.clone());
+        Self {
+            status: format!("Connecting to {}", address),
+            link: Self::connect(move || Session::join(address.as_str(), &own)),
+            settings: settings.clone(),
+            lag: Duration::from_secs(0),
+            views: [BoardView::new(), BoardView::new()],
+        }
+    }
+
+    fn connect<F>(connect: F) -> Link
+    where
+        F: FnOnce() -> Result<Session, NetError> + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = sender.send(connect());
+        });
+        Link::Connecting(receiver)
+    }
+
+    /// A link that reports the error on the next update
+    fn failed(err: NetError) -> Link {
+        let (sender, receiver) = mpsc::channel();
+        let _ = sender.send(Err(err));
+        Link::Connecting(receiver)
+    }
+
+    fn ended(title: &str) -> Transition {
+        Transition::Push(Box::new(VersusOver::online(title)))
+    }
+
+    fn error(err: &NetError) -> Transition {
+        eprintln!("online game: {}", err);
+        Self::ended(match err {
+            NetError::Left => "Opponent left",
+            NetError::Desync(_) => "Out of sync",
+            _ => "Connection lost",
+        })
+    }
+
+    /// Simulate the frames due, as far as the opponent's inputs allow
+    fn play(&mut self, ctx: &mut Context) -> Transition {
+        let session = match self.link {
+            Link::Playing(ref mut session) => session,
+            _ => return Transition::None,
+        };
+        self.lag += timer::delta(ctx);
+        let mut steps = 0;
+        while self.lag >= TICK && steps < MAX_STEPS {
+            match session.update() {
+                Ok(true) => self.lag -= TICK,
+                Ok(false) => break,
+                Err(err) => return Self::error(&err),
+            }
+            steps += 1;
+        }
+        // time spent waiting for the opponent is not caught up on
+        self.lag = self.lag.min(TICK);
+        if !session.is_over() {
+            return Transition::None;
+        }
+        // the opponent's reports of the last frames may still be on their
+        // way, they are waited for without holding up the window
+        match session.finish() {
+            Ok(true) => (),
+            Ok(false) => return Transition::None,
+            Err(err) => return Self::error(&err),
+        }
+        let local = session.local();
+        let over = |player| session.game(player).is_over();
+        Self::ended(match (over(local), over(1 - local)) {
+            (false, true) => "You win",
+            (true, false) => "You lose",
+            _ => "Draw",
+        })
+    }
+}
+
+impl Scene for Online {
+    fn update(&mut self, ctx: &mut Context, _shared: &mut SharedState) -> GameResult<Transition> {
+        match self.link {
+            Link::Listening(ref listener) => match listener.accept() {
+                Ok((stream, _)) => {
+                    let settings = self.settings.clone();
+                    self.link = Self::connect(move || Session::host(stream, &settings));
+                }
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => (),
+                Err(err) => self.link = Self::failed(NetError::Io(err)),
+            },
+            Link::Connecting(ref receiver) => match receiver.try_recv() {
+                Ok(Ok(session)) => self.link = Link::Playing(Box::new(session)),
+                Ok(Err(err)) => return Ok(Self::error(&err)),
+                Err(TryRecvError::Empty) => (),
+                Err(TryRecvError::Disconnected) => return Ok(Self::error(&NetError::Closed)),
+            },
+            Link::Playing(_) => return Ok(self.play(ctx)),
+        }
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, shared: &SharedState) -> GameResult {
+        let session = match self.link {
+            Link::Playing(ref session) => session,
+            _ => {
+                draw_centered(ctx, &Text::new(self.status.as_str()), 200.0)?;
+                return draw_centered(ctx, &Text::new("Escape to cancel"), 240.0);
+            }
+        };
+        let label = |player| {
+            let name = if player == session.local() {
+                "You"
+            } else {
+                "Opponent"
+            };
+            format!("P{}  {}", player + 1, name)
+        };
+        draw_boards(
+            ctx,
+            &mut self.views,
+            &shared.skin,
+            [session.game(0), session.game(1)],
+            &[label(0), label(1)],
+        )
+    }
+
+    fn action(
+        &mut self,
+        _ctx: &mut Context,
+        _shared: &mut SharedState,
+        action: Action,
+        pressed: bool,
+    ) -> Transition {
+        let session = match self.link {
+            Link::Playing(ref mut session) => Some(session),
+            _ => None,
+        };
+        match (action, session) {
+            (Action::Pause, session) if pressed => {
+                if let Some(session) = session {
+                    session.leave();
+                }
+                Transition::Reset(Box::new(Title::new()))
+            }
+            (Action::Pause, _) | (Action::Restart, _) | (Action::Autoplay, _) => Transition::None,
+            (_, Some(session)) if pressed => {
+                session.input(Input::Press(action));
+                Transition::None
+            }
+            (_, Some(session)) => {
+                session.input(Input::Release(action));
+                Transition::None
+            }
+            (_, None) => Transition::None,
+        }
+    }
+
+    fn takes_actions(&self) -> bool {
+        true
+    }
+
+    fn screen_size(&self) -> (f32, f32) {
+        VERSUS_SCREEN_SIZE
+    }
+
+    fn spectated(&self) -> Option<&GameState> {
+        match self.link {
+            Link::Playing(ref session) => Some(session.game(session.local())),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use ggez::conf::FullscreenType;
+use ggez::{graphics, Context, GameResult};
+
+use super::menu::{Menu, MenuEvent};
+use super::{MenuInput, Scene, SharedState, Transition};
+use crate::bot::Difficulty;
+use crate::finesse::Trainer;
+use crate::game_state::Ruleset;
+use crate::settings::{self, Settings};
+use crate::theme::Theme;
+
+const DAS: usize = 0;
+const ARR: usize = 1;
+const SOFT_DROP: usize = 2;
+const LOCK_DELAY: usize = 3;
+const RULESET: usize = 4;
+const GHOST: usize = 5;
+const NEXT_QUEUE: usize = 6;
+const FINESSE_TRAINER: usize = 7;
+const GARBAGE_MESSINESS: usize = 8;
+const OPPONENT: usize = 9;
+const THEME: usize = 10;
+const WINDOW_SCALE: usize = 11;
+const FULLSCREEN: usize = 12;
+const BACK: usize = 13;
+
+/// Resize the window to fit a screen of the given size at the scale in the
+/// settings and switch fullscreen on or off. Drawing keeps using unscaled
+/// coordinates.
+pub(crate) fn apply_window_settings(
+    ctx: &mut Context,
+    settings: &Settings,
+    size: (f32, f32),
+) -> GameResult {
+    let mode = if settings.fullscreen {
+        FullscreenType::Desktop
+    } else {
+        FullscreenType::Windowed
+    };
+    graphics::set_fullscreen(ctx, mode)?;
+    let scale = settings.window_scale as f32;
+    graphics::set_drawable_size(ctx, size.0 * scale, size.1 * scale)?;
+    graphics::set_screen_coordinates(ctx, graphics::Rect::new(0.0, 0.0, size.0, size.1))
+}
+
+/// Step a numeric setting by `step` in the given direction, keeping it
+/// within its allowed range
+fn adjust(value: &mut u32, delta: i8, step: u32, range: (u32, u32)) {
+    *value = if delta < 0 {
+        value.saturating_sub(step).max(range.0)
+    } else {
+        (*value + step).min(range.1)
+    };
+}
+
+/// The value after or before `current` in `all`, wrapping around
+fn cycle<T: Clone + PartialEq>(all: &[T], current: T, delta: i8) -> T {
+    let count = all.len();
+    let index = all.iter().position(|value| *value == current).unwrap_or(0);
+    if delta < 0 {
+        all[(index + count - 1) % count].clone()
+    } else {
+        all[(index + 1) % count].clone()
+    }
+}
+
+/// Settings menu, reachable from the title screen and the pause menu.
+/// Values are changed with left and right, and saved when leaving.
+pub(crate) struct Options {
+    menu: Menu,
+    /// Names of the themes found when the menu was opened
+    themes: Vec<String>,
+}
+
+impl Options {
+    pub(crate) fn new() -> Self {
+        Self {
+            menu: Menu::new(
+                "Options",
+                &[
+                    "DAS",
+                    "ARR",
+                    "Soft drop",
+                    "Lock delay",
+                    "Ruleset",
+                    "Ghost",
+                    "Next pieces",
+                    "Finesse trainer",
+                    "Garbage messiness",
+                    "Opponent",
+                    "Theme",
+                    "Window scale",
+                    "Fullscreen",
+                    "Back",
+                ],
+            )
+            .compact(),
+            themes: Theme::available(),
+        }
+    }
+
+    fn on_off(value: bool) -> &'static str {
+        if value {
+            "On"
+        } else {
+            "Off"
+        }
+    }
+
+    fn refresh(&mut self, settings: &Settings) {
+        let labels = [
+            (DAS, format!("DAS: {} ms", settings.das_ms)),
+            (ARR, format!("ARR: {} ms", settings.arr_ms)),
+            (
+                SOFT_DROP,
+                format!("Soft drop: {}x", settings.soft_drop_factor),
+            ),
+            (
+                LOCK_DELAY,
+                format!("Lock delay: {} ms", settings.lock_delay_ms),
+            ),
+            (RULESET, format!("Ruleset: {}", settings.ruleset.name())),
+            (GHOST, format!("Ghost: {}", Self::on_off(settings.ghost))),
+            (NEXT_QUEUE, format!("Next pieces: {}", settings.next_queue)),
+            (
+                FINESSE_TRAINER,
+                format!("Finesse trainer: {}", settings.finesse_trainer.name()),
+            ),
+            (
+                GARBAGE_MESSINESS,
+                format!("Garbage messiness: {}%", settings.garbage_messiness),
+            ),
+            (OPPONENT, format!("Opponent: {}", settings.opponent.name())),
+            (THEME, format!("Theme: {}", settings.theme)),
+            (
+                WINDOW_SCALE,
+                format!("Window scale: {}x", settings.window_scale),
+            ),
+            (
+                FULLSCREEN,
+                format!("Fullscreen: {}", Self::on_off(settings.fullscreen)),
+            ),
+        ];
+        for (index, label) in labels.iter() {
+            self.menu.set_entry(*index, label.clone());
+        }
+    }
+}
+
+impl Scene for Options {
+    fn update(&mut self, _ctx: &mut Context, shared: &mut SharedState) -> GameResult<Transition> {
+        self.refresh(&shared.settings);
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, _shared: &SharedState) -> GameResult {
+        self.menu.draw(ctx, 20.0)
+    }
+
+    fn menu_input(
+        &mut self,
+        ctx: &mut Context,
+        shared: &mut SharedState,
+        input: MenuInput,
+    ) -> Transition {
+        let screen_size = shared.screen_size;
+        let settings = &mut shared.settings;
+        let (index, delta) = match self.menu.input(input) {
+            MenuEvent::Confirm(BACK) | MenuEvent::Back => {
+                if let Err(err) = settings.save() {
+                    eprintln!("settings: {}", err);
+                }
+                return Transition::Pop;
+            }
+            MenuEvent::Confirm(index) => (index, 1),
+            MenuEvent::Adjust(index, delta) => (index, delta),
+            MenuEvent::None => return Transition::None,
+        };
+        match index {
+            DAS => adjust(&mut settings.das_ms, delta, 10, settings::DAS_RANGE),
+            ARR => adjust(&mut settings.arr_ms, delta, 5, settings::ARR_RANGE),
+            SOFT_DROP => adjust(
+                &mut settings.soft_drop_factor,
+                delta,
+                1,
+                settings::SOFT_DROP_RANGE,
+            ),
+            LOCK_DELAY => adjust(
+                &mut settings.lock_delay_ms,
+                delta,
+                50,
+                settings::LOCK_DELAY_RANGE,
+            ),
+            RULESET => settings.ruleset = cycle(&Ruleset::ALL, settings.ruleset, delta),
+            GHOST => settings.ghost = !settings.ghost,
+            NEXT_QUEUE => adjust(
+                &mut settings.next_queue,
+                delta,
+                1,
+                settings::NEXT_QUEUE_RANGE,
+            ),
+            FINESSE_TRAINER => {
+                settings.finesse_trainer = cycle(&Trainer::ALL, settings.finesse_trainer, delta)
+            }
+            GARBAGE_MESSINESS => adjust(
+                &mut settings.garbage_messiness,
+                delta,
+                10,
+                settings::GARBAGE_MESSINESS_RANGE,
+            ),
+            OPPONENT => settings.opponent = cycle(&Difficulty::ALL, settings.opponent, delta),
+            THEME => settings.theme = cycle(&self.themes, settings.theme.clone(), delta),
+            WINDOW_SCALE | FULLSCREEN => {
+                if index == WINDOW_SCALE {
+                    adjust(
+                        &mut settings.window_scale,
+                        delta,
+                        1,
+                        settings::WINDOW_SCALE_RANGE,
+                    );
+                } else {
+                    settings.fullscreen = !settings.fullscreen;
+                }
+                if let Err(err) = apply_window_settings(ctx, settings, screen_size) {
+                    eprintln!("cannot change the window: {}", err);
+                }
+            }
+            _ => (),
+        }
+        Transition::None
+    }
+}
@@ -0,0 +1,154 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ggez::graphics::{Scale, Text, TextFragment};
+use ggez::{Context, GameResult};
+
+use super::menu::{draw_centered, draw_shade, Menu, MenuEvent};
+use super::options::Options;
+use super::playing::Playing;
+use super::title::Title;
+use super::versus::Versus;
+use super::{MenuInput, Scene, SharedState, Transition};
+use crate::config;
+use crate::fumen;
+use crate::game_state::{GameMode, GameState, Setup};
+
+const RESUME: usize = 0;
+const RESTART: usize = 1;
+const EXPORT: usize = 2;
+const OPTIONS: usize = 3;
+const TITLE: usize = 4;
+const QUIT: usize = 5;
+
+/// Characters of the exported fumen per line on screen
+const FUMEN_LINE_LENGTH: usize = 40;
+
+/// Pause menu. It covers the whole playfield so a paused game cannot be
+/// used to plan ahead. The game below is not updated while it is shown,
+/// which stops both the clock and gravity.
+pub(crate) struct Paused {
+    mode: GameMode,
+    /// A versus game against the computer, restarted as one
+    computer: bool,
+    /// Games each player of a versus game has won, kept when it restarts
+    wins: [u32; 2],
+    setup: Option<Setup>,
+    /// The position the game was paused in, as fumen
+    fumen: String,
+    /// The exported fumen and where it was saved, once exported
+    exported: Option<String>,
+    menu: Menu,
+}
+
+impl Paused {
+    pub(crate) fn new(game: &GameState) -> Self {
+        Self {
+            mode: game.mode(),
+            computer: false,
+            wins: [0, 0],
+            setup: game.setup().cloned(),
+            fumen: fumen::encode_setup(&game.to_setup()),
+            exported: None,
+            menu: Menu::new(
+                "PAUSED",
+                &[
+                    "Resume",
+                    "Restart",
+                    "Export fumen",
+                    "Options",
+                    "Title",
+                    "Quit",
+                ],
+            ),
+        }
+    }
+
+    /// Pausing a versus game with the wins so far
+    pub(crate) fn versus(mut self, wins: [u32; 2], computer: bool) -> Self {
+        self.wins = wins;
+        self.computer = computer;
+        self
+    }
+}
+
+impl Scene for Paused {
+    fn update(&mut self, _ctx: &mut Context, _shared: &mut SharedState) -> GameResult<Transition> {
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, _shared: &SharedState) -> GameResult {
+        draw_shade(ctx, 1.0)?;
+        self.menu.draw(ctx, 100.0)?;
+        if let Some(ref exported) = self.exported {
+            let text = Text::new(TextFragment::new(exported.as_str()).scale(Scale::uniform(14.0)));
+            draw_centered(ctx, &text, 400.0)?;
+        }
+        Ok(())
+    }
+
+    /// Pressing pause again resumes the game
+    fn pause(&mut self, _ctx: &mut Context, _shared: &mut SharedState) -> Option<Transition> {
+        Some(Transition::Pop)
+    }
+
+    fn menu_input(
+        &mut self,
+        _ctx: &mut Context,
+        shared: &mut SharedState,
+        input: MenuInput,
+    ) -> Transition {
+        match self.menu.input(input) {
+            MenuEvent::Confirm(RESUME) | MenuEvent::Back => Transition::Pop,
+            MenuEvent::Confirm(RESTART) if self.mode == GameMode::Versus => Transition::Reset(
+                Box::new(Versus::new(&shared.settings, self.wins, self.computer)),
+            ),
+            MenuEvent::Confirm(RESTART) => Transition::Reset(Box::new(Playing::new(
+                self.mode,
+                &shared.settings,
+                self.setup.as_ref(),
+            ))),
+            MenuEvent::Confirm(EXPORT) => {
+                let saved = match save_fumen(&self.fumen) {
+                    Ok(path) => format!("Saved to {}", path.display()),
+                    Err(err) => format!("Could not save: {}", err),
+                };
+                let chars: Vec<char> = self.fumen.chars().collect();
+                let lines: Vec<String> = chars
+                    .chunks(FUMEN_LINE_LENGTH)
+                    .map(|line| line.iter().collect())
+                    .collect();
+                self.exported = Some(format!("{}\n\n{}", lines.join("\n"), saved));
+                self.menu.set_entry(EXPORT, "Fumen exported".to_string());
+                Transition::None
+            }
+            MenuEvent::Confirm(OPTIONS) => Transition::Push(Box::new(Options::new())),
+            MenuEvent::Confirm(TITLE) => Transition::Reset(Box::new(Title::new())),
+            // the game is suspended and can be resumed on the next start
+            MenuEvent::Confirm(QUIT) => Transition::Quit,
+            _ => Transition::None,
+        }
+    }
+
+    fn is_overlay(&self) -> bool {
+        true
+    }
+}
+
+/// Write an exported fumen to a new file in the data directory and return
+/// its path
+fn save_fumen(text: &str) -> io::Result<PathBuf> {
+    let dir = config::data_dir()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no data directory found"))?
+        .join("fumen");
+    fs::create_dir_all(&dir)?;
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let path = dir.join(format!("{}.txt", secs));
+    fs::write(&path, format!("{}\n", text))?;
+    Ok(path)
+}
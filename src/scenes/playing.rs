@@ -0,0 +1,203 @@
+use std::time::Duration;
+
+use ggez::graphics::{self, Scale, Text, TextFragment};
+use ggez::{timer, Context, GameResult};
+
+use super::board::BoardView;
+use super::game_over::GameOver;
+use super::paused::Paused;
+use super::{Scene, SharedState, Transition};
+use crate::action::Action;
+use crate::bot::{Autoplay, Bot, HeuristicBot};
+use crate::game_state::{GameMode, GameState, Setup};
+use crate::replay::Replay;
+use crate::settings::Settings;
+use crate::simulation::{Input, Simulation, TICK};
+use crate::suspend::Suspended;
+use crate::tbp::TbpBot;
+
+/// Frames simulated at most per drawn frame, so a long stall does not make
+/// the game run at full speed for a while to catch up
+const MAX_STEPS: u32 = 10;
+
+/// The scene in which the game is actually played. Every game is recorded
+/// and the replay saved once it ends. The computer can take over the game
+/// and hand it back at any time, its moves are recorded like the player's.
+pub(crate) struct Playing {
+    sim: Simulation,
+    replay: Replay,
+    /// Real time not simulated yet
+    lag: Duration,
+    /// Plays the game instead of the player while it is switched on
+    autoplay: Option<Autoplay>,
+    view: BoardView,
+}
+
+impl Playing {
+    /// Start a new game, from the given position if there is one
+    pub(crate) fn new(mode: GameMode, settings: &Settings, setup: Option<&Setup>) -> Self {
+        let seed = rand::random();
+        let mut replay = Replay::new(mode, seed, settings);
+        replay.setup = setup.cloned();
+        Self {
+            sim: replay.start(),
+            replay,
+            lag: Duration::from_secs(0),
+            autoplay: None,
+            view: BoardView::new(),
+        }
+    }
+
+    /// Continue a game suspended when the game was last closed. A game the
+    /// computer was playing goes back to the player.
+    pub(crate) fn resume(suspended: Suspended) -> Self {
+        let mut playing = Self {
+            sim: suspended.sim,
+            replay: suspended.replay,
+            lag: Duration::from_secs(0),
+            autoplay: None,
+            view: BoardView::new(),
+        };
+        if playing.sim.game().computer_playing() {
+            playing
+                .replay
+                .record(&mut playing.sim, Input::Press(Action::Autoplay));
+        }
+        playing
+    }
+
+    /// Let the computer take over, or give the game back to the player.
+    /// Either way nothing stays held from before. The game itself is told
+    /// who plays, so the replay shows it and the computer's pieces are not
+    /// judged for finesse.
+    fn toggle_autoplay(&mut self, settings: &Settings) {
+        self.replay.record(&mut self.sim, Input::ReleaseAll);
+        self.replay
+            .record(&mut self.sim, Input::Press(Action::Autoplay));
+        if self.autoplay.take().is_none() {
+            let bot = autoplay_bot(settings, self.sim.game());
+            self.autoplay = Some(Autoplay::new(bot));
+        }
+    }
+}
+
+/// The external bot if one is configured and starts, the built-in one
+/// otherwise
+fn autoplay_bot(settings: &Settings, game: &GameState) -> Box<dyn Bot> {
+    if settings.bot_command.trim().is_empty() {
+        return Box::new(HeuristicBot::default());
+    }
+    match TbpBot::launch(&settings.bot_command, game.ruleset()) {
+        Ok(bot) => Box::new(bot),
+        Err(err) => {
+            eprintln!(
+                "bot `{}`: {}; using the built-in bot",
+                settings.bot_command, err
+            );
+            Box::new(HeuristicBot::default())
+        }
+    }
+}
+
+impl Scene for Playing {
+    fn update(&mut self, ctx: &mut Context, shared: &mut SharedState) -> GameResult<Transition> {
+        // settings may have been changed from the pause menu
+        if self.sim.game().settings() != &shared.settings {
+            self.replay.record_settings(&mut self.sim, &shared.settings);
+        }
+        self.lag += timer::delta(ctx);
+        let mut steps = 0;
+        while self.lag >= TICK && !self.sim.game().is_over() {
+            self.lag -= TICK;
+            if let Some(ref mut autoplay) = self.autoplay {
+                for input in autoplay.inputs(self.sim.game()) {
+                    self.replay.record(&mut self.sim, input);
+                }
+            }
+            self.sim.step();
+            steps += 1;
+            if steps == MAX_STEPS {
+                self.lag = Duration::from_secs(0);
+            }
+        }
+        if self.sim.game().is_over() {
+            self.replay.record(&mut self.sim, Input::ReleaseAll);
+            self.replay.finish(&self.sim);
+            if let Err(err) = self.replay.save() {
+                eprintln!("replay: {}", err);
+            }
+            return Ok(Transition::Push(Box::new(GameOver::new(
+                self.sim.game(),
+                &shared.high_scores,
+            ))));
+        }
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, shared: &SharedState) -> GameResult {
+        self.view.draw(ctx, &shared.skin, self.sim.game())?;
+        if self.autoplay.is_some() {
+            let label = Text::new(TextFragment::new("AUTOPLAY").scale(Scale::uniform(16.0)));
+            graphics::draw(ctx, &label, (ggez::mint::Point2 { x: 6.0, y: 6.0 },))?;
+        }
+        Ok(())
+    }
+
+    fn action(
+        &mut self,
+        _ctx: &mut Context,
+        shared: &mut SharedState,
+        action: Action,
+        pressed: bool,
+    ) -> Transition {
+        if !pressed {
+            if self.autoplay.is_none() {
+                self.replay.record(&mut self.sim, Input::Release(action));
+            }
+            return Transition::None;
+        }
+        match action {
+            Action::Pause => {
+                // releases are not seen while the pause menu is open
+                self.replay.record(&mut self.sim, Input::ReleaseAll);
+                Transition::Push(Box::new(Paused::new(self.sim.game())))
+            }
+            Action::Restart => Transition::Reset(Box::new(Playing::new(
+                self.sim.game().mode(),
+                &shared.settings,
+                self.sim.game().setup(),
+            ))),
+            Action::Autoplay => {
+                self.toggle_autoplay(&shared.settings);
+                Transition::None
+            }
+            // the computer is playing
+            _ if self.autoplay.is_some() => Transition::None,
+            _ => {
+                self.replay.record(&mut self.sim, Input::Press(action));
+                Transition::None
+            }
+        }
+    }
+
+    fn takes_actions(&self) -> bool {
+        true
+    }
+
+    fn suspend(&mut self) {
+        if self.sim.game().is_over() {
+            return;
+        }
+        // the pause menu released everything, but the window may also be
+        // closed while keys are held
+        self.replay.record(&mut self.sim, Input::ReleaseAll);
+        let suspended = Suspended::new(self.sim.clone(), self.replay.clone());
+        if let Err(err) = suspended.save() {
+            eprintln!("suspended game: {}", err);
+        }
+    }
+
+    fn spectated(&self) -> Option<&GameState> {
+        Some(self.sim.game())
+    }
+}
@@ -0,0 +1,243 @@
+use std::time::Duration;
+
+use ggez::graphics::{self, Scale, Text, TextFragment};
+use ggez::{timer, Context, GameResult};
+
+use super::board::BoardView;
+use super::menu::{draw_centered, Menu, MenuEvent};
+use super::{MenuInput, Scene, SharedState, Transition};
+use crate::game_state::{format_duration, GameMode, SPRINT_LINES};
+use crate::replay::Replay;
+use crate::simulation::{Simulation, TICK};
+
+/// Number of the newest replays offered in the list
+const LIST_LENGTH: usize = 8;
+
+/// Playback speeds, the viewer starts at normal speed
+const SPEEDS: [f32; 6] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0];
+const NORMAL_SPEED: usize = 2;
+
+/// Frames between snapshots of the simulation, and how far a seek jumps
+const SNAPSHOT_INTERVAL: u32 = 300;
+const SEEK_FRAMES: u32 = 300;
+
+/// Lists the newest saved replays to pick one to watch
+pub(crate) struct ReplayList {
+    replays: Vec<Replay>,
+    menu: Option<Menu>,
+}
+
+impl ReplayList {
+    pub(crate) fn new() -> Self {
+        let paths = Replay::list().unwrap_or_else(|err| {
+            eprintln!("replays: {}", err);
+            Vec::new()
+        });
+        let replays: Vec<Replay> = paths
+            .iter()
+            .filter_map(|path| match Replay::load(path) {
+                Ok(replay) => Some(replay),
+                Err(err) => {
+                    eprintln!("replay {}: {}", path.display(), err);
+                    None
+                }
+            })
+            .take(LIST_LENGTH)
+            .collect();
+        let labels: Vec<String> = replays
+            .iter()
+            .map(|replay| {
+                let result = match replay.mode {
+                    GameMode::Sprint if replay.lines >= SPRINT_LINES => {
+                        format_duration(TICK * replay.frames)
+                    }
+                    GameMode::Sprint => format!("{} lines", replay.lines),
+                    _ => format!("{} pts", replay.score),
+                };
+                format!("{} {} {}", replay.date, replay.mode.name(), result)
+            })
+            .collect();
+        let labels: Vec<&str> = labels.iter().map(String::as_str).collect();
+        let menu = if labels.is_empty() {
+            None
+        } else {
+            Some(Menu::new("Replays", &labels))
+        };
+        Self { replays, menu }
+    }
+}
+
+impl Scene for ReplayList {
+    fn update(&mut self, _ctx: &mut Context, _shared: &mut SharedState) -> GameResult<Transition> {
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, _shared: &SharedState) -> GameResult {
+        match self.menu {
+            Some(ref menu) => menu.draw(ctx, 40.0),
+            None => {
+                draw_centered(ctx, &Text::new("No replays recorded yet"), 200.0)?;
+                draw_centered(ctx, &Text::new("Press any key"), 240.0)
+            }
+        }
+    }
+
+    fn menu_input(
+        &mut self,
+        _ctx: &mut Context,
+        _shared: &mut SharedState,
+        input: MenuInput,
+    ) -> Transition {
+        let menu = match self.menu {
+            Some(ref mut menu) => menu,
+            None => return Transition::Pop,
+        };
+        match menu.input(input) {
+            MenuEvent::Confirm(index) => {
+                Transition::Push(Box::new(ReplayViewer::new(self.replays[index].clone())))
+            }
+            MenuEvent::Back => Transition::Pop,
+            _ => Transition::None,
+        }
+    }
+}
+
+/// Playback state at a frame, to seek without simulating from the start
+#[derive(Clone)]
+struct Snapshot {
+    sim: Simulation,
+    next_input: usize,
+    next_setting: usize,
+}
+
+/// Plays a replay back by simulating the game again from its inputs.
+/// Confirm pauses, up and down change the speed, left and right seek.
+pub(crate) struct ReplayViewer {
+    replay: Replay,
+    state: Snapshot,
+    /// Taken every `SNAPSHOT_INTERVAL` frames as playback first gets there
+    snapshots: Vec<Snapshot>,
+    speed: usize,
+    paused: bool,
+    lag: Duration,
+    view: BoardView,
+}
+
+impl ReplayViewer {
+    pub(crate) fn new(replay: Replay) -> Self {
+        let state = Snapshot {
+            sim: replay.start(),
+            next_input: 0,
+            next_setting: 0,
+        };
+        Self {
+            replay,
+            snapshots: vec![state.clone()],
+            state,
+            speed: NORMAL_SPEED,
+            paused: false,
+            lag: Duration::from_secs(0),
+            view: BoardView::new(),
+        }
+    }
+
+    fn at_end(&self) -> bool {
+        self.state.sim.frame() >= self.replay.frames
+    }
+
+    /// Feed the inputs of the current frame and simulate it
+    fn advance(&mut self) {
+        let state = &mut self.state;
+        let frame = state.sim.frame();
+        if frame == self.snapshots.len() as u32 * SNAPSHOT_INTERVAL {
+            self.snapshots.push(state.clone());
+        }
+        while let Some((at, settings)) = self.replay.settings_changes.get(state.next_setting) {
+            if *at != frame {
+                break;
+            }
+            state.sim.set_settings(settings);
+            state.next_setting += 1;
+        }
+        while let Some(&(at, input)) = self.replay.inputs.get(state.next_input) {
+            if at != frame {
+                break;
+            }
+            state.sim.apply(input);
+            state.next_input += 1;
+        }
+        state.sim.step();
+    }
+
+    /// Jump to a frame, going back to the last snapshot before it first
+    /// if the frame was already played
+    fn seek(&mut self, target: u32) {
+        let target = target.min(self.replay.frames);
+        if target < self.state.sim.frame() {
+            let index = ((target / SNAPSHOT_INTERVAL) as usize).min(self.snapshots.len() - 1);
+            self.state = self.snapshots[index].clone();
+        }
+        while self.state.sim.frame() < target {
+            self.advance();
+        }
+        self.lag = Duration::from_secs(0);
+    }
+
+    fn draw_status(&self, ctx: &mut Context) -> GameResult {
+        let state = if self.at_end() {
+            "End"
+        } else if self.paused {
+            "Paused"
+        } else {
+            "Replay"
+        };
+        let status = format!(
+            "{} {}x\n{} / {}",
+            state,
+            SPEEDS[self.speed],
+            format_duration(TICK * self.state.sim.frame()),
+            format_duration(TICK * self.replay.frames)
+        );
+        let text = Text::new(TextFragment::new(status).scale(Scale::uniform(14.0)));
+        let x = crate::SCREEN_SIZE.0 - 150.0;
+        graphics::draw(ctx, &text, (ggez::mint::Point2 { x, y: 470.0 },))
+    }
+}
+
+impl Scene for ReplayViewer {
+    fn update(&mut self, ctx: &mut Context, _shared: &mut SharedState) -> GameResult<Transition> {
+        if self.paused || self.at_end() {
+            return Ok(Transition::None);
+        }
+        let dt = timer::delta(ctx).as_nanos() as f64 * f64::from(SPEEDS[self.speed]);
+        self.lag += Duration::from_nanos(dt as u64);
+        while self.lag >= TICK && !self.at_end() {
+            self.lag -= TICK;
+            self.advance();
+        }
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, shared: &SharedState) -> GameResult {
+        self.view.draw(ctx, &shared.skin, self.state.sim.game())?;
+        self.draw_status(ctx)
+    }
+
+    fn menu_input(
+        &mut self,
+        _ctx: &mut Context,
+        _shared: &mut SharedState,
+        input: MenuInput,
+    ) -> Transition {
+        let frame = self.state.sim.frame();
+        match input {
+            MenuInput::Confirm => self.paused = !self.paused,
+            MenuInput::Up => self.speed = (self.speed + 1).min(SPEEDS.len() - 1),
+            MenuInput::Down => self.speed = self.speed.saturating_sub(1),
+            MenuInput::Left => self.seek(frame.saturating_sub(SEEK_FRAMES)),
+            MenuInput::Right => self.seek(frame + SEEK_FRAMES),
+            MenuInput::Back => return Transition::Pop,
+        }
+        Transition::None
+    }
+}
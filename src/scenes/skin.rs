@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+
+use ggez::graphics::{Font, Image};
+use ggez::{Context, GameResult};
+
+use crate::theme::{Block, Manifest, Texture, Theme};
+
+/// A theme with its images and font ready to draw
+pub(crate) struct Skin {
+    /// The theme it was made from, empty until one is loaded
+    pub(crate) name: String,
+    pub(crate) manifest: Manifest,
+    blocks: HashMap<Block, Image>,
+    pub(crate) background_image: Option<Image>,
+    pub(crate) font: Option<Font>,
+}
+
+impl Default for Skin {
+    /// The flat look, before any theme is loaded
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            manifest: Manifest::default(),
+            blocks: HashMap::new(),
+            background_image: None,
+            font: None,
+        }
+    }
+}
+
+impl Skin {
+    /// Load a theme. One that cannot be loaded is reported and the flat
+    /// look is used under its name, so it is not tried again every frame.
+    pub(crate) fn load(ctx: &mut Context, name: &str) -> Self {
+        let skin = match Theme::load(name) {
+            Ok(theme) => Self::from_theme(ctx, &theme).map_err(|err| err.to_string()),
+            Err(err) => Err(err.to_string()),
+        };
+        skin.unwrap_or_else(|err| {
+            eprintln!("theme {}: {}; using the flat look", name, err);
+            Self {
+                name: name.to_string(),
+                ..Self::default()
+            }
+        })
+    }
+
+    fn from_theme(ctx: &mut Context, theme: &Theme) -> GameResult<Self> {
+        let image = |ctx: &mut Context, texture: &Texture| {
+            Image::from_rgba8(ctx, texture.width, texture.height, &texture.rgba)
+        };
+        let mut blocks = HashMap::new();
+        for (&block, texture) in theme.blocks.iter() {
+            blocks.insert(block, image(ctx, texture)?);
+        }
+        let background_image = match theme.background_image {
+            Some(ref texture) => Some(image(ctx, texture)?),
+            None => None,
+        };
+        let font = match theme.font {
+            Some(ref bytes) => Some(Font::new_glyph_font_bytes(ctx, bytes)?),
+            None => None,
+        };
+        Ok(Self {
+            name: theme.name.clone(),
+            manifest: theme.manifest.clone(),
+            blocks,
+            background_image,
+            font,
+        })
+    }
+
+    pub(crate) fn texture(&self, block: Block) -> Option<&Image> {
+        self.blocks.get(&block)
+    }
+}
@@ -0,0 +1,99 @@
+use ggez::{Context, GameResult};
+
+use super::high_scores::ScoreBoard;
+use super::menu::{Menu, MenuEvent};
+use super::mode_select::ModeSelect;
+use super::options::Options;
+use super::playing::Playing;
+use super::replay::ReplayList;
+use super::{MenuInput, Scene, SharedState, Transition};
+use crate::suspend::Suspended;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Entry {
+    Resume,
+    Play,
+    HighScores,
+    Replays,
+    Options,
+    Quit,
+}
+
+impl Entry {
+    fn label(self) -> &'static str {
+        match self {
+            Entry::Resume => "Resume",
+            Entry::Play => "Play",
+            Entry::HighScores => "High Scores",
+            Entry::Replays => "Replays",
+            Entry::Options => "Options",
+            Entry::Quit => "Quit",
+        }
+    }
+}
+
+/// The first screen shown when the game starts. A game suspended when the
+/// game was last closed is offered first.
+pub(crate) struct Title {
+    entries: Vec<Entry>,
+    menu: Menu,
+}
+
+impl Title {
+    pub(crate) fn new() -> Self {
+        let mut entries = vec![
+            Entry::Play,
+            Entry::HighScores,
+            Entry::Replays,
+            Entry::Options,
+            Entry::Quit,
+        ];
+        if Suspended::exists() {
+            entries.insert(0, Entry::Resume);
+        }
+        let labels: Vec<&str> = entries.iter().map(|entry| entry.label()).collect();
+        Self {
+            menu: Menu::new("TETRIS", &labels),
+            entries,
+        }
+    }
+}
+
+impl Scene for Title {
+    fn update(&mut self, _ctx: &mut Context, _shared: &mut SharedState) -> GameResult<Transition> {
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, _shared: &SharedState) -> GameResult {
+        self.menu.draw(ctx, 100.0)
+    }
+
+    fn menu_input(
+        &mut self,
+        _ctx: &mut Context,
+        shared: &mut SharedState,
+        input: MenuInput,
+    ) -> Transition {
+        let entry = match self.menu.input(input) {
+            MenuEvent::Confirm(index) => self.entries[index],
+            MenuEvent::Back => Entry::Quit,
+            _ => return Transition::None,
+        };
+        match entry {
+            Entry::Resume => match Suspended::take() {
+                Ok(suspended) => Transition::Replace(Box::new(Playing::resume(suspended))),
+                Err(err) => {
+                    eprintln!("suspended game: {}", err);
+                    Transition::Replace(Box::new(Title::new()))
+                }
+            },
+            Entry::Play => Transition::Push(Box::new(ModeSelect::new())),
+            Entry::HighScores => {
+                Transition::Push(Box::new(ScoreBoard::new(shared.settings.ruleset)))
+            }
+            Entry::Replays => Transition::Push(Box::new(ReplayList::new())),
+            Entry::Options => Transition::Push(Box::new(Options::new())),
+            Entry::Quit => Transition::Quit,
+        }
+    }
+}
@@ -0,0 +1,306 @@
+use std::time::Duration;
+
+use ggez::graphics::{self, Scale, Text, TextFragment};
+use ggez::{timer, Context, GameResult};
+
+use super::board::BoardView;
+use super::menu::{draw_centered, draw_shade, Menu, MenuEvent};
+use super::paused::Paused;
+use super::skin::Skin;
+use super::title::Title;
+use super::{MenuInput, Scene, SharedState, Transition};
+use crate::action::Action;
+use crate::bot::Opponent;
+use crate::game_state::{GameMode, GameState};
+use crate::settings::Settings;
+use crate::simulation::{Input, Simulation, TICK};
+use crate::{SCREEN_SIZE, VERSUS_SCREEN_SIZE};
+
+/// Frames simulated at most per drawn frame, as in a single player game
+const MAX_STEPS: u32 = 10;
+
+const REMATCH: usize = 0;
+
+/// Two players side by side in one window, each with their own keys or
+/// gamepad, or a player against the computer on the right. Both boards are
+/// dealt the same pieces, and the rows one player clears are sent to the
+/// other as garbage. Versus games are not recorded.
+pub(crate) struct Versus {
+    players: [Simulation; 2],
+    /// Plays the second board in games against the computer
+    opponent: Option<Opponent>,
+    /// Real time not simulated yet
+    lag: Duration,
+    /// Games won by each player since the first one of the session
+    wins: [u32; 2],
+    views: [BoardView; 2],
+}
+
+impl Versus {
+    /// A new game, with the computer as the second player at the
+    /// difficulty of the settings if `computer` is set
+    pub(crate) fn new(settings: &Settings, wins: [u32; 2], computer: bool) -> Self {
+        let seed = rand::random();
+        let player = || Simulation::new(GameMode::Versus, settings, seed);
+        let opponent = if computer {
+            Some(Opponent::new(settings.opponent.strength(), !seed))
+        } else {
+            None
+        };
+        Self {
+            players: [player(), player()],
+            opponent,
+            lag: Duration::from_secs(0),
+            wins,
+            views: [BoardView::new(), BoardView::new()],
+        }
+    }
+
+    /// Simulate a frame of both games and exchange the garbage they sent
+    fn step(&mut self) {
+        if let Some(ref mut opponent) = self.opponent {
+            for input in opponent.inputs(self.players[1].game()) {
+                self.players[1].apply(input);
+            }
+        }
+        for sim in self.players.iter_mut() {
+            sim.step();
+        }
+        let sent = [self.players[0].take_attack(), self.players[1].take_attack()];
+        self.players[0].receive_garbage(sent[1]);
+        self.players[1].receive_garbage(sent[0]);
+    }
+
+    fn pause(&mut self) -> Transition {
+        // releases are not seen while the pause menu is open
+        for sim in self.players.iter_mut() {
+            sim.apply(Input::ReleaseAll);
+        }
+        let paused = Paused::new(self.players[0].game()).versus(self.wins, self.opponent.is_some());
+        Transition::Push(Box::new(paused))
+    }
+}
+
+impl Scene for Versus {
+    fn update(&mut self, ctx: &mut Context, shared: &mut SharedState) -> GameResult<Transition> {
+        // settings may have been changed from the pause menu
+        if self.players[0].game().settings() != &shared.settings {
+            for sim in self.players.iter_mut() {
+                sim.set_settings(&shared.settings);
+            }
+            if let Some(ref mut opponent) = self.opponent {
+                opponent.set_strength(shared.settings.opponent.strength());
+            }
+        }
+        let over = |versus: &Self| versus.players.iter().any(|sim| sim.game().is_over());
+        self.lag += timer::delta(ctx);
+        let mut steps = 0;
+        while self.lag >= TICK && !over(self) {
+            self.lag -= TICK;
+            self.step();
+            steps += 1;
+            if steps == MAX_STEPS {
+                self.lag = Duration::from_secs(0);
+            }
+        }
+        if over(self) {
+            // both can top out on the same frame, which is a draw
+            let winner = match (
+                self.players[0].game().is_over(),
+                self.players[1].game().is_over(),
+            ) {
+                (false, true) => Some(0),
+                (true, false) => Some(1),
+                _ => None,
+            };
+            if let Some(winner) = winner {
+                self.wins[winner] += 1;
+            }
+            let computer = self.opponent.is_some();
+            return Ok(Transition::Push(Box::new(VersusOver::new(
+                winner, self.wins, computer,
+            ))));
+        }
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, shared: &SharedState) -> GameResult {
+        let computer = self.opponent.is_some();
+        let labels = [0, 1].map(|i| format!("{}  {} wins", name(i, computer), self.wins[i]));
+        draw_boards(
+            ctx,
+            &mut self.views,
+            &shared.skin,
+            [self.players[0].game(), self.players[1].game()],
+            &labels,
+        )
+    }
+
+    /// Keys that belong to neither player, only pausing is used. Against
+    /// the computer all keys belong to the player.
+    fn action(
+        &mut self,
+        ctx: &mut Context,
+        shared: &mut SharedState,
+        action: Action,
+        pressed: bool,
+    ) -> Transition {
+        match action {
+            Action::Pause if pressed => self.pause(),
+            _ if self.opponent.is_some() => self.player_action(ctx, shared, 0, action, pressed),
+            _ => Transition::None,
+        }
+    }
+
+    fn takes_actions(&self) -> bool {
+        true
+    }
+
+    fn players(&self) -> usize {
+        if self.opponent.is_some() {
+            1
+        } else {
+            self.players.len()
+        }
+    }
+
+    fn player_action(
+        &mut self,
+        _ctx: &mut Context,
+        _shared: &mut SharedState,
+        player: usize,
+        action: Action,
+        pressed: bool,
+    ) -> Transition {
+        let sim = &mut self.players[player];
+        match action {
+            Action::Pause if pressed => self.pause(),
+            Action::Pause | Action::Restart | Action::Autoplay => Transition::None,
+            _ if pressed => {
+                sim.apply(Input::Press(action));
+                Transition::None
+            }
+            _ => {
+                sim.apply(Input::Release(action));
+                Transition::None
+            }
+        }
+    }
+
+    fn screen_size(&self) -> (f32, f32) {
+        VERSUS_SCREEN_SIZE
+    }
+
+    fn spectated(&self) -> Option<&GameState> {
+        Some(self.players[0].game())
+    }
+}
+
+/// What the players of a local game are called
+fn name(player: usize, computer: bool) -> String {
+    if computer && player == 1 {
+        "CPU".to_string()
+    } else {
+        format!("P{}", player + 1)
+    }
+}
+
+/// Draw two games side by side, each as in a single player game moved to
+/// its half of the window, with a label above it
+pub(super) fn draw_boards(
+    ctx: &mut Context,
+    views: &mut [BoardView; 2],
+    skin: &Skin,
+    games: [&GameState; 2],
+    labels: &[String; 2],
+) -> GameResult {
+    let boards = views.iter_mut().zip(games.iter()).zip(labels.iter());
+    for (i, ((view, game), label)) in boards.enumerate() {
+        let left = i as f32 * SCREEN_SIZE.0;
+        graphics::set_screen_coordinates(
+            ctx,
+            graphics::Rect::new(-left, 0.0, VERSUS_SCREEN_SIZE.0, VERSUS_SCREEN_SIZE.1),
+        )?;
+        view.draw(ctx, skin, game)?;
+        let label = Text::new(TextFragment::new(label.as_str()).scale(Scale::uniform(16.0)));
+        graphics::draw(ctx, &label, (ggez::mint::Point2 { x: 6.0, y: 6.0 },))?;
+    }
+    graphics::set_screen_coordinates(
+        ctx,
+        graphics::Rect::new(0.0, 0.0, VERSUS_SCREEN_SIZE.0, VERSUS_SCREEN_SIZE.1),
+    )
+}
+
+/// Shown on top of both boards once a player has topped out, or once a
+/// game over the network has ended for any reason
+pub(super) struct VersusOver {
+    /// Games won by each player, shown under the title of local games
+    wins: Option<[u32; 2]>,
+    /// The second player was the computer
+    computer: bool,
+    menu: Menu,
+}
+
+impl VersusOver {
+    fn new(winner: Option<usize>, wins: [u32; 2], computer: bool) -> Self {
+        let title = match winner {
+            Some(0) if computer => "You win".to_string(),
+            Some(_) if computer => "CPU wins".to_string(),
+            Some(player) => format!("Player {} wins", player + 1),
+            None => "Draw".to_string(),
+        };
+        Self {
+            wins: Some(wins),
+            computer,
+            menu: Menu::new(&title, &["Rematch", "Title"]),
+        }
+    }
+
+    /// The end of a game over the network, where only going back to the
+    /// title is offered
+    pub(super) fn online(title: &str) -> Self {
+        Self {
+            wins: None,
+            computer: false,
+            menu: Menu::new(title, &["Title"]),
+        }
+    }
+}
+
+impl Scene for VersusOver {
+    fn update(&mut self, _ctx: &mut Context, _shared: &mut SharedState) -> GameResult<Transition> {
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, _shared: &SharedState) -> GameResult {
+        draw_shade(ctx, 0.6)?;
+        self.menu.draw_title(ctx, 150.0)?;
+        if let Some(wins) = self.wins {
+            let score = Text::new(format!("{} - {}", wins[0], wins[1]));
+            draw_centered(ctx, &score, 205.0)?;
+        }
+        self.menu.draw_entries(ctx, 260.0)
+    }
+
+    fn menu_input(
+        &mut self,
+        _ctx: &mut Context,
+        shared: &mut SharedState,
+        input: MenuInput,
+    ) -> Transition {
+        match (self.menu.input(input), self.wins) {
+            (MenuEvent::Confirm(REMATCH), Some(wins)) => {
+                let versus = Versus::new(&shared.settings, wins, self.computer);
+                Transition::Reset(Box::new(versus))
+            }
+            (MenuEvent::Confirm(_), _) | (MenuEvent::Back, _) => {
+                Transition::Reset(Box::new(Title::new()))
+            }
+            _ => Transition::None,
+        }
+    }
+
+    fn is_overlay(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,262 @@
+//! Player preferences, stored in `settings.toml` in the config directory.
+//!
+//! The file carries a `version` number. When the format changes, bump
+//! `CURRENT_VERSION` and add a step to `MIGRATIONS` that upgrades a table of
+//! the previous version, so files written by older builds keep working.
+use std::path::PathBuf;
+use std::time::Duration;
+use std::{fmt, fs, io};
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::bot::Difficulty;
+use crate::config::config_dir;
+use crate::finesse::Trainer;
+use crate::game_state::Ruleset;
+use crate::theme::DEFAULT_THEME;
+
+const FILE_NAME: &str = "settings.toml";
+
+/// Version of the settings format written by this build
+pub const CURRENT_VERSION: u32 = 2;
+
+/// Upgrade steps, the one at index `i` turns version `i` into version `i + 1`
+const MIGRATIONS: &[fn(&mut toml::value::Table)] = &[from_unversioned, from_v1];
+
+/// Files without a version number have the same layout as version 1
+fn from_unversioned(_table: &mut toml::value::Table) {}
+
+/// Version 2 added the ruleset. Players upgrading from version 1 keep the
+/// classic random pieces they were used to, new players get 7-bags.
+fn from_v1(table: &mut toml::value::Table) {
+    table.insert(
+        "ruleset".to_string(),
+        toml::Value::String("classic".to_string()),
+    );
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub version: u32,
+    /// Delayed auto shift in milliseconds
+    pub das_ms: u32,
+    /// Auto repeat rate in milliseconds, 0 moves straight to the wall
+    pub arr_ms: u32,
+    /// How many times faster than gravity a soft drop is
+    pub soft_drop_factor: u32,
+    /// How long a landed piece can still be moved, in milliseconds. With 0
+    /// the piece locks on the next gravity step, as in the original game.
+    pub lock_delay_ms: u32,
+    /// Show where the current piece will land
+    pub ghost: bool,
+    /// Number of upcoming pieces shown
+    pub next_queue: u32,
+    /// Sound volume in percent, kept for when the game has sound. It is
+    /// not in the options menu since nothing plays yet.
+    pub volume: u32,
+    /// Window size as a multiple of the base size
+    pub window_scale: u32,
+    pub fullscreen: bool,
+    /// How pieces are dealt in new games
+    pub ruleset: Ruleset,
+    /// What happens when a piece is placed with more key presses than
+    /// needed
+    pub finesse_trainer: Trainer,
+    /// Chance in percent that a row of garbage is open in another column
+    /// than the row below it from the same attack
+    pub garbage_messiness: u32,
+    /// How well the computer plays in versus games against it
+    pub opponent: Difficulty,
+    /// Command that starts a bot speaking the Tetris Bot Protocol, which
+    /// plays instead of the built-in one when the computer takes over.
+    /// Arguments are separated by spaces.
+    pub bot_command: String,
+    /// Address to publish the game on for spectators, such as
+    /// `127.0.0.1:7462`. Nothing is published when it is empty.
+    pub spectator_address: String,
+    /// Directory name of the theme the window is drawn in, see `theme`
+    pub theme: String,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            version: CURRENT_VERSION,
+            das_ms: 167,
+            arr_ms: 33,
+            soft_drop_factor: 20,
+            lock_delay_ms: 500,
+            ghost: true,
+            next_queue: 3,
+            volume: 80,
+            window_scale: 1,
+            fullscreen: false,
+            ruleset: Ruleset::Guideline,
+            finesse_trainer: Trainer::Off,
+            garbage_messiness: 0,
+            opponent: Difficulty::Medium,
+            bot_command: String::new(),
+            spectator_address: String::new(),
+            theme: DEFAULT_THEME.to_string(),
+        }
+    }
+}
+
+/// Why the settings could not be loaded or saved
+#[derive(Debug)]
+pub enum SettingsError {
+    NoConfigDir,
+    Io(io::Error),
+    Parse(toml::de::Error),
+    Serialize(toml::ser::Error),
+    UnknownVersion(u32),
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SettingsError::NoConfigDir => write!(f, "no config directory found"),
+            SettingsError::Io(err) => write!(f, "{}", err),
+            SettingsError::Parse(err) => write!(f, "invalid settings: {}", err),
+            SettingsError::Serialize(err) => write!(f, "cannot write settings: {}", err),
+            SettingsError::UnknownVersion(version) => write!(
+                f,
+                "settings version {} is newer than this game (version {})",
+                version, CURRENT_VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
+/// Allowed range of every numeric setting
+pub const DAS_RANGE: (u32, u32) = (0, 500);
+pub const ARR_RANGE: (u32, u32) = (0, 200);
+pub const SOFT_DROP_RANGE: (u32, u32) = (1, 40);
+pub const LOCK_DELAY_RANGE: (u32, u32) = (0, 2000);
+pub const NEXT_QUEUE_RANGE: (u32, u32) = (1, 5);
+pub const VOLUME_RANGE: (u32, u32) = (0, 100);
+pub const WINDOW_SCALE_RANGE: (u32, u32) = (1, 3);
+pub const GARBAGE_MESSINESS_RANGE: (u32, u32) = (0, 100);
+
+fn clamp(value: u32, range: (u32, u32)) -> u32 {
+    value.max(range.0).min(range.1)
+}
+
+impl Settings {
+    pub fn path() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join(FILE_NAME))
+    }
+
+    /// Load the settings, falling back to the defaults when there is no
+    /// settings file yet or it cannot be read
+    pub fn load_or_default() -> Self {
+        match Self::load() {
+            Ok(settings) => settings,
+            Err(SettingsError::Io(ref err)) if err.kind() == io::ErrorKind::NotFound => {
+                Self::default()
+            }
+            Err(err) => {
+                eprintln!("settings: {}; using default settings", err);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn load() -> Result<Self, SettingsError> {
+        let path = Self::path().ok_or(SettingsError::NoConfigDir)?;
+        let text = fs::read_to_string(path).map_err(SettingsError::Io)?;
+        Self::from_toml(&text)
+    }
+
+    /// Parse a settings file, upgrading it to the current version first
+    pub fn from_toml(text: &str) -> Result<Self, SettingsError> {
+        let mut table: toml::value::Table = toml::from_str(text).map_err(SettingsError::Parse)?;
+        let version = table
+            .get("version")
+            .and_then(|version| version.as_integer())
+            .unwrap_or(0) as u32;
+        if version > CURRENT_VERSION {
+            return Err(SettingsError::UnknownVersion(version));
+        }
+        for migrate in &MIGRATIONS[version as usize..] {
+            migrate(&mut table);
+        }
+        table.insert(
+            "version".to_string(),
+            toml::Value::Integer(i64::from(CURRENT_VERSION)),
+        );
+        let settings: Settings = toml::Value::Table(table)
+            .try_into()
+            .map_err(SettingsError::Parse)?;
+        Ok(settings.clamped())
+    }
+
+    pub fn save(&self) -> Result<(), SettingsError> {
+        let path = Self::path().ok_or(SettingsError::NoConfigDir)?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(SettingsError::Io)?;
+        }
+        let text = toml::to_string(self).map_err(SettingsError::Serialize)?;
+        fs::write(path, text).map_err(SettingsError::Io)
+    }
+
+    /// Bring every value back into its allowed range
+    pub fn clamped(mut self) -> Self {
+        self.das_ms = clamp(self.das_ms, DAS_RANGE);
+        self.arr_ms = clamp(self.arr_ms, ARR_RANGE);
+        self.soft_drop_factor = clamp(self.soft_drop_factor, SOFT_DROP_RANGE);
+        self.lock_delay_ms = clamp(self.lock_delay_ms, LOCK_DELAY_RANGE);
+        self.next_queue = clamp(self.next_queue, NEXT_QUEUE_RANGE);
+        self.volume = clamp(self.volume, VOLUME_RANGE);
+        self.window_scale = clamp(self.window_scale, WINDOW_SCALE_RANGE);
+        self.garbage_messiness = clamp(self.garbage_messiness, GARBAGE_MESSINESS_RANGE);
+        self
+    }
+
+    pub fn das(&self) -> Duration {
+        Duration::from_millis(u64::from(self.das_ms))
+    }
+
+    pub fn arr(&self) -> Duration {
+        Duration::from_millis(u64::from(self.arr_ms))
+    }
+
+    pub fn lock_delay(&self) -> Duration {
+        Duration::from_millis(u64::from(self.lock_delay_ms))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let settings = Settings {
+            das_ms: 100,
+            ghost: false,
+            ..Settings::default()
+        };
+        let text = toml::to_string(&settings).unwrap();
+        assert_eq!(Settings::from_toml(&text).unwrap(), settings);
+    }
+
+    #[test]
+    fn test_old_and_partial_files() {
+        // no version and missing fields
+        let settings = Settings::from_toml("arr_ms = 0\nnext_queue = 9").unwrap();
+        assert_eq!(settings.version, CURRENT_VERSION);
+        assert_eq!(settings.arr_ms, 0);
+        assert_eq!(settings.next_queue, NEXT_QUEUE_RANGE.1);
+        assert_eq!(settings.das_ms, Settings::default().das_ms);
+        assert_eq!(settings.ruleset, Ruleset::Classic);
+
+        match Settings::from_toml("version = 99") {
+            Err(SettingsError::UnknownVersion(99)) => (),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,169 @@
+//! Runs a game in fixed time steps from a log of inputs. Given the same
+//! seed, settings and inputs at the same frames it always plays out the
+//! same way, which is what replays rely on.
+use std::time::Duration;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::action::Action;
+use crate::game_state::{GameMode, GameState, Setup};
+use crate::handling::Handling;
+use crate::settings::Settings;
+
+/// Length of one frame of the simulation, 60 frames per second
+pub const TICK: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+/// A change in the state of the player's controls
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Input {
+    Press(Action),
+    Release(Action),
+    /// Forget every held control, used when the game is paused
+    ReleaseAll,
+}
+
+impl Input {
+    /// Small number the input is stored as in replay files
+    pub fn code(self) -> u8 {
+        let index = |action| Action::ALL.iter().position(|&a| a == action).unwrap_or(0) as u8;
+        match self {
+            Input::ReleaseAll => 0,
+            Input::Press(action) => 1 + 2 * index(action),
+            Input::Release(action) => 2 + 2 * index(action),
+        }
+    }
+
+    pub fn from_code(code: u8) -> Option<Input> {
+        if code == 0 {
+            return Some(Input::ReleaseAll);
+        }
+        let action = *Action::ALL.get(((code - 1) / 2) as usize)?;
+        if code % 2 == 1 {
+            Some(Input::Press(action))
+        } else {
+            Some(Input::Release(action))
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Simulation {
+    game: GameState,
+    handling: Handling,
+    frame: u32,
+}
+
+impl Simulation {
+    pub fn new(mode: GameMode, settings: &Settings, seed: u64) -> Self {
+        Self {
+            game: GameState::new(mode, settings, seed),
+            handling: Handling::new(settings.das(), settings.arr()),
+            frame: 0,
+        }
+    }
+
+    /// Start from a set up position instead of an empty well
+    pub fn with_setup(mode: GameMode, settings: &Settings, seed: u64, setup: &Setup) -> Self {
+        Self {
+            game: GameState::with_setup(mode, settings, seed, setup),
+            handling: Handling::new(settings.das(), settings.arr()),
+            frame: 0,
+        }
+    }
+
+    pub fn game(&self) -> &GameState {
+        &self.game
+    }
+
+    /// Number of frames simulated so far
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    pub fn set_settings(&mut self, settings: &Settings) {
+        self.game.set_settings(settings);
+        self.handling.set_timing(settings.das(), settings.arr());
+    }
+
+    /// Apply an input before the next frame is simulated
+    pub fn apply(&mut self, input: Input) {
+        match input {
+            Input::Press(action) => {
+                self.handling.press(action);
+                self.game.input(action);
+            }
+            Input::Release(action) => self.handling.release(action),
+            Input::ReleaseAll => self.handling.release_all(),
+        }
+    }
+
+    /// Queue garbage from the opponent, see `GameState::receive_garbage`
+    pub fn receive_garbage(&mut self, rows: u32) {
+        self.game.receive_garbage(rows);
+    }
+
+    /// Garbage for the opponent, see `GameState::take_attack`
+    pub fn take_attack(&mut self) -> u32 {
+        self.game.take_attack()
+    }
+
+    /// Simulate one frame
+    pub fn step(&mut self) {
+        if let Some((direction, repeats)) = self.handling.update(TICK) {
+            for _ in 0..repeats {
+                self.game.perform(direction);
+            }
+        }
+        if self.handling.soft_drop_held() {
+            self.game.perform(Action::SoftDrop);
+        }
+        self.game.update(TICK);
+        self.frame += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_input_codes() {
+        for &action in Action::ALL.iter() {
+            for &input in [Input::Press(action), Input::Release(action)].iter() {
+                assert_eq!(Input::from_code(input.code()), Some(input));
+            }
+        }
+        assert_eq!(Input::from_code(0), Some(Input::ReleaseAll));
+        assert_eq!(Input::from_code(255), None);
+    }
+
+    #[test]
+    fn test_deterministic() {
+        let settings = Settings::default();
+        let inputs = [
+            (10, Input::Press(Action::MoveLeft)),
+            (40, Input::Release(Action::MoveLeft)),
+            (41, Input::Press(Action::HardDrop)),
+            (90, Input::Press(Action::RotateCw)),
+            (91, Input::Press(Action::HardDrop)),
+        ];
+        let play = || {
+            let mut sim = Simulation::new(GameMode::Marathon, &settings, 1234);
+            let mut inputs = inputs.iter().peekable();
+            while sim.frame() < 600 {
+                while let Some(&&(frame, input)) = inputs.peek() {
+                    if frame != sim.frame() {
+                        break;
+                    }
+                    sim.apply(input);
+                    inputs.next();
+                }
+                sim.step();
+            }
+            sim.game().stats().clone()
+        };
+        let stats = play();
+        assert!(stats.pieces >= 2);
+        assert_eq!(stats, play());
+    }
+}
@@ -0,0 +1,156 @@
+//! Statistics of a game, built up from the events the engine emits so the
+//! HUD, the game over screen and anything exporting them agree.
+use std::time::Duration;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::tetromino::Shape;
+
+/// Something that happened in the game
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameEvent {
+    /// Time passed while the game was running
+    Tick(Duration),
+    /// The player pressed a key or button bound to a move. Auto repeat
+    /// from a held key is not counted.
+    KeyPressed,
+    /// A piece was locked into the stack
+    Locked {
+        shape: Shape,
+        /// Rows cleared by the piece
+        lines: u32,
+        /// A T piece rotated into a spot with at least three of the four
+        /// corners around its center filled
+        tspin: bool,
+        /// The stack is empty after clearing
+        perfect_clear: bool,
+    },
+    /// A piece was judged for finesse before it locked
+    Finesse {
+        /// Key presses more than the fewest needed
+        faults: u32,
+    },
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Stats {
+    pub elapsed: Duration,
+    pub keys: u32,
+    pub pieces: u32,
+    pub lines: u32,
+    /// Number of clears by rows cleared at once, singles first
+    pub clears: [u32; 4],
+    pub tspins: u32,
+    pub perfect_clears: u32,
+    /// Pieces locked of every shape, in the order of `Shape::ALL`
+    pub shapes: [u32; 7],
+    /// Pieces in a row that cleared lines, up to the last one locked
+    #[serde(default)]
+    pub combo: u32,
+    /// The last clear was a Tetris or a T-spin, so another one would be
+    /// back to back
+    #[serde(default)]
+    pub back_to_back: bool,
+    /// Key presses more than needed to place the pieces
+    #[serde(default)]
+    pub finesse_faults: u32,
+}
+
+impl Stats {
+    pub fn record(&mut self, event: GameEvent) {
+        match event {
+            GameEvent::Tick(dt) => self.elapsed += dt,
+            GameEvent::KeyPressed => self.keys += 1,
+            GameEvent::Locked {
+                shape,
+                lines,
+                tspin,
+                perfect_clear,
+            } => {
+                self.pieces += 1;
+                self.shapes[shape.index()] += 1;
+                self.lines += lines;
+                if lines > 0 {
+                    self.clears[(lines.min(4) - 1) as usize] += 1;
+                    self.combo += 1;
+                    self.back_to_back = lines >= 4 || tspin;
+                } else {
+                    self.combo = 0;
+                }
+                if tspin {
+                    self.tspins += 1;
+                }
+                if perfect_clear {
+                    self.perfect_clears += 1;
+                }
+            }
+            GameEvent::Finesse { faults } => self.finesse_faults += faults,
+        }
+    }
+
+    /// The level goes up every ten lines
+    pub fn level(&self) -> u32 {
+        self.lines / 10 + 1
+    }
+
+    /// Pieces locked per second of play
+    pub fn pieces_per_second(&self) -> f32 {
+        let secs = self.elapsed.as_millis() as f32 / 1000.0;
+        if secs > 0.0 {
+            self.pieces as f32 / secs
+        } else {
+            0.0
+        }
+    }
+
+    /// Key presses needed for every locked piece on average
+    pub fn keys_per_piece(&self) -> f32 {
+        if self.pieces > 0 {
+            self.keys as f32 / self.pieces as f32
+        } else {
+            0.0
+        }
+    }
+
+    pub fn tetrises(&self) -> u32 {
+        self.clears[3]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let mut stats = Stats::default();
+        let locked = |shape, lines| GameEvent::Locked {
+            shape,
+            lines,
+            tspin: shape == Shape::T,
+            perfect_clear: false,
+        };
+        stats.record(GameEvent::Tick(Duration::from_secs(2)));
+        for _ in 0..6 {
+            stats.record(GameEvent::KeyPressed);
+        }
+        stats.record(GameEvent::Finesse { faults: 2 });
+        stats.record(locked(Shape::I, 4));
+        stats.record(GameEvent::Finesse { faults: 0 });
+        stats.record(locked(Shape::T, 2));
+        assert_eq!((stats.combo, stats.back_to_back), (2, true));
+        stats.record(locked(Shape::O, 0));
+        assert_eq!((stats.combo, stats.back_to_back), (0, true));
+        stats.record(locked(Shape::L, 1));
+        assert_eq!((stats.combo, stats.back_to_back), (1, false));
+
+        assert_eq!(stats.pieces, 4);
+        assert_eq!(stats.lines, 7);
+        assert_eq!(stats.clears, [1, 1, 0, 1]);
+        assert_eq!(stats.tspins, 1);
+        assert_eq!(stats.finesse_faults, 2);
+        assert_eq!(stats.shapes[Shape::T.index()], 1);
+        assert_eq!(stats.pieces_per_second(), 2.0);
+        assert_eq!(stats.keys_per_piece(), 1.5);
+    }
+}
@@ -0,0 +1,127 @@
+//! A game put aside when the player quits, stored as `suspend.json` in the
+//! data directory until it is resumed.
+use std::path::PathBuf;
+use std::{fmt, fs, io};
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::config::data_dir;
+use crate::replay::{Replay, REPLAY_VERSION};
+use crate::simulation::Simulation;
+
+const FILE_NAME: &str = "suspend.json";
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Suspended {
+    /// Suspended games hold the same simulation as replays, so they can
+    /// only be resumed by builds with the same replay version
+    pub version: u32,
+    pub sim: Simulation,
+    /// Recording of the game so far, continued after resuming
+    pub replay: Replay,
+}
+
+/// Why a suspended game could not be loaded or saved
+#[derive(Debug)]
+pub enum SuspendError {
+    NoDataDir,
+    Io(io::Error),
+    Json(serde_json::Error),
+    UnsupportedVersion(u32),
+}
+
+impl fmt::Display for SuspendError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SuspendError::NoDataDir => write!(f, "no data directory found"),
+            SuspendError::Io(err) => write!(f, "{}", err),
+            SuspendError::Json(err) => write!(f, "invalid suspended game: {}", err),
+            SuspendError::UnsupportedVersion(version) => write!(
+                f,
+                "suspended game version {} cannot be resumed by this game (version {})",
+                version, REPLAY_VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SuspendError {}
+
+impl Suspended {
+    pub fn new(sim: Simulation, replay: Replay) -> Self {
+        Self {
+            version: REPLAY_VERSION,
+            sim,
+            replay,
+        }
+    }
+
+    pub fn path() -> Option<PathBuf> {
+        data_dir().map(|dir| dir.join(FILE_NAME))
+    }
+
+    /// Whether there is a suspended game to resume
+    pub fn exists() -> bool {
+        match Self::path() {
+            Some(path) => path.is_file(),
+            None => false,
+        }
+    }
+
+    pub fn save(&self) -> Result<(), SuspendError> {
+        let path = Self::path().ok_or(SuspendError::NoDataDir)?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(SuspendError::Io)?;
+        }
+        let text = serde_json::to_string(self).map_err(SuspendError::Json)?;
+        fs::write(path, text).map_err(SuspendError::Io)
+    }
+
+    /// Load the suspended game and remove the file, so a game can only be
+    /// resumed once. The file is removed even if it cannot be read.
+    pub fn take() -> Result<Self, SuspendError> {
+        let path = Self::path().ok_or(SuspendError::NoDataDir)?;
+        let text = fs::read_to_string(&path).map_err(SuspendError::Io);
+        if let Err(err) = fs::remove_file(&path) {
+            eprintln!("suspended game: cannot remove {}: {}", path.display(), err);
+        }
+        let suspended: Suspended = serde_json::from_str(&text?).map_err(SuspendError::Json)?;
+        if suspended.version != REPLAY_VERSION {
+            return Err(SuspendError::UnsupportedVersion(suspended.version));
+        }
+        Ok(suspended)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action::Action;
+    use crate::game_state::GameMode;
+    use crate::settings::Settings;
+    use crate::simulation::Input;
+
+    #[test]
+    fn test_resume_plays_on_the_same() {
+        let settings = Settings::default();
+        let mut sim = Simulation::new(GameMode::Marathon, &settings, 5);
+        let mut replay = Replay::new(GameMode::Marathon, 5, &settings);
+        for _ in 0..15 {
+            replay.record(&mut sim, Input::Press(Action::HardDrop));
+            for _ in 0..20 {
+                sim.step();
+            }
+        }
+
+        let text = serde_json::to_string(&Suspended::new(sim.clone(), replay)).unwrap();
+        let mut resumed: Suspended = serde_json::from_str(&text).unwrap();
+        for _ in 0..300 {
+            sim.apply(Input::Press(Action::RotateCw));
+            sim.step();
+            resumed.sim.apply(Input::Press(Action::RotateCw));
+            resumed.sim.step();
+        }
+        assert_eq!(resumed.sim.game().stats(), sim.game().stats());
+        assert_eq!(resumed.sim.game().points(), sim.game().points());
+    }
+}
@@ -0,0 +1,625 @@
+//! The Tetris Bot Protocol (TBP), which lets bots written in any language
+//! play the game. A bot is a program that reads JSON messages from its
+//! standard input, one per line, and writes its answers to its standard
+//! output the same way:
+//!
+//! 1. the bot introduces itself with `info`
+//! 2. the game sends `rules`, the bot answers `ready`, or `error` if it
+//!    cannot play by them
+//! 3. the game sends `start` with the position, then `suggest` whenever a
+//!    piece is to be placed, and the bot answers with a `suggestion` of
+//!    moves, the best first
+//! 4. the game tells the bot which move it made with `play`, and about
+//!    every piece that shows up in the queue with `new_piece`
+//! 5. `stop` ends the game, `quit` the bot
+//!
+//! Boards are 40 rows of 10 cells with the bottom row first. A piece is
+//! placed at the position of its rotation center, with `y` counted from the
+//! bottom up, as in fumen.
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::time::Duration;
+use std::{fmt, thread};
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::action::Action;
+use crate::bot::{after, Bot};
+use crate::fumen::{Piece, Rotation};
+use crate::game_state::{GameState, Ruleset};
+use crate::movegen::{self, Placement};
+use crate::tetromino::{Segment, Shape, Tetromino, GRID_SIZE};
+
+/// Size of the boards in messages
+pub const BOARD_WIDTH: usize = 10;
+pub const BOARD_HEIGHT: usize = 40;
+
+/// Letter of garbage cells in boards
+pub const GARBAGE: char = 'G';
+
+/// How long a bot is given to quit before it is stopped
+const QUIT_TIMEOUT: Duration = Duration::from_millis(200);
+const QUIT_POLL: Duration = Duration::from_millis(10);
+
+/// A cell of a board, the letter of the piece it came from, `G` for
+/// garbage or nothing if it is empty
+pub type Cell = Option<char>;
+
+/// A board as sent to bots, the bottom row first
+pub type Board = Vec<Vec<Cell>>;
+
+/// Messages the game sends to a bot
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FrontendMessage {
+    Rules {
+        randomizer: Randomizer,
+    },
+    Start {
+        hold: Option<Shape>,
+        queue: Vec<Shape>,
+        combo: u32,
+        back_to_back: bool,
+        board: Board,
+    },
+    Stop,
+    Suggest,
+    Play {
+        #[serde(rename = "move")]
+        mv: Move,
+    },
+    NewPiece {
+        piece: Shape,
+    },
+    Quit,
+}
+
+/// Messages a bot sends to the game
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BotMessage {
+    Info {
+        name: String,
+        version: String,
+        author: String,
+        features: Vec<String>,
+    },
+    Ready,
+    Error {
+        reason: String,
+    },
+    Suggestion {
+        moves: Vec<Move>,
+    },
+}
+
+/// How pieces are dealt, so bots can guess what comes after the queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Randomizer {
+    Unknown,
+    SevenBag,
+}
+
+impl From<Ruleset> for Randomizer {
+    fn from(ruleset: Ruleset) -> Self {
+        match ruleset {
+            Ruleset::Classic => Randomizer::Unknown,
+            Ruleset::Guideline => Randomizer::SevenBag,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Move {
+    pub location: Location,
+    pub spin: Spin,
+}
+
+/// Where a piece ends up
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Location {
+    #[serde(rename = "type")]
+    pub piece: Shape,
+    pub orientation: Orientation,
+    pub x: i32,
+    pub y: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Orientation {
+    North,
+    East,
+    South,
+    West,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Spin {
+    None,
+    Mini,
+    Full,
+}
+
+impl Orientation {
+    pub const ALL: [Orientation; 4] = [
+        Orientation::North,
+        Orientation::East,
+        Orientation::South,
+        Orientation::West,
+    ];
+
+    fn rotation(self) -> Rotation {
+        match self {
+            Orientation::North => Rotation::Spawn,
+            Orientation::East => Rotation::Right,
+            Orientation::South => Rotation::Reverse,
+            Orientation::West => Rotation::Left,
+        }
+    }
+}
+
+impl Location {
+    /// Cells covered by the piece, with `y` counted from the bottom up
+    pub fn blocks(&self) -> [(i32, i32); 4] {
+        Piece {
+            shape: self.piece,
+            rotation: self.orientation.rotation(),
+            x: self.x,
+            y: self.y,
+        }
+        .blocks()
+    }
+
+    /// Cells covered by the piece in the coordinates of the well, sorted
+    pub fn cells(&self) -> Vec<(i16, i16)> {
+        let mut cells: Vec<(i16, i16)> = self
+            .blocks()
+            .iter()
+            .map(|&(x, y)| (x as i16, GRID_SIZE.1 - 1 - y as i16))
+            .collect();
+        cells.sort();
+        cells
+    }
+
+    /// The location of a piece in the well. Pieces that look the same in
+    /// several orientations get the first of them.
+    pub fn of(piece: &Tetromino) -> Option<Location> {
+        let cells = sorted_cells(piece);
+        let corner = |blocks: &[(i32, i32)]| {
+            let x = blocks.iter().map(|&(x, _)| x).min().unwrap_or(0);
+            let y = blocks.iter().map(|&(_, y)| y).min().unwrap_or(0);
+            (x, y)
+        };
+        let blocks: Vec<(i32, i32)> = cells
+            .iter()
+            .map(|&(x, y)| (i32::from(x), i32::from(GRID_SIZE.1 - 1 - y)))
+            .collect();
+        let (x, y) = corner(&blocks);
+        Orientation::ALL
+            .iter()
+            .map(|&orientation| {
+                let origin = Location {
+                    piece: piece.shape(),
+                    orientation,
+                    x: 0,
+                    y: 0,
+                };
+                let (dx, dy) = corner(&origin.blocks());
+                Location {
+                    x: x - dx,
+                    y: y - dy,
+                    ..origin
+                }
+            })
+            .find(|location| location.cells() == cells)
+    }
+}
+
+fn sorted_cells(piece: &Tetromino) -> Vec<(i16, i16)> {
+    let mut cells: Vec<(i16, i16)> = piece.body.iter().map(|seg| (seg.x, seg.y)).collect();
+    cells.sort();
+    cells
+}
+
+/// The stack of the well as a board
+pub fn board(stack: &[Segment]) -> Board {
+    let mut board = vec![vec![None; BOARD_WIDTH]; BOARD_HEIGHT];
+    for seg in stack {
+        let y = GRID_SIZE.1 - 1 - seg.y;
+        if seg.x < 0 || seg.x as usize >= BOARD_WIDTH || y < 0 || y as usize >= BOARD_HEIGHT {
+            continue;
+        }
+        let cell = Shape::from_color(seg.color).map_or(GARBAGE, Shape::letter);
+        board[y as usize][seg.x as usize] = Some(cell);
+    }
+    board
+}
+
+/// Why a bot could not be used
+#[derive(Debug)]
+pub enum TbpError {
+    NoCommand,
+    Launch(io::Error),
+    Io(io::Error),
+    Json(serde_json::Error),
+    /// The bot closed its output, usually because it exited
+    Closed,
+    Unexpected(BotMessage),
+    /// The bot does not play by the rules of the game
+    Rejected(String),
+    /// The current piece cannot be placed anywhere
+    NoMove,
+}
+
+impl fmt::Display for TbpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TbpError::NoCommand => write!(f, "no bot command given"),
+            TbpError::Launch(err) => write!(f, "cannot start bot: {}", err),
+            TbpError::Io(err) => write!(f, "{}", err),
+            TbpError::Json(err) => write!(f, "invalid message: {}", err),
+            TbpError::Closed => write!(f, "the bot exited"),
+            TbpError::Unexpected(message) => write!(f, "unexpected message {:?}", message),
+            TbpError::Rejected(reason) => write!(f, "the bot cannot play this game: {}", reason),
+            TbpError::NoMove => write!(f, "the piece cannot be placed"),
+        }
+    }
+}
+
+impl std::error::Error for TbpError {}
+
+/// What a bot was last told about the game
+#[derive(Debug, PartialEq)]
+struct Known {
+    board: Board,
+    hold: Option<Shape>,
+    queue: Vec<Shape>,
+}
+
+impl Known {
+    /// The game as the bot should see it: the stack, the held piece and the
+    /// current piece followed by the visible queue
+    fn of(game: &GameState) -> Self {
+        Self {
+            board: board(game.base()),
+            hold: game.hold(),
+            queue: Some(game.current().shape())
+                .into_iter()
+                .chain(game.next())
+                .collect(),
+        }
+    }
+
+    /// Follow a move the way the bot does: a piece other than the first in
+    /// the queue was played by holding first
+    fn play(&mut self, piece: Shape, result: &GameState) {
+        if !self.queue.is_empty() {
+            let first = self.queue.remove(0);
+            // with nothing held before, the piece played came from the queue
+            if first != piece && self.hold.replace(first).is_none() && !self.queue.is_empty() {
+                self.queue.remove(0);
+            }
+        }
+        self.board = board(result.base());
+    }
+}
+
+/// The placement that makes a move: the one covering the same cells, and
+/// of those the one that is a T-spin if the move is a spin
+fn matching<'a>(placements: &'a [Placement], mv: &Move) -> Option<&'a Placement> {
+    let cells = mv.location.cells();
+    let spin = mv.spin != Spin::None;
+    let mut same_cells = placements
+        .iter()
+        .filter(|placement| sorted_cells(&placement.piece) == cells);
+    same_cells
+        .clone()
+        .find(|placement| placement.tspin == spin)
+        .or_else(|| same_cells.next())
+}
+
+/// A bot running in another process, playing through `Autoplay` like the
+/// built-in ones
+pub struct TbpBot {
+    process: Child,
+    input: ChildStdin,
+    output: BufReader<ChildStdout>,
+    pub name: String,
+    pub version: String,
+    pub author: String,
+    known: Option<Known>,
+    /// A move that needs the piece in hold, held first
+    pending: Option<Move>,
+}
+
+impl TbpBot {
+    /// Start a bot from a command line. Arguments are separated by
+    /// whitespace, there is no quoting.
+    pub fn launch(command_line: &str, ruleset: Ruleset) -> Result<Self, TbpError> {
+        let mut words = command_line.split_whitespace();
+        let mut command = Command::new(words.next().ok_or(TbpError::NoCommand)?);
+        command.args(words);
+        Self::spawn(command, ruleset)
+    }
+
+    /// Start a bot and agree on the rules with it
+    pub fn spawn(mut command: Command, ruleset: Ruleset) -> Result<Self, TbpError> {
+        let mut process = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(TbpError::Launch)?;
+        let (input, output) = match (process.stdin.take(), process.stdout.take()) {
+            (Some(input), Some(output)) => (input, BufReader::new(output)),
+            _ => return Err(TbpError::Closed),
+        };
+        let mut bot = Self {
+            process,
+            input,
+            output,
+            name: String::new(),
+            version: String::new(),
+            author: String::new(),
+            known: None,
+            pending: None,
+        };
+        match bot.receive()? {
+            BotMessage::Info {
+                name,
+                version,
+                author,
+                ..
+            } => {
+                bot.name = name;
+                bot.version = version;
+                bot.author = author;
+            }
+            other => return Err(TbpError::Unexpected(other)),
+        }
+        bot.send(&FrontendMessage::Rules {
+            randomizer: ruleset.into(),
+        })?;
+        match bot.receive()? {
+            BotMessage::Ready => Ok(bot),
+            BotMessage::Error { reason } => Err(TbpError::Rejected(reason)),
+            other => Err(TbpError::Unexpected(other)),
+        }
+    }
+
+    fn send(&mut self, message: &FrontendMessage) -> Result<(), TbpError> {
+        let line = serde_json::to_string(message).map_err(TbpError::Json)?;
+        writeln!(self.input, "{}", line)
+            .and_then(|()| self.input.flush())
+            .map_err(TbpError::Io)
+    }
+
+    fn receive(&mut self) -> Result<BotMessage, TbpError> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.output.read_line(&mut line).map_err(TbpError::Io)? == 0 {
+                return Err(TbpError::Closed);
+            }
+            if !line.trim().is_empty() {
+                return serde_json::from_str(&line).map_err(TbpError::Json);
+            }
+        }
+    }
+
+    /// Bring the bot up to date. Pieces that showed up in the queue are
+    /// sent one by one, anything else it could not know about, such as a
+    /// restarted game, starts the game over for it.
+    fn sync(&mut self, game: &GameState) -> Result<(), TbpError> {
+        let current = Known::of(game);
+        let new_pieces = match self.known {
+            Some(ref known)
+                if known.board == current.board
+                    && known.hold == current.hold
+                    && current.queue.starts_with(&known.queue) =>
+            {
+                current.queue[known.queue.len()..].to_vec()
+            }
+            _ => {
+                if self.known.is_some() {
+                    self.send(&FrontendMessage::Stop)?;
+                }
+                let stats = game.stats();
+                self.send(&FrontendMessage::Start {
+                    hold: current.hold,
+                    queue: current.queue.clone(),
+                    combo: stats.combo,
+                    back_to_back: stats.back_to_back,
+                    board: current.board.clone(),
+                })?;
+                Vec::new()
+            }
+        };
+        for &piece in new_pieces.iter() {
+            self.send(&FrontendMessage::NewPiece { piece })?;
+        }
+        self.known = Some(current);
+        Ok(())
+    }
+
+    fn try_plan(&mut self, game: &GameState) -> Result<Vec<Action>, TbpError> {
+        let moves = match self.pending.take() {
+            Some(mv) => vec![mv],
+            None => {
+                self.sync(game)?;
+                self.send(&FrontendMessage::Suggest)?;
+                match self.receive()? {
+                    BotMessage::Suggestion { moves } => moves,
+                    other => return Err(TbpError::Unexpected(other)),
+                }
+            }
+        };
+        let current = game.current().shape();
+        let held = game.hold().or_else(|| game.next().next());
+        let placements = movegen::placements(game);
+        for mv in moves {
+            if mv.location.piece != current {
+                if game.can_hold() && held == Some(mv.location.piece) {
+                    self.pending = Some(mv);
+                    return Ok(vec![Action::Hold]);
+                }
+                continue;
+            }
+            if let Some(placement) = matching(&placements, &mv) {
+                self.play(mv, &after(game, placement))?;
+                return Ok(placement.actions());
+            }
+        }
+        // none of the moves can be made, so make another one and tell the
+        // bot
+        let placement = placements.into_iter().next().ok_or(TbpError::NoMove)?;
+        let location = Location::of(&placement.piece).ok_or(TbpError::NoMove)?;
+        let spin = if placement.tspin {
+            Spin::Full
+        } else {
+            Spin::None
+        };
+        self.play(Move { location, spin }, &after(game, &placement))?;
+        Ok(placement.actions())
+    }
+
+    fn play(&mut self, mv: Move, result: &GameState) -> Result<(), TbpError> {
+        self.send(&FrontendMessage::Play { mv })?;
+        if let Some(ref mut known) = self.known {
+            known.play(mv.location.piece, result);
+        }
+        Ok(())
+    }
+}
+
+impl Bot for TbpBot {
+    /// Errors are reported and the piece is hard dropped, the game goes on
+    fn plan(&mut self, game: &GameState) -> Vec<Action> {
+        self.try_plan(game).unwrap_or_else(|err| {
+            eprintln!("bot {}: {}", self.name, err);
+            self.known = None;
+            self.pending = None;
+            vec![Action::HardDrop]
+        })
+    }
+}
+
+impl Drop for TbpBot {
+    /// Ask the bot to quit, and stop it if it does not
+    fn drop(&mut self) {
+        if self.send(&FrontendMessage::Quit).is_ok() {
+            let mut waited = Duration::from_secs(0);
+            while waited < QUIT_TIMEOUT {
+                if let Ok(Some(_)) = self.process.try_wait() {
+                    return;
+                }
+                thread::sleep(QUIT_POLL);
+                waited += QUIT_POLL;
+            }
+        }
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::ready;
+    use crate::diagram;
+    use crate::game_state::{GameMode, Setup};
+    use crate::settings::Settings;
+    use crate::simulation::TICK;
+
+    #[test]
+    fn test_messages() {
+        let play = FrontendMessage::Play {
+            mv: Move {
+                location: Location {
+                    piece: Shape::T,
+                    orientation: Orientation::North,
+                    x: 4,
+                    y: 0,
+                },
+                spin: Spin::None,
+            },
+        };
+        let json = r#"{"type":"play","move":{"location":{"type":"T","orientation":"north","x":4,"y":0},"spin":"none"}}"#;
+        assert_eq!(serde_json::to_string(&play).unwrap(), json);
+        assert_eq!(
+            serde_json::to_string(&FrontendMessage::Suggest).unwrap(),
+            r#"{"type":"suggest"}"#
+        );
+        let ready: BotMessage = serde_json::from_str(r#"{"type":"ready"}"#).unwrap();
+        assert_eq!(ready, BotMessage::Ready);
+        // fields added by later versions of the protocol are ignored
+        let suggestion: BotMessage =
+            serde_json::from_str(r#"{"type":"suggestion","moves":[],"move_info":{}}"#).unwrap();
+        assert_eq!(suggestion, BotMessage::Suggestion { moves: Vec::new() });
+    }
+
+    #[test]
+    fn test_locations() {
+        // a T lying flat on the floor in the middle
+        let location = Location {
+            piece: Shape::T,
+            orientation: Orientation::North,
+            x: 4,
+            y: 0,
+        };
+        assert_eq!(location.cells(), vec![(3, 19), (4, 18), (4, 19), (5, 19)]);
+        for &shape in Shape::ALL.iter() {
+            for &orientation in Orientation::ALL.iter() {
+                let location = Location {
+                    piece: shape,
+                    orientation,
+                    x: 4,
+                    y: 5,
+                };
+                let mut piece = Tetromino::from(shape);
+                piece.body = location
+                    .cells()
+                    .iter()
+                    .map(|&pos| Segment::new(pos, (&shape).into()))
+                    .collect();
+                let found = Location::of(&piece).unwrap();
+                assert_eq!(found.cells(), location.cells());
+            }
+        }
+    }
+
+    #[test]
+    fn test_spin_moves() {
+        // a T-spin double, the piece has to be turned into the slot
+        let setup = Setup {
+            board: diagram::parse("...G......\nGGG...GGGG\nGGGG.GGGGG")
+                .unwrap()
+                .board,
+            hold: None,
+            queue: vec![Shape::T],
+        };
+        let mut game = GameState::with_setup(GameMode::Sandbox, &Settings::default(), 1, &setup);
+        while !ready(&game) {
+            game.update(TICK);
+        }
+        let mv = Move {
+            location: Location {
+                piece: Shape::T,
+                orientation: Orientation::South,
+                x: 4,
+                y: 1,
+            },
+            spin: Spin::Full,
+        };
+        let placements = movegen::placements(&game);
+        let placement = matching(&placements, &mv).unwrap();
+        assert!(placement.tspin);
+        assert!(placement.path.iter().any(|step| step.rotates()));
+        let result = after(&game, placement);
+        assert_eq!(result.lines(), 2);
+        assert_eq!(result.stats().tspins, 1);
+    }
+}
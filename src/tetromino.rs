@@ -1,15 +1,18 @@
 mod utils;
 
 use self::utils::body_generators;
-use ggez::{graphics, Context, GameResult};
+use crate::bitboard::{Board, Cells};
+use ggez::graphics;
+use serde_derive::{Deserialize, Serialize};
 
-use rand::Rng;
-
-pub(crate) const GRID_SIZE: (i16, i16) = (10, 20);
-pub(crate) const GRID_CELL_SIZE: (i16, i16) = (26, 26);
+pub const GRID_SIZE: (i16, i16) = (10, 20);
+pub const GRID_CELL_SIZE: (i16, i16) = (26, 26);
 
 type ColorTuple = (u8, u8, u8, u8);
 
+/// Color of garbage cells, which do not come from any piece
+pub const GARBAGE_COLOR: ColorTuple = (128, 128, 128, 255);
+
 /// Trait implementation for turning a Segment into graphics Rectangle object
 impl From<&Segment> for graphics::Rect {
     fn from(seg: &Segment) -> Self {
@@ -24,15 +27,17 @@ impl From<&Segment> for graphics::Rect {
 
 /// Represents motion of a piece
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub(crate) enum Motion {
+pub enum Motion {
     Left,
     Right,
-    RotateLeft,
+    RotateCw,
+    RotateCcw,
+    Rotate180,
 }
 
 /// Represents piece's shape, 7 classic tetromino shapes are used
-#[derive(Debug, Copy, Clone)]
-pub(crate) enum Shape {
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Shape {
     L,
     O,
     S,
@@ -43,11 +48,57 @@ pub(crate) enum Shape {
 }
 
 /// A segment is one out of four blocks making each piece
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub(crate) struct Segment {
-    pub(crate) x: i16,
-    pub(crate) y: i16,
-    pub(crate) color: ColorTuple,
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Segment {
+    pub x: i16,
+    pub y: i16,
+    pub color: ColorTuple,
+}
+
+impl Shape {
+    pub const ALL: [Shape; 7] = [
+        Shape::L,
+        Shape::O,
+        Shape::S,
+        Shape::Z,
+        Shape::I,
+        Shape::T,
+        Shape::J,
+    ];
+
+    /// Position of the shape in `Shape::ALL`
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// The letter the shape is named after
+    pub fn letter(self) -> char {
+        match self {
+            Shape::L => 'L',
+            Shape::O => 'O',
+            Shape::S => 'S',
+            Shape::Z => 'Z',
+            Shape::I => 'I',
+            Shape::T => 'T',
+            Shape::J => 'J',
+        }
+    }
+
+    /// The shape named by a letter, in either case
+    pub fn from_letter(letter: char) -> Option<Shape> {
+        Shape::ALL
+            .iter()
+            .cloned()
+            .find(|shape| shape.letter() == letter.to_ascii_uppercase())
+    }
+
+    /// The shape a cell of the stack came from, judging by its color
+    pub fn from_color(color: ColorTuple) -> Option<Shape> {
+        Shape::ALL
+            .iter()
+            .cloned()
+            .find(|shape| ColorTuple::from(shape) == color)
+    }
 }
 
 /// Default shape is I
@@ -59,22 +110,13 @@ impl Default for Shape {
 
 impl Segment {
     /// Create new segment from position coordinates and add color
-    pub(crate) fn new(pos: (i16, i16), color: ColorTuple) -> Self {
+    pub fn new(pos: (i16, i16), color: ColorTuple) -> Self {
         Self {
             x: pos.0,
             y: pos.1,
             color,
         }
     }
-
-    /// Add an extra segment as a ghost cell
-    pub(crate) fn add_ghost_layer(&self) -> Self {
-        Self {
-            x: self.x,
-            y: self.y - 1,
-            color: (1, 1, 0, 255),
-        }
-    }
 }
 
 impl From<i32> for Shape {
@@ -106,10 +148,10 @@ impl From<&Shape> for ColorTuple {
 }
 
 /// Represents a single piece that has a shape and body made out of segments
-#[derive(Clone, Debug)]
-pub(crate) struct Tetromino {
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Tetromino {
     shape: Shape,
-    pub(crate) body: Vec<Segment>,
+    pub body: Vec<Segment>,
 }
 
 impl From<Shape> for Tetromino {
@@ -123,91 +165,53 @@ impl From<Shape> for Tetromino {
 }
 
 impl Tetromino {
-    /// Create new piece with a random shape
-    pub(crate) fn new() -> Self {
-        let shape: Shape = Tetromino::generate_shape().unwrap();
-        Self::from(shape)
-    }
-
     /// Translate piece
-    pub(crate) fn translate(&mut self, x: i16, y: i16) {
+    pub fn translate(&mut self, x: i16, y: i16) {
         for seg in self.body.iter_mut() {
             seg.x += x;
             seg.y += y;
         }
     }
 
-    /// Move by a single step or rotate the piece
-    pub(crate) fn move_to(&mut self, dir: Motion, base: &Vec<Segment>) {
-        match dir {
-            Motion::Left => {
-                if self.body.iter().all(|elem| {
-                    elem.x > 0
-                        && elem.y > -1
-                        && !base
-                            .iter()
-                            .any(|b_elem| b_elem.x == (elem.x - 1) && b_elem.y == elem.y)
-                }) {
-                    self.body.iter_mut().for_each(|seg| seg.x -= 1);
-                }
-            }
-            Motion::Right => {
-                if self.body.iter().all(|elem| {
-                    elem.x < GRID_SIZE.0 - 1
-                        && elem.y > -1
-                        && !base
-                            .iter()
-                            .any(|b_elem| b_elem.x == (elem.x + 1) && b_elem.y == elem.y)
-                }) {
-                    self.body.iter_mut().for_each(|seg| seg.x += 1);
-                }
-            }
-            Motion::RotateLeft => {
-                self.rotate_left(base);
-            }
+    /// Move by a single step or rotate the piece, by the rules of `Board`
+    pub fn move_to(&mut self, dir: Motion, board: &Board) {
+        let cells = board.moved(self.shape, &self.cells(), dir);
+        self.set_cells(&cells);
+    }
+
+    /// The positions of the cells of the piece
+    pub fn cells(&self) -> Cells {
+        let mut cells = [(0, 0); 4];
+        for (cell, seg) in cells.iter_mut().zip(self.body.iter()) {
+            *cell = (seg.x, seg.y);
+        }
+        cells
+    }
+
+    /// Move the cells of the piece to the positions, keeping their colors
+    pub fn set_cells(&mut self, cells: &Cells) {
+        for (seg, &(x, y)) in self.body.iter_mut().zip(cells.iter()) {
+            seg.x = x;
+            seg.y = y;
         }
     }
 
     /// Implements the downward motion
-    pub(crate) fn update(&mut self) {
+    pub fn update(&mut self) {
         for seg in self.body.iter_mut() {
             seg.y += 1;
         }
     }
 
-    /// Draw the figure using simple rectangles
-    pub(crate) fn draw(&self, ctx: &mut Context) -> GameResult<()> {
-        for seg in self.body.iter() {
-            let rectangle = graphics::Mesh::new_rectangle(
-                ctx,
-                graphics::DrawMode::fill(),
-                seg.into(),
-                seg.color.into(),
-            )?;
-            graphics::draw(ctx, &rectangle, (ggez::mint::Point2 { x: 0.0, y: 0.0 },))?;
-        }
-        Ok(())
+    pub fn shape(&self) -> Shape {
+        self.shape
     }
 
     /// Clone body of a piece
-    pub(crate) fn clone_body(&self) -> Vec<Segment> {
+    pub fn clone_body(&self) -> Vec<Segment> {
         self.body.clone()
     }
 
-    fn generate_shape() -> Option<Shape> {
-        let mut rng = rand::thread_rng();
-        match rng.gen_range(0, 7) {
-            0 => Some(Shape::L),
-            1 => Some(Shape::O),
-            2 => Some(Shape::S),
-            3 => Some(Shape::Z),
-            4 => Some(Shape::I),
-            5 => Some(Shape::T),
-            6 => Some(Shape::J),
-            _ => unreachable!(),
-        }
-    }
-
     fn generate_body(shape: &Shape) -> Vec<Segment> {
         match *shape {
             Shape::L => body_generators::generate_l(),
@@ -224,50 +228,12 @@ impl Tetromino {
     }
 
     /// Return the central segment around which a piece rotates
-    fn get_central_segment(&self) -> Option<Segment> {
+    pub fn get_central_segment(&self) -> Option<Segment> {
         match self.shape {
             Shape::O => None,
             _ => Some(self.body[1]),
         }
     }
-
-    /// Implements kick from a wall if a piece segment left the well as a result
-    /// of rotation
-    fn kickback(&mut self) {
-        if self.body.iter().any(|seg| seg.x < 0) {
-            let kick = self.body.iter().min_by_key(|seg| seg.x).unwrap().x;
-            self.body
-                .iter_mut()
-                .for_each(|seg| *seg = Segment::new((seg.x - kick, seg.y), seg.color));
-        } else if self.body.iter().any(|seg| seg.x >= GRID_SIZE.0) {
-            let kick = self.body.iter().max_by_key(|seg| seg.x).unwrap().x;
-            self.body.iter_mut().for_each(|seg| {
-                *seg = Segment::new((seg.x - (kick - GRID_SIZE.0 + 1), seg.y), seg.color)
-            });
-        }
-    }
-
-    fn rotate_left(&mut self, base: &Vec<Segment>) {
-        if let Some(central_segment) = self.get_central_segment() {
-            // get the translation
-            let (x, y) = (-central_segment.x, -central_segment.y);
-            // translate, rotate and translate the body back
-            let new_body: Vec<Segment> = self
-                .body
-                .iter()
-                .map(|seg| Segment::new((-1 * (seg.y + y) - x, seg.x + x - y), seg.color))
-                .collect();
-            if !new_body.iter().any(|seg| {
-                seg.y >= GRID_SIZE.1
-                    || base
-                        .iter()
-                        .any(|b_elem| b_elem.x == seg.x && b_elem.y == seg.y)
-            }) {
-                self.body = new_body;
-                self.kickback();
-            }
-        }
-    }
 }
 
 #[cfg(test)]
@@ -278,18 +244,19 @@ mod tests {
 
     /// Test whether piece is translated appropriately
     fn test_translate() {
-        let mut piece = Tetromino::new();
-        let orig_piece = piece.clone();
-        piece.translate(2, -1);
-        for (seg_translated, seg_orig) in piece.body.iter().zip(orig_piece.body.iter()) {
-            assert_eq!(seg_translated.x, seg_orig.x + 2);
-            assert_eq!(seg_translated.y, seg_orig.y - 1);
+        for num in 0..7 {
+            let mut piece = Tetromino::from(Shape::from(num));
+            let orig_piece = piece.clone();
+            piece.translate(2, -1);
+            for (seg_translated, seg_orig) in piece.body.iter().zip(orig_piece.body.iter()) {
+                assert_eq!(seg_translated.x, seg_orig.x + 2);
+                assert_eq!(seg_translated.y, seg_orig.y - 1);
+            }
         }
     }
 
     #[test]
     fn test_kickback() {
-        let base = Vec::new();
         let shape = Shape::I;
         let mut piece = Tetromino::from(shape);
         let body: Vec<Segment> = vec![(0, -1), (0, 0), (0, 1), (0, 2)]
@@ -297,7 +264,25 @@ mod tests {
             .map(|v| Segment::new(v, (0, 0, 0, 0)))
             .collect();
         piece.body = body;
-        piece.rotate_left(&base);
+        piece.move_to(Motion::RotateCw, &Board::default());
+        assert!(piece.body.iter().all(|seg| seg.x >= 0 && seg.y == 0));
     }
 
+    #[test]
+    fn test_l_and_j() {
+        // upright with the foot on the right for L and on the left for J
+        let l = Tetromino::from(Shape::L);
+        assert_eq!(l.cells(), [(5, -1), (5, 0), (5, 1), (6, 1)]);
+        let j = Tetromino::from(Shape::J);
+        assert_eq!(j.cells(), [(5, -1), (5, 0), (5, 1), (4, 1)]);
+
+        // turned on their side they match the usual flat pieces, the L
+        // with a cell below its left end and the J above it
+        let mut l = l;
+        l.move_to(Motion::RotateCw, &Board::default());
+        assert_eq!(l.cells(), [(6, 0), (5, 0), (4, 0), (4, 1)]);
+        let mut j = j;
+        j.move_to(Motion::RotateCw, &Board::default());
+        assert_eq!(j.cells(), [(6, 0), (5, 0), (4, 0), (4, -1)]);
+    }
 }
@@ -0,0 +1,382 @@
+//! Themes change how the window looks: the blocks, the background, the
+//! grid and the font of the side panel. A theme is a directory with a
+//! `theme.toml` manifest and the files it names. Themes are looked up by
+//! the name of their directory, first in `themes` in the config directory
+//! and then among the themes that come with the game. Every key of the
+//! manifest can be left out, an empty manifest is the flat look:
+//!
+//! ```toml
+//! background = [16, 16, 24, 255]
+//! background_image = "background.png"
+//! well = [24, 24, 32, 255]
+//! font = "font.ttf"
+//!
+//! [grid]
+//! style = "dots"
+//! color = [90, 90, 110, 200]
+//! width = 2.0
+//!
+//! [blocks]
+//! i = "i.png"
+//! ghost = "ghost.png"
+//! garbage = "garbage.png"
+//! ```
+//!
+//! Block textures are stretched over a cell, blocks without one are drawn
+//! as cells of their color. The ghost texture is tinted with the color of
+//! the falling piece.
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::{env, fmt, fs, io};
+
+use serde_derive::Deserialize;
+
+use crate::config::config_dir;
+use crate::tetromino::{Segment, Shape};
+
+const MANIFEST: &str = "theme.toml";
+
+/// The theme of new players, the look of the game before themes
+pub const DEFAULT_THEME: &str = "flat";
+
+/// Textures larger than this on either side are refused
+const MAX_TEXTURE_SIZE: u32 = 4096;
+
+type Color = (u8, u8, u8, u8);
+
+/// How the lines between the cells of the well are drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GridStyle {
+    Lines,
+    /// A dot where the lines would cross
+    Dots,
+    None,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Grid {
+    pub style: GridStyle,
+    pub color: Color,
+    /// Thickness of the lines, or size of the dots
+    pub width: f32,
+}
+
+impl Default for Grid {
+    fn default() -> Self {
+        Self {
+            style: GridStyle::Lines,
+            color: (77, 77, 77, 178),
+            width: 1.0,
+        }
+    }
+}
+
+/// Texture files of the blocks, relative to the theme directory
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Blocks {
+    pub i: Option<String>,
+    pub o: Option<String>,
+    pub t: Option<String>,
+    pub s: Option<String>,
+    pub z: Option<String>,
+    pub j: Option<String>,
+    pub l: Option<String>,
+    pub ghost: Option<String>,
+    pub garbage: Option<String>,
+}
+
+impl Blocks {
+    pub fn file(&self, block: Block) -> Option<&str> {
+        let file = match block {
+            Block::Piece(Shape::I) => &self.i,
+            Block::Piece(Shape::O) => &self.o,
+            Block::Piece(Shape::T) => &self.t,
+            Block::Piece(Shape::S) => &self.s,
+            Block::Piece(Shape::Z) => &self.z,
+            Block::Piece(Shape::J) => &self.j,
+            Block::Piece(Shape::L) => &self.l,
+            Block::Ghost => &self.ghost,
+            Block::Garbage => &self.garbage,
+        };
+        file.as_ref().map(String::as_str)
+    }
+}
+
+/// The contents of `theme.toml`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Manifest {
+    /// Color behind the well and the side panel
+    pub background: Color,
+    /// Stretched over the background color
+    pub background_image: Option<String>,
+    /// Color of the empty well
+    pub well: Color,
+    /// Font of the side panel, a TrueType file
+    pub font: Option<String>,
+    pub grid: Grid,
+    pub blocks: Blocks,
+}
+
+impl Default for Manifest {
+    fn default() -> Self {
+        Self {
+            background: (0, 0, 0, 255),
+            background_image: None,
+            well: (32, 32, 32, 255),
+            font: None,
+            grid: Grid::default(),
+            blocks: Blocks::default(),
+        }
+    }
+}
+
+/// What a cell is drawn as
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Block {
+    Piece(Shape),
+    /// Where the falling piece will land
+    Ghost,
+    Garbage,
+}
+
+impl Block {
+    pub const ALL: [Block; 9] = [
+        Block::Piece(Shape::L),
+        Block::Piece(Shape::O),
+        Block::Piece(Shape::S),
+        Block::Piece(Shape::Z),
+        Block::Piece(Shape::I),
+        Block::Piece(Shape::T),
+        Block::Piece(Shape::J),
+        Block::Ghost,
+        Block::Garbage,
+    ];
+
+    /// The block a cell of the stack or of a piece is, judging by its color
+    pub fn of(seg: &Segment) -> Self {
+        Shape::from_color(seg.color).map_or(Block::Garbage, Block::Piece)
+    }
+}
+
+/// An image decoded to 8 bit RGBA pixels, row by row from the top
+#[derive(Debug, Clone, PartialEq)]
+pub struct Texture {
+    pub width: u16,
+    pub height: u16,
+    pub rgba: Vec<u8>,
+}
+
+/// Why a theme could not be loaded
+#[derive(Debug)]
+pub enum ThemeError {
+    NotFound(String),
+    Io(PathBuf, io::Error),
+    Parse(toml::de::Error),
+    Image(PathBuf, image::ImageError),
+    TooLarge(PathBuf),
+}
+
+impl fmt::Display for ThemeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ThemeError::NotFound(name) => write!(f, "no theme called {}", name),
+            ThemeError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            ThemeError::Parse(err) => write!(f, "invalid theme manifest: {}", err),
+            ThemeError::Image(path, err) => write!(f, "{}: {}", path.display(), err),
+            ThemeError::TooLarge(path) => write!(
+                f,
+                "{}: textures can be at most {} pixels wide and high",
+                path.display(),
+                MAX_TEXTURE_SIZE
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ThemeError {}
+
+/// A theme with its files read
+#[derive(Debug, Clone)]
+pub struct Theme {
+    /// The name of its directory, as in the settings
+    pub name: String,
+    pub manifest: Manifest,
+    pub blocks: HashMap<Block, Texture>,
+    pub background_image: Option<Texture>,
+    /// A TrueType font
+    pub font: Option<Vec<u8>>,
+}
+
+impl Theme {
+    /// The look of the game without a theme, loaded for the flat theme
+    /// when no directory has one
+    pub fn flat() -> Self {
+        Self {
+            name: DEFAULT_THEME.to_string(),
+            manifest: Manifest::default(),
+            blocks: HashMap::new(),
+            background_image: None,
+            font: None,
+        }
+    }
+
+    /// Directories searched for themes, the first one with a theme of a
+    /// name wins: the player's own themes, then those next to the
+    /// executable or in a directory above it, which is the source tree for
+    /// games started with cargo
+    pub fn dirs() -> Vec<PathBuf> {
+        let mut dirs: Vec<PathBuf> = config_dir().into_iter().collect();
+        if let Ok(exe) = env::current_exe() {
+            dirs.extend(exe.ancestors().skip(1).map(Path::to_path_buf));
+        }
+        dirs.into_iter().map(|dir| dir.join("themes")).collect()
+    }
+
+    /// Names of the themes that can be loaded, sorted
+    pub fn available() -> Vec<String> {
+        let mut names = vec![DEFAULT_THEME.to_string()];
+        for dir in Self::dirs() {
+            let entries = match fs::read_dir(dir) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+            for entry in entries.filter_map(Result::ok) {
+                if entry.path().join(MANIFEST).is_file() {
+                    names.extend(entry.file_name().to_str().map(String::from));
+                }
+            }
+        }
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Load the theme of the given name. The flat theme is built in and
+    /// needs no directory.
+    pub fn load(name: &str) -> Result<Self, ThemeError> {
+        let found = Self::dirs()
+            .into_iter()
+            .map(|dir| dir.join(name))
+            .find(|dir| dir.join(MANIFEST).is_file());
+        match found {
+            Some(dir) => Self::from_dir(name, &dir),
+            None if name == DEFAULT_THEME => Ok(Self::flat()),
+            None => Err(ThemeError::NotFound(name.to_string())),
+        }
+    }
+
+    /// Load the theme in a directory
+    pub fn from_dir(name: &str, dir: &Path) -> Result<Self, ThemeError> {
+        let path = dir.join(MANIFEST);
+        let text = fs::read_to_string(&path).map_err(|err| ThemeError::Io(path, err))?;
+        let manifest: Manifest = toml::from_str(&text).map_err(ThemeError::Parse)?;
+        let mut blocks = HashMap::new();
+        for &block in Block::ALL.iter() {
+            if let Some(file) = manifest.blocks.file(block) {
+                blocks.insert(block, read_texture(&dir.join(file))?);
+            }
+        }
+        let background_image = match manifest.background_image {
+            Some(ref file) => Some(read_texture(&dir.join(file))?),
+            None => None,
+        };
+        let font = match manifest.font {
+            Some(ref file) => {
+                let path = dir.join(file);
+                Some(fs::read(&path).map_err(|err| ThemeError::Io(path, err))?)
+            }
+            None => None,
+        };
+        Ok(Self {
+            name: name.to_string(),
+            manifest,
+            blocks,
+            background_image,
+            font,
+        })
+    }
+}
+
+fn read_texture(path: &Path) -> Result<Texture, ThemeError> {
+    let image = image::open(path)
+        .map_err(|err| ThemeError::Image(path.to_path_buf(), err))?
+        .to_rgba();
+    let (width, height) = image.dimensions();
+    if width > MAX_TEXTURE_SIZE || height > MAX_TEXTURE_SIZE {
+        return Err(ThemeError::TooLarge(path.to_path_buf()));
+    }
+    Ok(Texture {
+        width: width as u16,
+        height: height as u16,
+        rgba: image.into_raw(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tetromino::GARBAGE_COLOR;
+
+    fn shipped(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("themes")
+            .join(name)
+    }
+
+    #[test]
+    fn test_flat_is_default() {
+        let flat = Theme::from_dir("flat", &shipped("flat")).unwrap();
+        assert_eq!(flat.manifest, Manifest::default());
+        assert!(flat.blocks.is_empty());
+        assert!(shipped("beveled").join(MANIFEST).is_file());
+        // the flat theme is built in, wherever the game is run from
+        assert!(Theme::available().contains(&DEFAULT_THEME.to_string()));
+    }
+
+    #[test]
+    fn test_beveled() {
+        let beveled = Theme::from_dir("beveled", &shipped("beveled")).unwrap();
+        assert_eq!(beveled.manifest.grid.style, GridStyle::Dots);
+        for block in Block::ALL.iter() {
+            let texture = &beveled.blocks[block];
+            let pixels = texture.width as usize * texture.height as usize;
+            assert_eq!(texture.rgba.len(), pixels * 4);
+        }
+        assert!(beveled.background_image.is_some());
+    }
+
+    #[test]
+    fn test_manifest() {
+        let manifest: Manifest = toml::from_str(
+            r#"
+            well = [10, 20, 30, 255]
+            [grid]
+            style = "none"
+            [blocks]
+            t = "t.png"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(manifest.well, (10, 20, 30, 255));
+        assert_eq!(manifest.grid.style, GridStyle::None);
+        assert_eq!(manifest.grid.width, 1.0);
+        assert_eq!(manifest.blocks.file(Block::Piece(Shape::T)), Some("t.png"));
+        assert_eq!(manifest.blocks.file(Block::Piece(Shape::I)), None);
+        assert!(toml::from_str::<Manifest>("colour = [0, 0, 0, 255]").is_err());
+
+        assert_eq!(
+            Block::of(&Segment::new((0, 0), GARBAGE_COLOR)),
+            Block::Garbage
+        );
+        let t = Segment::new((0, 0), (&Shape::T).into());
+        assert_eq!(Block::of(&t), Block::Piece(Shape::T));
+        match Theme::load("no such theme") {
+            Err(ThemeError::NotFound(_)) => (),
+            other => panic!("expected no theme, got {:?}", other.map(|theme| theme.name)),
+        }
+    }
+}
@@ -0,0 +1,337 @@
+//! Tunes the weights of the heuristic bot with the cross-entropy method.
+//! Every generation draws candidate weights from a normal distribution,
+//! lets each of them play the same seeded games, and moves the
+//! distribution to the candidates that cleared the most lines. The games
+//! are real games whose pieces are put in place without simulating the
+//! frames their inputs take. The population is small enough to be saved
+//! after every generation, so a long training can be stopped and resumed.
+use std::path::Path;
+use std::sync::Arc;
+use std::{fmt, fs, io, thread};
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::action::Action;
+use crate::bot::{Choice, HeuristicBot, Weights};
+use crate::game_state::{GameMode, GameState, Ruleset};
+use crate::randomizer::Rng;
+use crate::settings::Settings;
+
+/// Spread of the first generation around the default weights
+const INITIAL_DEVIATION: f32 = 3.0;
+/// The spread never shrinks below this, so the search does not stop
+/// early on a lucky generation
+const MIN_DEVIATION: f32 = 0.05;
+
+/// How a generation is run
+#[derive(Debug, Clone)]
+pub struct Training {
+    /// Candidates drawn per generation
+    pub population: usize,
+    /// Best candidates the next generation is drawn around
+    pub elite: usize,
+    /// Games every candidate plays, the same seeds for all of them
+    pub games: u32,
+    /// Pieces a game is stopped after
+    pub pieces: u32,
+    pub ruleset: Ruleset,
+    pub threads: usize,
+}
+
+impl Default for Training {
+    fn default() -> Self {
+        Self {
+            population: 50,
+            elite: 10,
+            games: 4,
+            pieces: 2000,
+            ruleset: Ruleset::Guideline,
+            threads: 4,
+        }
+    }
+}
+
+/// Weights with how they did on average over their games
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Candidate {
+    pub weights: Weights,
+    pub lines: f32,
+    pub score: f32,
+}
+
+/// What a generation found
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub generation: u32,
+    /// The best candidate of the generation
+    pub best: Candidate,
+    /// Lines cleared on average by all candidates of the generation
+    pub mean_lines: f32,
+}
+
+/// The distribution candidates are drawn from, and the best weights found
+/// so far
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Population {
+    pub seed: u64,
+    /// Generations evolved so far
+    pub generation: u32,
+    pub mean: Weights,
+    pub deviation: Weights,
+    pub best: Option<Candidate>,
+}
+
+/// Why a checkpoint could not be loaded or saved
+#[derive(Debug)]
+pub enum TrainingError {
+    Io(io::Error),
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for TrainingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TrainingError::Io(err) => write!(f, "{}", err),
+            TrainingError::Json(err) => write!(f, "invalid checkpoint: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for TrainingError {}
+
+/// How a game went
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Outcome {
+    pub pieces: u32,
+    pub lines: u32,
+    pub score: u64,
+    /// The stack reached the top before the pieces ran out
+    pub topped_out: bool,
+}
+
+/// Let the heuristic bot play a marathon game until it tops out or has
+/// placed `max_pieces`
+pub fn play(weights: &Weights, ruleset: Ruleset, seed: u64, max_pieces: u32) -> Outcome {
+    let settings = Settings {
+        ruleset,
+        ..Settings::default()
+    };
+    let mut game = GameState::new(GameMode::Marathon, &settings, seed);
+    let bot = HeuristicBot::new(weights.clone());
+    let mut topped_out = false;
+    while game.stats().pieces < max_pieces && !topped_out {
+        let pieces = game.stats().pieces;
+        match bot.choose(&game) {
+            Some(Choice::Place(placement)) => {
+                game.place(&placement);
+                // a piece at the top cannot be locked, the game is lost
+                topped_out = game.is_over() || game.stats().pieces == pieces;
+            }
+            Some(Choice::Hold) => game.perform(Action::Hold),
+            None => topped_out = true,
+        }
+    }
+    Outcome {
+        pieces: game.stats().pieces,
+        lines: game.lines(),
+        score: game.points(),
+        topped_out,
+    }
+}
+
+/// A number from the standard normal distribution, by the Box-Muller
+/// transform
+fn gaussian(rng: &mut Rng) -> f32 {
+    let uniform = |rng: &mut Rng| ((rng.next_u64() >> 11) as f64 + 1.0) / (1u64 << 53) as f64;
+    let (u, v) = (uniform(rng), uniform(rng));
+    ((-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos()) as f32
+}
+
+/// Let every candidate play the games with the given seeds, spread over
+/// the threads
+pub fn evaluate(candidates: Vec<Weights>, seeds: &[u64], training: &Training) -> Vec<Candidate> {
+    let candidates = Arc::new(candidates);
+    let seeds = Arc::new(seeds.to_vec());
+    let threads = training.threads.max(1);
+    let workers: Vec<_> = (0..threads)
+        .map(|worker| {
+            let (candidates, seeds) = (Arc::clone(&candidates), Arc::clone(&seeds));
+            let (ruleset, pieces) = (training.ruleset, training.pieces);
+            thread::spawn(move || {
+                (worker..candidates.len())
+                    .step_by(threads)
+                    .map(|index| {
+                        let weights = &candidates[index];
+                        let (mut lines, mut score) = (0, 0);
+                        for &seed in seeds.iter() {
+                            let outcome = play(weights, ruleset, seed, pieces);
+                            lines += outcome.lines;
+                            score += outcome.score;
+                        }
+                        let games = seeds.len().max(1) as f32;
+                        let candidate = Candidate {
+                            weights: weights.clone(),
+                            lines: lines as f32 / games,
+                            score: score as f32 / games,
+                        };
+                        (index, candidate)
+                    })
+                    .collect::<Vec<_>>()
+            })
+        })
+        .collect();
+    let mut results = vec![None; candidates.len()];
+    for worker in workers {
+        for (index, candidate) in worker.join().expect("training thread panicked") {
+            results[index] = Some(candidate);
+        }
+    }
+    results.into_iter().flatten().collect()
+}
+
+impl Population {
+    /// Start around the default weights
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            generation: 0,
+            mean: Weights::default(),
+            deviation: Weights::from_array([INITIAL_DEVIATION; 7]),
+            best: None,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, TrainingError> {
+        let text = fs::read_to_string(path).map_err(TrainingError::Io)?;
+        serde_json::from_str(&text).map_err(TrainingError::Json)
+    }
+
+    /// Save the population, replacing the file only once it is written so
+    /// an interrupted save keeps the last checkpoint
+    pub fn save(&self, path: &Path) -> Result<(), TrainingError> {
+        let text = serde_json::to_string_pretty(self).map_err(TrainingError::Json)?;
+        let partial = path.with_extension("partial");
+        fs::write(&partial, text).map_err(TrainingError::Io)?;
+        fs::rename(&partial, path).map_err(TrainingError::Io)
+    }
+
+    /// Play a generation and move the distribution to its best candidates
+    pub fn evolve(&mut self, training: &Training) -> Report {
+        // every generation gets its own games and candidates, and resuming
+        // from a checkpoint gives the same ones
+        let mut rng = Rng::new(self.seed ^ u64::from(self.generation).wrapping_mul(0x9e37_79b9));
+        let seeds: Vec<u64> = (0..training.games).map(|_| rng.next_u64()).collect();
+        let (mean, deviation) = (self.mean.to_array(), self.deviation.to_array());
+        let candidates = (0..training.population.max(1))
+            .map(|_| {
+                let mut values = mean;
+                for (value, deviation) in values.iter_mut().zip(deviation.iter()) {
+                    *value += deviation * gaussian(&mut rng);
+                }
+                Weights::from_array(values)
+            })
+            .collect();
+
+        let mut results = evaluate(candidates, &seeds, training);
+        results.sort_by(|a, b| {
+            (b.lines, b.score)
+                .partial_cmp(&(a.lines, a.score))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        let mean_lines = results.iter().map(|c| c.lines).sum::<f32>() / results.len() as f32;
+        let elite = &results[..training.elite.max(1).min(results.len())];
+        let mut mean = [0.0; 7];
+        let mut deviation = [0.0; 7];
+        for candidate in elite {
+            for (sum, value) in mean.iter_mut().zip(candidate.weights.to_array().iter()) {
+                *sum += value / elite.len() as f32;
+            }
+        }
+        for candidate in elite {
+            let values = candidate.weights.to_array();
+            for ((sum, value), mean) in deviation.iter_mut().zip(values.iter()).zip(mean.iter()) {
+                *sum += (value - mean).powi(2) / elite.len() as f32;
+            }
+        }
+        for value in deviation.iter_mut() {
+            *value = value.sqrt().max(MIN_DEVIATION);
+        }
+        self.mean = Weights::from_array(mean);
+        self.deviation = Weights::from_array(deviation);
+        self.generation += 1;
+
+        let best = results[0].clone();
+        if self.best.as_ref().map(|b| best.lines > b.lines) != Some(false) {
+            self.best = Some(best.clone());
+        }
+        Report {
+            generation: self.generation,
+            best,
+            mean_lines,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gaussian() {
+        let mut rng = Rng::new(9);
+        let samples: Vec<f32> = (0..10_000).map(|_| gaussian(&mut rng)).collect();
+        let mean = samples.iter().sum::<f32>() / samples.len() as f32;
+        let variance =
+            samples.iter().map(|x| (x - mean).powi(2)).sum::<f32>() / samples.len() as f32;
+        assert!(mean.abs() < 0.05, "{}", mean);
+        assert!((variance - 1.0).abs() < 0.05, "{}", variance);
+    }
+
+    #[test]
+    fn test_play() {
+        let weights = Weights::default();
+        let outcome = play(&weights, Ruleset::Guideline, 5, 300);
+        assert_eq!(outcome, play(&weights, Ruleset::Guideline, 5, 300));
+        assert_eq!(outcome.pieces, 300);
+        assert!(!outcome.topped_out);
+        assert!(outcome.lines >= 100);
+
+        // weights that like a high stack lose quickly
+        let careless = Weights::from_array([1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+        let outcome = play(&careless, Ruleset::Guideline, 5, 300);
+        assert!(outcome.topped_out);
+        assert!(outcome.pieces < 300);
+    }
+
+    #[test]
+    fn test_evolve() {
+        let training = Training {
+            population: 6,
+            elite: 2,
+            games: 2,
+            pieces: 40,
+            threads: 3,
+            ..Training::default()
+        };
+        let mut population = Population::new(1);
+        let mut resumed = None;
+        for _ in 0..3 {
+            let report = population.evolve(&training);
+            assert_eq!(report.generation, population.generation);
+            assert!(report.best.lines >= report.mean_lines);
+            let best = population.best.as_ref().unwrap();
+            assert!(best.lines >= report.best.lines);
+            if population.generation == 1 {
+                let text = serde_json::to_string(&population).unwrap();
+                resumed = serde_json::from_str::<Population>(&text).ok();
+            }
+        }
+        assert_eq!(population.generation, 3);
+
+        // a resumed population evolves the same way
+        let mut resumed = resumed.unwrap();
+        resumed.evolve(&training);
+        resumed.evolve(&training);
+        assert_eq!(resumed, population);
+    }
+}
@@ -0,0 +1,56 @@
+//! Plays games against the stub bot in `src/bin/tbp-stub.rs` through the
+//! Tetris Bot Protocol
+use std::process::Command;
+
+use tetris_ggez::bot::Autoplay;
+use tetris_ggez::game_state::{GameMode, Ruleset, Setup};
+use tetris_ggez::settings::Settings;
+use tetris_ggez::simulation::Simulation;
+use tetris_ggez::tbp::TbpBot;
+use tetris_ggez::tetromino::Shape;
+
+fn stub() -> TbpBot {
+    let bot = TbpBot::spawn(
+        Command::new(env!("CARGO_BIN_EXE_tbp-stub")),
+        Ruleset::Guideline,
+    );
+    bot.unwrap()
+}
+
+/// Let the bot play until it has placed some pieces
+fn play(sim: &mut Simulation, pieces: u32) {
+    let mut autoplay = Autoplay::new(Box::new(stub()));
+    while sim.game().stats().pieces < pieces && !sim.game().is_over() {
+        for input in autoplay.inputs(sim.game()) {
+            sim.apply(input);
+        }
+        sim.step();
+    }
+}
+
+#[test]
+fn test_stub_plays() {
+    let bot = stub();
+    assert_eq!(bot.name, "tbp-stub");
+    drop(bot);
+
+    let mut sim = Simulation::new(GameMode::Marathon, &Settings::default(), 11);
+    play(&mut sim, 30);
+    assert_eq!(sim.game().stats().pieces, 30);
+    assert!(!sim.game().is_over());
+    assert!(sim.game().lines() > 0);
+}
+
+#[test]
+fn test_stub_starts_from_setup() {
+    let setup = Setup {
+        board: Vec::new(),
+        hold: Some(Shape::I),
+        queue: vec![Shape::O, Shape::O, Shape::O, Shape::O, Shape::O],
+    };
+    let mut sim = Simulation::with_setup(GameMode::Sandbox, &Settings::default(), 5, &setup);
+    play(&mut sim, 5);
+    // five O pieces side by side on the floor clear two lines
+    assert_eq!(sim.game().lines(), 2);
+    assert!(sim.game().base().is_empty());
+}